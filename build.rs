@@ -0,0 +1,26 @@
+//! Records the build info reported by the health endpoints
+
+use std::env;
+use std::process::Command;
+
+fn main() {
+    for name in &["TARGET", "PROFILE"] {
+        let value = env::var(name).unwrap_or_default();
+        println!("cargo:rustc-env=WEBCRAWL_{}={}", name, value);
+    }
+
+    // left out when building outside of a git checkout
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+
+    if let Some(commit) = commit {
+        println!("cargo:rustc-env=WEBCRAWL_COMMIT={}", commit.trim());
+    }
+
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+}
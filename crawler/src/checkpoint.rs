@@ -0,0 +1,36 @@
+//! Crawler state snapshots
+
+use crate::frontier::Queued;
+use crate::ty::{Seed, Urls};
+use std::path::PathBuf;
+
+/// A snapshot of the Crawler state, allowing to resume the crawl later
+///
+/// See [Crawler::checkpoints](../struct.Crawler.html#method.checkpoints) and
+/// [Crawler::resume](../struct.Crawler.html#method.resume).
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Checkpoint {
+    /// hashes of all queued and crawled urls, see [url_hash](../fn.url_hash.html)
+    ///
+    /// It's empty if the seen-set of the Crawler is not able to list them.
+    pub seen: Vec<u64>,
+    /// urls to be crawled, including the ones that were being fetched
+    ///
+    /// Only the urls kept in memory are listed, the spilled ones are in the `segments`.
+    pub queue: Vec<Queued>,
+    /// segment files of the urls spilled by the queue, see
+    /// [Frontier::checkpoint](../trait.Frontier.html#method.checkpoint)
+    pub segments: Vec<PathBuf>,
+    /// the starting urls of the crawl
+    ///
+    /// It's empty for the crawls that don't restrict their scope.
+    pub seeds: Vec<Seed>,
+    /// the resulting urls, without redirects
+    ///
+    /// It's empty if the Crawler doesn't keep them, see
+    /// [without_result](../struct.Crawler.html#method.without_result).
+    pub effective: Urls,
+    /// number of the resulting urls, also when they aren't kept in `effective`
+    pub found: usize,
+}
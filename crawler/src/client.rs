@@ -0,0 +1,140 @@
+//! HTTP client of the built-in fetchers, connecting only to the addresses permitted by
+//! the network policy
+
+use crate::error::*;
+use crate::policy::{self, NetworkPolicy};
+use futures::future::{self, Either};
+use futures::Future;
+use hyper::client::connect::dns::{Name, Resolve};
+use hyper::client::connect::{Connect, Connected, Destination};
+use hyper::client::HttpConnector;
+use hyper::{Body, Client};
+use hyper_tls::HttpsConnector;
+use native_tls::TlsConnector;
+use std::io;
+use std::net::IpAddr;
+use std::time::Duration;
+use tokio::timer::Timeout;
+
+pub(crate) type HttpClient = Client<HttpsConnector<Guarded>, Body>;
+
+/// Build a client of the requests checked against given policy
+pub(crate) fn client(
+    policy: Option<NetworkPolicy>,
+    connect_timeout: Option<Duration>,
+) -> Result<HttpClient> {
+    let mut http = HttpConnector::new_with_resolver(Resolver {
+        policy: policy.clone(),
+    });
+    http.enforce_http(false);
+
+    let guarded = Guarded {
+        http,
+        policy,
+        connect_timeout,
+    };
+
+    Ok(Client::builder().build(HttpsConnector::from((guarded, TlsConnector::new()?))))
+}
+
+/// Resolves the host names, failing if any of their addresses isn't permitted
+#[derive(Debug, Clone)]
+pub(crate) struct Resolver {
+    policy: Option<NetworkPolicy>,
+}
+
+impl Resolve for Resolver {
+    type Addrs = std::vec::IntoIter<IpAddr>;
+    type Future = Box<dyn Future<Item = Self::Addrs, Error = io::Error> + Send>;
+
+    fn resolve(&self, name: Name) -> Self::Future {
+        let resolved = match &self.policy {
+            Some(policy) => Either::A(policy.resolve(name.as_str())),
+            None => Either::B(policy::resolve(name.as_str())),
+        };
+
+        Box::new(resolved.map(Vec::into_iter).map_err(into_io))
+    }
+}
+
+/// Connects to the resolved addresses, and to the IP hosts only if they're permitted
+///
+/// The IP hosts aren't resolved, so they're checked here.
+
+#[derive(Debug, Clone)]
+pub(crate) struct Guarded {
+    http: HttpConnector<Resolver>,
+    policy: Option<NetworkPolicy>,
+    connect_timeout: Option<Duration>,
+}
+
+impl Connect for Guarded {
+    type Transport = <HttpConnector<Resolver> as Connect>::Transport;
+    type Error = io::Error;
+    type Future = Box<dyn Future<Item = (Self::Transport, Connected), Error = io::Error> + Send>;
+
+    fn connect(&self, dst: Destination) -> Self::Future {
+        let host = dst.host().trim_start_matches('[').trim_end_matches(']');
+
+        if let (Some(policy), Ok(ip)) = (&self.policy, host.parse::<IpAddr>()) {
+            if let Err(e) = policy.verify(host, ip) {
+                return Box::new(future::err(into_io(e)));
+            }
+        }
+
+        let connecting = self.http.connect(dst);
+
+        match self.connect_timeout {
+            Some(timeout) => Box::new(Timeout::new(connecting, timeout).map_err(|e| {
+                if e.is_elapsed() {
+                    into_io(Failed::error(FetchError::Timeout, "connection timed out"))
+                } else {
+                    e.into_inner()
+                        .unwrap_or_else(|| io::Error::other("connect timer failed"))
+                }
+            })),
+            None => Box::new(connecting),
+        }
+    }
+}
+
+/// Convert given error to an I/O one, keeping its kind
+fn into_io(error: Error) -> io::Error {
+    match error.downcast::<Failed>() {
+        Ok(failed) => io::Error::other(failed),
+        Err(error) => io::Error::other(error.compat()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Request;
+    use std::net::TcpListener;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn pinned() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        listener.set_nonblocking(true).unwrap();
+
+        let mut runtime = Runtime::new().unwrap();
+
+        for host in &["127.0.0.1", "localhost"] {
+            let client = client(Some(NetworkPolicy::new()), None).unwrap();
+            let request = Request::get(format!("http://{}:{}/", host, port))
+                .body(Body::empty())
+                .unwrap();
+
+            let error = runtime
+                .block_on(client.request(request).map_err(Error::from))
+                .unwrap_err();
+
+            assert_eq!(FetchError::of(&error), FetchError::Refused, "{}", host);
+        }
+
+        // refused before connecting
+        assert!(listener.accept().is_err());
+    }
+}
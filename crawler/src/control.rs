@@ -0,0 +1,125 @@
+//! Crawl flow control
+
+use futures::task::AtomicTask;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+const RUNNING: usize = 0;
+const PAUSED: usize = 1;
+const CANCELLED: usize = 2;
+
+/// State of the crawl, as seen by the Control handle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// the crawler is scheduling new requests
+    Running,
+    /// the crawler doesn't schedule new requests until resumed
+    Paused,
+    /// the crawler stops scheduling requests and finishes after the pending ones
+    Cancelled,
+}
+
+/// A handle allowing to pause, resume or cancel a running Crawler
+///
+/// The handle is cheap to clone, and all clones refer to the same Crawler.
+/// Requests that are already in flight are always allowed to finish, so a cancelled
+/// crawler still produces the results gathered so far.
+
+#[derive(Debug, Clone, Default)]
+pub struct Control {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    state: AtomicUsize,
+    task: AtomicTask,
+}
+
+impl Control {
+    /// Create new Control handle, in the running state
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return current state
+    pub fn state(&self) -> State {
+        match self.inner.state.load(Ordering::SeqCst) {
+            RUNNING => State::Running,
+            PAUSED => State::Paused,
+            _ => State::Cancelled,
+        }
+    }
+
+    /// Pause the crawler
+    ///
+    /// Returns false if the crawler wasn't running.
+
+    pub fn pause(&self) -> bool {
+        self.transition(RUNNING, PAUSED)
+    }
+
+    /// Resume paused crawler
+    ///
+    /// Returns false if the crawler wasn't paused.
+
+    pub fn resume(&self) -> bool {
+        self.transition(PAUSED, RUNNING)
+    }
+
+    /// Cancel the crawler
+    ///
+    /// Returns false if the crawler was already cancelled.
+
+    pub fn cancel(&self) -> bool {
+        let previous = self.inner.state.swap(CANCELLED, Ordering::SeqCst);
+
+        self.inner.task.notify();
+
+        previous != CANCELLED
+    }
+
+    /// Register current task to be notified on state changes
+    pub(crate) fn register(&self) {
+        self.inner.task.register();
+    }
+
+    fn transition(&self, from: usize, to: usize) -> bool {
+        let changed = self
+            .inner
+            .state
+            .compare_exchange(from, to, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok();
+
+        if changed {
+            self.inner.task.notify();
+        }
+
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transitions() {
+        let control = Control::new();
+
+        assert_eq!(control.state(), State::Running);
+        assert!(!control.resume());
+
+        assert!(control.pause());
+        assert!(!control.pause());
+        assert_eq!(control.state(), State::Paused);
+
+        assert!(control.resume());
+        assert_eq!(control.state(), State::Running);
+
+        assert!(control.cancel());
+        assert!(!control.cancel());
+        assert!(!control.pause());
+        assert_eq!(control.state(), State::Cancelled);
+    }
+}
@@ -1,4 +1,7 @@
 pub(crate) use failure::{err_msg, Error};
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
 use url;
 
 pub(crate) type Result<T> = std::result::Result<T, Error>;
@@ -8,3 +11,92 @@ pub enum UrlParseError {
     Parse(url::ParseError),
     BadOrigin,
 }
+
+/// Kind of a failed request of the built-in fetchers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FetchError {
+    /// the request took longer than the configured timeout
+    Timeout,
+    /// the host couldn't be resolved
+    Dns,
+    /// the connection to the server failed
+    Connect,
+    /// the address of the server is refused by the network policy
+    Refused,
+    /// the document redirected too many times
+    Redirects,
+    /// any other failure, e.g. an interrupted response
+    Other,
+}
+
+impl FetchError {
+    /// Return the kind of given error of a request
+    pub fn of(error: &Error) -> Self {
+        if let Some(failed) = error.downcast_ref::<Failed>() {
+            return failed.kind;
+        }
+
+        match error.downcast_ref::<hyper::Error>() {
+            Some(e) => {
+                // the failures of the connections carry their kind
+                let mut cause = e.cause2().map(|cause| cause as &(dyn StdError + 'static));
+
+                while let Some(error) = cause {
+                    if let Some(failed) = error.downcast_ref::<Failed>() {
+                        return failed.kind;
+                    }
+
+                    cause = match error.downcast_ref::<io::Error>() {
+                        Some(error) => error
+                            .get_ref()
+                            .map(|inner| inner as &(dyn StdError + 'static)),
+                        None => error.source(),
+                    };
+                }
+
+                if e.is_connect() {
+                    FetchError::Connect
+                } else {
+                    FetchError::Other
+                }
+            }
+            None => FetchError::Other,
+        }
+    }
+
+    /// Return the name of the kind, e.g. `timeout`
+    pub fn name(self) -> &'static str {
+        match self {
+            FetchError::Timeout => "timeout",
+            FetchError::Dns => "dns",
+            FetchError::Connect => "connect",
+            FetchError::Refused => "refused",
+            FetchError::Redirects => "redirects",
+            FetchError::Other => "other",
+        }
+    }
+}
+
+/// A failed request of a known kind
+#[derive(Debug)]
+pub(crate) struct Failed {
+    kind: FetchError,
+    message: String,
+}
+
+impl Failed {
+    pub(crate) fn error(kind: FetchError, message: impl Into<String>) -> Error {
+        Error::from(Failed {
+            kind,
+            message: message.into(),
+        })
+    }
+}
+
+impl fmt::Display for Failed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl StdError for Failed {}
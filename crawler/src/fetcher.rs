@@ -1,45 +1,353 @@
+use crate::client::{self, HttpClient};
 use crate::error::*;
-use crate::ty::{AsStr, FetchBuffer};
+use crate::policy::NetworkPolicy;
+use crate::ty::{AsStr, FetchBuffer, Fetched, Validators};
+use futures::future::{self, loop_fn, Loop};
 use futures::{Future, Stream};
+use hyper::header::{
+    HeaderMap, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, LOCATION,
+    USER_AGENT,
+};
+use hyper::{Body, Chunk, Request, Response, StatusCode};
 use log::*;
-use reqwest::r#async::{Chunk, Client};
 use std::str::from_utf8;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::timer::Timeout;
 use url::Url;
 
 impl AsStr for Chunk {
     fn as_str(&self) -> &str {
         from_utf8(self.as_ref()).unwrap()
     }
+
+    fn as_bytes(&self) -> &[u8] {
+        self.as_ref()
+    }
+}
+
+/// Maximum number of the redirects followed
+const MAX_REDIRECTS: usize = 10;
+
+/// The options used by the fetchers, if installed
+static INSTALLED: OnceLock<FetchOptions> = OnceLock::new();
+
+/// The client of the fetchers, shared by all requests so they can reuse the connections
+static CLIENT: OnceLock<HttpClient> = OnceLock::new();
+
+/// Options of the requests sent by [fetch](prelude/fn.fetch.html),
+/// [fetch_conditional](prelude/fn.fetch_conditional.html) and [send](prelude/fn.send.html)
+///
+/// Without installed options, the requests are sent without a `User-Agent` and timeouts.
+
+#[derive(Debug, Clone, Default)]
+pub struct FetchOptions {
+    user_agent: Option<HeaderValue>,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+}
+
+impl FetchOptions {
+    /// Create new options, without a `User-Agent` and timeouts
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send given `User-Agent` header
+    pub fn with_user_agent(mut self, user_agent: &str) -> Result<Self> {
+        self.user_agent = Some(
+            HeaderValue::from_str(user_agent)
+                .map_err(|_| err_msg(format!("invalid user agent {:?}", user_agent)))?,
+        );
+
+        Ok(self)
+    }
+
+    /// Give up on a request, including its redirects, after given time
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Give up on connecting to a server after given time
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Install as the options of the built-in fetchers
+    ///
+    /// Fails if options were already installed, or the fetchers already sent a request.
+
+    pub fn install(self) -> Result<()> {
+        if started() {
+            return Err(err_msg("fetchers already started"));
+        }
+
+        INSTALLED
+            .set(self)
+            .map_err(|_| err_msg("fetch options already installed"))
+    }
+
+    /// Return the client of the requests, checked against the installed network policy
+    ///
+    /// The client is built by the first request, so the options and the policy have to be
+    /// installed before.
+
+    fn client(&self) -> Result<HttpClient> {
+        if let Some(client) = CLIENT.get() {
+            return Ok(client.clone());
+        }
+
+        let client = client::client(NetworkPolicy::installed().cloned(), self.connect_timeout)?;
+
+        Ok(CLIENT.get_or_init(|| client).clone())
+    }
+
+    /// Send given request with the client, adding the `User-Agent`
+    fn send(
+        &self,
+        client: &HttpClient,
+        mut request: Request<Body>,
+    ) -> impl Future<Item = Response<Body>, Error = Error> + Send {
+        if let Some(user_agent) = &self.user_agent {
+            request
+                .headers_mut()
+                .entry(USER_AGENT)
+                .expect("valid header name")
+                .or_insert_with(|| user_agent.clone());
+        }
+
+        client.request(request).map_err(Error::from)
+    }
+
+    /// Fail given request if it takes longer than the timeout
+    fn limit<T: Send + 'static>(
+        &self,
+        request: impl Future<Item = T, Error = Error> + Send + 'static,
+    ) -> Box<dyn Future<Item = T, Error = Error> + Send> {
+        match self.timeout {
+            Some(timeout) => Box::new(Timeout::new(request, timeout).map_err(|e| {
+                if e.is_elapsed() {
+                    Failed::error(FetchError::Timeout, "request timed out")
+                } else {
+                    e.into_inner()
+                        .unwrap_or_else(|| err_msg("request timer failed"))
+                }
+            })),
+            None => Box::new(request),
+        }
+    }
+}
+
+/// Return true if the fetchers sent a request, so their client is built
+pub(crate) fn started() -> bool {
+    CLIENT.get().is_some()
+}
+
+/// Return the installed options, or the default ones
+fn options() -> FetchOptions {
+    INSTALLED.get().cloned().unwrap_or_default()
+}
+
+/// Send given request, without following the redirects
+///
+/// The request is sent with the installed [options](../struct.FetchOptions.html), and only to
+/// the addresses permitted by the installed [network policy](../struct.NetworkPolicy.html),
+/// so it's suitable for the urls supplied by the users, like the callbacks.
+
+pub fn send(
+    request: Request<Body>,
+) -> Box<dyn Future<Item = Response<Body>, Error = Error> + Send> {
+    let options = options();
+
+    match options.client() {
+        Ok(client) => {
+            let sent = options.send(&client, request);
+            options.limit(sent)
+        }
+        Err(e) => Box::new(future::err(e)),
+    }
 }
 
-/// Simple document fetcher, based on reqwest library
+/// Simple document fetcher, based on hyper library
 ///
 /// It will fetch given document, returning a pair (effective_url, FetchBuffer), which can then
 /// be parsed.
 
 pub fn fetch(url: Url) -> Box<dyn Future<Item = Option<(Url, FetchBuffer)>, Error = Error> + Send> {
-    let client = Client::new();
-
     debug!("Fetching {}", url);
 
     // TODO: differentiate between errors
 
-    Box::new(
-        client
-            .get(url.clone())
-            .send()
-            .and_then(|response| {
-                let real_url = response.url().clone();
+    let options = options();
+
+    options.limit(
+        get(options.clone(), url, HeaderMap::new())
+            .and_then(|(real_url, response)| {
                 response
                     .into_body()
                     .concat2()
-                    .and_then(move |chunks| Ok((real_url, chunks)))
-            })
-            .and_then(move |(real_url, chunk)| {
-                let r: FetchBuffer = Box::new(chunk);
+                    .map_err(Error::from)
+                    .map(move |chunk| {
+                        let r: FetchBuffer = Box::new(chunk);
 
-                Ok(Some((real_url, r)))
+                        Some((real_url, r))
+                    })
             })
             .or_else(|_| Ok(None)),
     )
 }
+
+/// Conditional document fetcher, based on hyper library
+///
+/// It will send the validators of the previously fetched version of given document,
+/// returning either `Fetched::Document` with the new validators, or `Fetched::NotModified`.
+
+pub fn fetch_conditional(
+    url: Url,
+    validators: Validators,
+) -> Box<dyn Future<Item = Option<Fetched>, Error = Error> + Send> {
+    Box::new(
+        try_fetch_conditional(url, validators)
+            .map(Some)
+            .or_else(|_| Ok(None)),
+    )
+}
+
+/// Conditional document fetcher, failing with the error of the request
+///
+/// Works like [fetch_conditional](fn.fetch_conditional.html), except the failed requests
+/// aren't turned into `None`, so their [FetchError](enum.FetchError.html) can be told.
+
+pub fn try_fetch_conditional(
+    url: Url,
+    validators: Validators,
+) -> Box<dyn Future<Item = Fetched, Error = Error> + Send> {
+    debug!("Fetching {} conditionally", url);
+
+    let mut headers = HeaderMap::new();
+    let validator = |value: String| HeaderValue::from_str(&value).ok();
+
+    if let Some(etag) = validators.etag.and_then(validator) {
+        headers.insert(IF_NONE_MATCH, etag);
+    }
+
+    if let Some(last_modified) = validators.last_modified.and_then(validator) {
+        headers.insert(IF_MODIFIED_SINCE, last_modified);
+    }
+
+    let options = options();
+
+    options.limit(
+        get(options.clone(), url, headers).and_then(|(real_url, response)| {
+            let status = response.status();
+            let validators = validators_of(response.headers());
+            let headers = response
+                .headers()
+                .iter()
+                .map(|(name, value)| {
+                    (
+                        name.as_str().to_owned(),
+                        String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                    )
+                })
+                .collect();
+
+            response
+                .into_body()
+                .concat2()
+                .map_err(Error::from)
+                .and_then(move |chunk| {
+                    if status == StatusCode::NOT_MODIFIED {
+                        return Ok(Fetched::NotModified);
+                    }
+
+                    let r: FetchBuffer = Box::new(chunk);
+
+                    Ok(Fetched::Document {
+                        url: real_url,
+                        buffer: r,
+                        status: status.as_u16(),
+                        validators,
+                        headers,
+                    })
+                })
+        }),
+    )
+}
+
+/// Send a GET request of given url with given headers, following the redirects
+///
+/// Returns the url the response came from, after the redirects.
+
+fn get(
+    options: FetchOptions,
+    url: Url,
+    headers: HeaderMap,
+) -> impl Future<Item = (Url, Response<Body>), Error = Error> + Send {
+    future::result(options.client()).and_then(move |client| {
+        loop_fn((url, 0), move |(url, redirects)| {
+            let mut request = Request::get(url.as_str());
+
+            for (name, value) in &headers {
+                request.header(name, value.clone());
+            }
+
+            future::result(request.body(Body::empty()).map_err(Error::from))
+                .and_then({
+                    let options = options.clone();
+                    let client = client.clone();
+
+                    move |request| options.send(&client, request)
+                })
+                .map_err(|e| {
+                    warn!("{}", e);
+                    e
+                })
+                .and_then(move |response| match redirect_of(&url, &response) {
+                    Some(_) if redirects >= MAX_REDIRECTS => Err(Failed::error(
+                        FetchError::Redirects,
+                        format!("too many redirects of {}", url),
+                    )),
+                    Some(location) => {
+                        debug!("Following redirect to {}", location);
+
+                        Ok(Loop::Continue((location, redirects + 1)))
+                    }
+                    None => Ok(Loop::Break((url, response))),
+                })
+        })
+    })
+}
+
+/// Return the url given response of given url redirects to, if any
+fn redirect_of(url: &Url, response: &Response<Body>) -> Option<Url> {
+    match response.status() {
+        StatusCode::MOVED_PERMANENTLY
+        | StatusCode::FOUND
+        | StatusCode::SEE_OTHER
+        | StatusCode::TEMPORARY_REDIRECT
+        | StatusCode::PERMANENT_REDIRECT => response
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .and_then(|location| url.join(location).ok()),
+        _ => None,
+    }
+}
+
+/// Extract the cache validators from response headers
+fn validators_of(headers: &HeaderMap) -> Validators {
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned)
+    };
+
+    Validators {
+        etag: header(ETAG),
+        last_modified: header(LAST_MODIFIED),
+    }
+}
@@ -0,0 +1,919 @@
+//! Queues of urls to be crawled
+
+use crate::error::*;
+use crate::ty::Scorer;
+use hashbrown::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// A url waiting to be crawled
+#[derive(Debug, Clone, PartialEq)]
+pub struct Queued {
+    /// the url to be crawled
+    pub url: Url,
+    /// number of links followed from the seed
+    pub depth: usize,
+    /// index of the seed that led to the url
+    pub seed: usize,
+}
+
+impl Queued {
+    /// Create new queued url
+    pub fn new(url: Url, depth: usize, seed: usize) -> Self {
+        Self { url, depth, seed }
+    }
+}
+
+/// A queue of urls to be crawled, allowing for pluggable implementations
+///
+/// Every url is accompanied by its depth, i.e. the number of links followed from the starting url,
+/// and the index of the seed that led to it.
+/// The Crawler takes care of deduplication, so every url is pushed at most once.
+
+pub trait Frontier: Send {
+    /// Add url to the queue
+    fn push(&mut self, queued: Queued) -> Result<()>;
+
+    /// Remove the next url to be crawled from the queue
+    fn pop(&mut self) -> Result<Option<Queued>>;
+
+    /// Return the number of queued urls
+    fn len(&self) -> usize;
+
+    /// Return true if there are no queued urls
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return all queued urls, without removing them
+    ///
+    /// The urls spilled to disk are read back, see [checkpoint](#method.checkpoint)
+    /// for a way that doesn't.
+    fn snapshot(&self) -> Result<Vec<Queued>>;
+
+    /// Return the queued urls kept in memory, and the segment files holding the spilled ones
+    ///
+    /// Nothing is removed from the queue. The files remain valid until this queue reads them
+    /// back and takes two more checkpoints, so that a stored checkpoint can be resumed
+    /// after a crash.
+    fn checkpoint(&mut self) -> Result<(Vec<Queued>, Vec<PathBuf>)> {
+        Ok((self.snapshot()?, Vec::new()))
+    }
+
+    /// Add the urls of given segment files, returned by the checkpoint of another queue
+    ///
+    /// The default implementation pushes the urls one by one, leaving the files in place.
+    fn restore(&mut self, segments: &[PathBuf]) -> Result<()> {
+        for path in segments {
+            for queued in read_segment(path)? {
+                self.push(queued)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Frontier for Box<dyn Frontier> {
+    fn push(&mut self, queued: Queued) -> Result<()> {
+        (**self).push(queued)
+    }
+
+    fn pop(&mut self) -> Result<Option<Queued>> {
+        (**self).pop()
+    }
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn snapshot(&self) -> Result<Vec<Queued>> {
+        (**self).snapshot()
+    }
+
+    fn checkpoint(&mut self) -> Result<(Vec<Queued>, Vec<PathBuf>)> {
+        (**self).checkpoint()
+    }
+
+    fn restore(&mut self, segments: &[PathBuf]) -> Result<()> {
+        (**self).restore(segments)
+    }
+}
+
+/// Read the urls of given segment file
+pub(crate) fn read_segment(path: &Path) -> Result<Vec<Queued>> {
+    BufReader::new(File::open(path)?)
+        .lines()
+        .map(|line| {
+            let line = line?;
+            let mut parts = line.splitn(3, ' ');
+
+            let depth = parts.next().unwrap_or_default().parse()?;
+            let seed = parts.next().unwrap_or_default().parse()?;
+            let url = Url::parse(parts.next().unwrap_or_default())?;
+
+            Ok(Queued::new(url, depth, seed))
+        })
+        .collect()
+}
+
+/// Segment files of the urls spilled by a queue
+#[derive(Debug)]
+struct Spill {
+    dir: PathBuf,
+    /// segment file name counter
+    next: usize,
+    /// true once a checkpoint may refer to the segments
+    checkpointed: bool,
+    /// segments read back since the last checkpoint
+    read: Vec<PathBuf>,
+    /// segments read back before the last checkpoint, removed at the next one
+    released: Vec<PathBuf>,
+}
+
+impl Spill {
+    fn new(dir: PathBuf) -> Result<Self> {
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            dir,
+            next: 0,
+            checkpointed: false,
+            read: Vec::new(),
+            released: Vec::new(),
+        })
+    }
+
+    /// Write given urls to a new segment file
+    fn write<'a>(&mut self, urls: impl IntoIterator<Item = &'a Queued>) -> Result<PathBuf> {
+        // the restored segments of a previous queue may still be there
+        let path = loop {
+            let path = self.dir.join(format!("segment-{}", self.next));
+            self.next += 1;
+
+            if !path.exists() {
+                break path;
+            }
+        };
+
+        let mut writer = BufWriter::new(File::create(&path)?);
+
+        for queued in urls {
+            writeln!(writer, "{} {} {}", queued.depth, queued.seed, queued.url)?;
+        }
+
+        writer.flush()?;
+
+        Ok(path)
+    }
+
+    /// Read back given segment file, removing it once no checkpoint refers to it
+    fn read(&mut self, path: &Path) -> Result<Vec<Queued>> {
+        let urls = read_segment(path)?;
+
+        if self.checkpointed {
+            self.read.push(path.to_owned());
+        } else {
+            fs::remove_file(path)?;
+        }
+
+        Ok(urls)
+    }
+
+    /// Note a checkpoint, removing the segments the previous one may still refer to
+    fn checkpoint(&mut self) {
+        self.checkpointed = true;
+
+        let read = mem::take(&mut self.read);
+
+        for path in mem::replace(&mut self.released, read) {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// Remove given segments that weren't read back
+    fn discard<'a>(&self, unread: impl IntoIterator<Item = &'a PathBuf>) {
+        for path in unread {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        for path in self.read.iter().chain(self.released.iter()) {
+            let _ = fs::remove_file(path);
+        }
+
+        // fails if some segments were kept
+        let _ = fs::remove_dir(&self.dir);
+    }
+}
+
+/// A FIFO queue kept in memory
+#[derive(Debug, Default)]
+pub struct MemoryFrontier {
+    queue: VecDeque<Queued>,
+}
+
+impl MemoryFrontier {
+    /// Create new, empty queue
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Frontier for MemoryFrontier {
+    fn push(&mut self, queued: Queued) -> Result<()> {
+        self.queue.push_back(queued);
+
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<Option<Queued>> {
+        Ok(self.queue.pop_front())
+    }
+
+    fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    fn snapshot(&self) -> Result<Vec<Queued>> {
+        Ok(self.queue.iter().cloned().collect())
+    }
+}
+
+/// A FIFO queue keeping at most `2 * capacity` urls in memory
+///
+/// The urls that don't fit are spilled to segment files in given directory,
+/// each holding at most `capacity` urls. The files are removed once they're read back,
+/// see [checkpoint](trait.Frontier.html#method.checkpoint), and the remaining ones
+/// when the queue is dropped, unless they're [kept](#method.with_kept_segments).
+
+#[derive(Debug)]
+pub struct DiskFrontier {
+    spill: Spill,
+    capacity: usize,
+    /// keep the segments that weren't read back when dropped
+    keep: bool,
+    /// urls to be popped first
+    head: VecDeque<Queued>,
+    /// spilled urls, oldest first
+    segments: VecDeque<(PathBuf, usize)>,
+    /// urls pushed after the spilled ones
+    tail: Vec<Queued>,
+}
+
+impl DiskFrontier {
+    /// Create new, empty queue
+    ///
+    /// The directory is created if it doesn't exist, and it should not be shared
+    /// with other queues, except for the ones restored from their checkpoints.
+
+    pub fn new(dir: impl Into<PathBuf>, capacity: usize) -> Result<Self> {
+        Ok(Self {
+            spill: Spill::new(dir.into())?,
+            capacity: capacity.max(1),
+            keep: false,
+            head: VecDeque::new(),
+            segments: VecDeque::new(),
+            tail: Vec::new(),
+        })
+    }
+
+    /// Keep the spilled urls when the queue is dropped, so that its stored checkpoints
+    /// can be resumed later
+    ///
+    /// The directory has to be removed by the owner once it's not needed anymore.
+
+    pub fn with_kept_segments(mut self) -> Self {
+        self.keep = true;
+        self
+    }
+
+    fn spill(&mut self) -> Result<()> {
+        let path = self.spill.write(&self.tail)?;
+
+        self.segments.push_back((path, self.tail.len()));
+        self.tail.clear();
+
+        Ok(())
+    }
+}
+
+impl Frontier for DiskFrontier {
+    fn push(&mut self, queued: Queued) -> Result<()> {
+        if self.segments.is_empty() && self.tail.is_empty() && self.head.len() < self.capacity {
+            self.head.push_back(queued);
+        } else {
+            self.tail.push(queued);
+
+            if self.tail.len() >= self.capacity {
+                self.spill()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<Option<Queued>> {
+        if self.head.is_empty() {
+            if let Some((path, _)) = self.segments.pop_front() {
+                self.head.extend(self.spill.read(&path)?);
+            } else {
+                self.head.extend(self.tail.drain(..));
+            }
+        }
+
+        Ok(self.head.pop_front())
+    }
+
+    fn len(&self) -> usize {
+        let spilled: usize = self.segments.iter().map(|(_, len)| len).sum();
+
+        self.head.len() + spilled + self.tail.len()
+    }
+
+    fn snapshot(&self) -> Result<Vec<Queued>> {
+        let mut urls = self.head.iter().cloned().collect::<Vec<_>>();
+
+        for (path, _) in &self.segments {
+            urls.extend(read_segment(path)?);
+        }
+
+        urls.extend(self.tail.iter().cloned());
+
+        Ok(urls)
+    }
+
+    fn checkpoint(&mut self) -> Result<(Vec<Queued>, Vec<PathBuf>)> {
+        // the tail comes after the spilled urls
+        if !self.segments.is_empty() && !self.tail.is_empty() {
+            self.spill()?;
+        }
+
+        self.spill.checkpoint();
+
+        let urls = self.head.iter().chain(self.tail.iter()).cloned().collect();
+        let segments = self.segments.iter().map(|(path, _)| path.clone()).collect();
+
+        Ok((urls, segments))
+    }
+
+    fn restore(&mut self, segments: &[PathBuf]) -> Result<()> {
+        if !self.tail.is_empty() {
+            self.spill()?;
+        }
+
+        // the stored checkpoint refers to the restored segments
+        self.spill.checkpointed = true;
+
+        for path in segments {
+            let len = read_segment(path)?.len();
+
+            self.segments.push_back((path.clone(), len));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for DiskFrontier {
+    fn drop(&mut self) {
+        if !self.keep {
+            self.spill
+                .discard(self.segments.iter().map(|(path, _)| path));
+        }
+    }
+}
+
+/// Crawling order of a [PriorityFrontier](struct.PriorityFrontier.html)
+#[derive(Clone)]
+pub enum Priority {
+    /// the shallowest urls first, i.e. breadth-first
+    BreadthFirst,
+    /// the deepest urls first, i.e. depth-first
+    DepthFirst,
+    /// the shortest urls first
+    ShortestUrl,
+    /// the urls with the highest scores first, e.g. the sitemap priorities
+    ///
+    /// The urls without a score get [DEFAULT_SCORE](constant.DEFAULT_SCORE.html).
+    Scores(HashMap<Url, f64>),
+    /// the urls with the highest scores returned by given function first
+    Custom(Scorer),
+}
+
+/// A score of the urls missing from [Priority::Scores](enum.Priority.html#variant.Scores),
+/// the same as the default sitemap priority
+pub const DEFAULT_SCORE: f64 = 0.5;
+
+impl Priority {
+    /// Return the score of given url, higher scores are crawled first
+    pub fn score(&self, url: &Url, depth: usize) -> f64 {
+        match self {
+            Priority::BreadthFirst => -(depth as f64),
+            Priority::DepthFirst => depth as f64,
+            Priority::ShortestUrl => -(url.as_str().len() as f64),
+            Priority::Scores(scores) => scores.get(url).cloned().unwrap_or(DEFAULT_SCORE),
+            Priority::Custom(scorer) => (scorer)(url, depth),
+        }
+    }
+}
+
+// this is required, because Debug cannot be auto-derived
+// for function pointers with reference arguments
+impl std::fmt::Debug for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Priority::BreadthFirst => write!(f, "BreadthFirst"),
+            Priority::DepthFirst => write!(f, "DepthFirst"),
+            Priority::ShortestUrl => write!(f, "ShortestUrl"),
+            Priority::Scores(scores) => write!(f, "Scores({} urls)", scores.len()),
+            Priority::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+/// A queue ordered by given [Priority](enum.Priority.html)
+///
+/// The urls with equal scores are crawled in the order they were pushed, as long as
+/// they fit in memory. The queue is kept in memory, unless it's given a
+/// [capacity](#method.with_spill).
+
+#[derive(Debug)]
+pub struct PriorityFrontier {
+    priority: Priority,
+    heap: BinaryHeap<Entry>,
+    /// push counter, used for breaking ties
+    pushed: u64,
+    /// segment files of the lowest scored urls with the capacity, if they're spilled
+    spill: Option<(Spill, usize)>,
+    /// keep the segments that weren't read back when dropped
+    keep: bool,
+    /// spilled urls, with their numbers and the highest scores of the segments
+    segments: Vec<(PathBuf, usize, f64)>,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    score: f64,
+    order: u64,
+    queued: Queued,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // the heap pops the greatest entry, so the earlier pushed ones are greater
+        self.score
+            .total_cmp(&other.score)
+            .then_with(|| other.order.cmp(&self.order))
+    }
+}
+
+impl PriorityFrontier {
+    /// Create new, empty queue
+    pub fn new(priority: Priority) -> Self {
+        Self {
+            priority,
+            heap: BinaryHeap::new(),
+            pushed: 0,
+            spill: None,
+            keep: false,
+            segments: Vec::new(),
+        }
+    }
+
+    /// Keep at most `2 * capacity` urls in memory
+    ///
+    /// The lowest scored urls that don't fit are spilled to segment files in given directory,
+    /// each holding `capacity` urls, like with a [DiskFrontier](struct.DiskFrontier.html).
+
+    pub fn with_spill(mut self, dir: impl Into<PathBuf>, capacity: usize) -> Result<Self> {
+        self.spill = Some((Spill::new(dir.into())?, capacity.max(1)));
+
+        Ok(self)
+    }
+
+    /// Keep the spilled urls when the queue is dropped, so that its stored checkpoints
+    /// can be resumed later
+    ///
+    /// The directory has to be removed by the owner once it's not needed anymore.
+
+    pub fn with_kept_segments(mut self) -> Self {
+        self.keep = true;
+        self
+    }
+
+    fn entry(&mut self, queued: Queued) -> Entry {
+        let score = self.priority.score(&queued.url, queued.depth);
+        let order = self.pushed;
+
+        self.pushed += 1;
+
+        Entry {
+            score,
+            order,
+            queued,
+        }
+    }
+
+    /// Spill the lowest scored urls, if there are too many of them in memory
+    fn shrink(&mut self) -> Result<()> {
+        let (spill, capacity) = match &mut self.spill {
+            Some((spill, capacity)) if self.heap.len() > 2 * *capacity => (spill, *capacity),
+            _ => return Ok(()),
+        };
+
+        // ascending, so the lowest scored ones come first
+        let mut entries = mem::take(&mut self.heap).into_sorted_vec();
+        let kept = entries.split_off(capacity);
+
+        let highest = entries.last().map(|entry| entry.score).unwrap_or_default();
+        let path = spill.write(entries.iter().rev().map(|entry| &entry.queued))?;
+
+        self.segments.push((path, entries.len(), highest));
+        self.heap = kept.into();
+
+        Ok(())
+    }
+
+    /// Read back the segment with the highest score, if it's higher than the ones in memory
+    fn load(&mut self) -> Result<()> {
+        let best = self
+            .segments
+            .iter()
+            .enumerate()
+            .max_by(|(_, (_, _, a)), (_, (_, _, b))| a.total_cmp(b))
+            .map(|(index, (_, _, score))| (index, *score));
+
+        let index = match best {
+            Some((index, score)) if self.heap.peek().is_none_or(|top| top.score < score) => index,
+            _ => return Ok(()),
+        };
+
+        let (path, _, _) = self.segments.swap_remove(index);
+
+        let urls = match &mut self.spill {
+            Some((spill, _)) => spill.read(&path)?,
+            None => read_segment(&path)?,
+        };
+
+        for queued in urls {
+            let entry = self.entry(queued);
+            self.heap.push(entry);
+        }
+
+        // the spilled urls score lower than the loaded ones
+        self.shrink()
+    }
+}
+
+impl Frontier for PriorityFrontier {
+    fn push(&mut self, queued: Queued) -> Result<()> {
+        let entry = self.entry(queued);
+
+        self.heap.push(entry);
+
+        self.shrink()
+    }
+
+    fn pop(&mut self) -> Result<Option<Queued>> {
+        self.load()?;
+
+        Ok(self.heap.pop().map(|entry| entry.queued))
+    }
+
+    fn len(&self) -> usize {
+        let spilled: usize = self.segments.iter().map(|(_, len, _)| len).sum();
+
+        self.heap.len() + spilled
+    }
+
+    fn snapshot(&self) -> Result<Vec<Queued>> {
+        let mut entries = self.heap.clone().into_vec();
+        let mut order = self.pushed;
+
+        for (path, _, _) in &self.segments {
+            for queued in read_segment(path)? {
+                entries.push(Entry {
+                    score: self.priority.score(&queued.url, queued.depth),
+                    order,
+                    queued,
+                });
+                order += 1;
+            }
+        }
+
+        entries.sort_by(|a, b| b.cmp(a));
+
+        Ok(entries.into_iter().map(|entry| entry.queued).collect())
+    }
+
+    fn checkpoint(&mut self) -> Result<(Vec<Queued>, Vec<PathBuf>)> {
+        if let Some((spill, _)) = &mut self.spill {
+            spill.checkpoint();
+        }
+
+        let mut entries = self.heap.clone().into_sorted_vec();
+        entries.reverse();
+
+        let urls = entries.into_iter().map(|entry| entry.queued).collect();
+        let segments = self
+            .segments
+            .iter()
+            .map(|(path, _, _)| path.clone())
+            .collect();
+
+        Ok((urls, segments))
+    }
+
+    fn restore(&mut self, segments: &[PathBuf]) -> Result<()> {
+        let spill = match &mut self.spill {
+            Some((spill, _)) => spill,
+            None => {
+                for path in segments {
+                    for queued in read_segment(path)? {
+                        self.push(queued)?;
+                    }
+                }
+
+                return Ok(());
+            }
+        };
+
+        // the stored checkpoint refers to the restored segments
+        spill.checkpointed = true;
+
+        for path in segments {
+            let urls = read_segment(path)?;
+            let highest = urls
+                .iter()
+                .map(|queued| self.priority.score(&queued.url, queued.depth))
+                .max_by(f64::total_cmp)
+                .unwrap_or_default();
+
+            self.segments.push((path.clone(), urls.len(), highest));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for PriorityFrontier {
+    fn drop(&mut self) {
+        if let (Some((spill, _)), false) = (&self.spill, self.keep) {
+            spill.discard(self.segments.iter().map(|(path, _, _)| path));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(range: std::ops::Range<usize>) -> Vec<Queued> {
+        range
+            .map(|i| {
+                Queued::new(
+                    Url::parse(&format!("http://example.com/{}.html", i)).unwrap(),
+                    i,
+                    0,
+                )
+            })
+            .collect()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("frontier-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn disk_fifo() {
+        let dir = temp_dir("fifo");
+        let mut frontier = DiskFrontier::new(&dir, 3).unwrap();
+
+        for queued in urls(0..10) {
+            frontier.push(queued).unwrap();
+        }
+
+        assert_eq!(frontier.len(), 10);
+        // 3 in the head, 2 segments, 1 in the tail
+        assert_eq!(frontier.segments.len(), 2);
+        assert_eq!(frontier.snapshot().unwrap(), urls(0..10));
+
+        let mut popped = Vec::new();
+
+        for _ in 0..5 {
+            popped.push(frontier.pop().unwrap().unwrap());
+        }
+
+        for queued in urls(10..12) {
+            frontier.push(queued).unwrap();
+        }
+
+        while let Some(queued) = frontier.pop().unwrap() {
+            popped.push(queued);
+        }
+
+        assert_eq!(popped, urls(0..12));
+        assert!(frontier.is_empty());
+
+        drop(frontier);
+
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn disk_checkpoint() {
+        let dir = temp_dir("checkpoint");
+        let mut frontier = DiskFrontier::new(&dir, 3).unwrap().with_kept_segments();
+
+        for queued in urls(0..10) {
+            frontier.push(queued).unwrap();
+        }
+
+        // the spilled urls are referred to, not read back
+        let (queued, segments) = frontier.checkpoint().unwrap();
+
+        assert_eq!(queued, urls(0..3));
+        assert_eq!(segments.len(), 3);
+
+        // the segments read back stay until the second checkpoint after
+        for _ in 0..4 {
+            frontier.pop().unwrap();
+        }
+
+        frontier.checkpoint().unwrap();
+        assert!(segments[0].exists());
+
+        frontier.checkpoint().unwrap();
+        assert!(!segments[0].exists());
+
+        drop(frontier);
+
+        assert!(segments[1..].iter().all(|path| path.exists()));
+
+        let mut restored = DiskFrontier::new(&dir, 3).unwrap();
+
+        for queued in queued {
+            restored.push(queued).unwrap();
+        }
+
+        restored.restore(&segments[1..]).unwrap();
+
+        assert_eq!(restored.len(), 7);
+
+        let mut popped = Vec::new();
+
+        while let Some(queued) = restored.pop().unwrap() {
+            popped.push(queued);
+        }
+
+        assert_eq!(popped, [urls(0..3), urls(6..10)].concat());
+
+        drop(restored);
+
+        assert!(!dir.exists());
+    }
+
+    fn drain(frontier: &mut dyn Frontier) -> Vec<String> {
+        let mut popped = Vec::new();
+
+        while let Some(queued) = frontier.pop().unwrap() {
+            popped.push(queued.url.path().to_owned());
+        }
+
+        popped
+    }
+
+    #[test]
+    fn priorities() {
+        let pushed = [
+            ("/a/b/c.html", 2),
+            ("/a.html", 0),
+            ("/a/bb.html", 1),
+            ("/d.html", 0),
+        ];
+
+        let ordered = |priority| {
+            let mut frontier = PriorityFrontier::new(priority);
+
+            for (path, depth) in &pushed {
+                frontier
+                    .push(Queued::new(
+                        url!(&format!("http://example.com{}", path)),
+                        *depth,
+                        0,
+                    ))
+                    .unwrap();
+            }
+
+            let snapshot = frontier.snapshot().unwrap();
+            let popped = drain(&mut frontier);
+
+            assert_eq!(
+                snapshot
+                    .iter()
+                    .map(|queued| queued.url.path())
+                    .collect::<Vec<_>>(),
+                popped
+            );
+
+            popped
+        };
+
+        assert_eq!(
+            ordered(Priority::BreadthFirst),
+            ["/a.html", "/d.html", "/a/bb.html", "/a/b/c.html"]
+        );
+        assert_eq!(
+            ordered(Priority::DepthFirst),
+            ["/a/b/c.html", "/a/bb.html", "/a.html", "/d.html"]
+        );
+        assert_eq!(
+            ordered(Priority::ShortestUrl),
+            ["/a.html", "/d.html", "/a/bb.html", "/a/b/c.html"]
+        );
+
+        let mut scores = HashMap::new();
+        scores.insert(url!("http://example.com/d.html"), 1.0);
+        scores.insert(url!("http://example.com/a.html"), 0.1);
+
+        assert_eq!(
+            ordered(Priority::Scores(scores)),
+            ["/d.html", "/a/b/c.html", "/a/bb.html", "/a.html"]
+        );
+
+        fn longest(url: &Url, _depth: usize) -> f64 {
+            url.as_str().len() as f64
+        }
+
+        assert_eq!(
+            ordered(Priority::Custom(longest)),
+            ["/a/b/c.html", "/a/bb.html", "/a.html", "/d.html"]
+        );
+    }
+
+    #[test]
+    fn priority_spill() {
+        let dir = temp_dir("priority");
+        let mut frontier = PriorityFrontier::new(Priority::DepthFirst)
+            .with_spill(&dir, 2)
+            .unwrap();
+
+        // shuffled, so the spilled urls aren't the last pushed ones
+        for i in 0..20 {
+            frontier.push(urls(0..20).remove(i * 7 % 20)).unwrap();
+        }
+
+        assert_eq!(frontier.len(), 20);
+
+        let mut popped = Vec::new();
+
+        for _ in 0..5 {
+            popped.push(frontier.pop().unwrap().unwrap().depth);
+        }
+
+        // deeper than the rest, so they come before the spilled ones
+        for depth in 16..18 {
+            frontier.push(urls(depth..depth + 1).remove(0)).unwrap();
+        }
+
+        while let Some(queued) = frontier.pop().unwrap() {
+            popped.push(queued.depth);
+            assert!(frontier.heap.len() <= 4);
+        }
+
+        let expected = [19, 18, 17, 16, 15, 17, 16]
+            .iter()
+            .cloned()
+            .chain((0..15).rev())
+            .collect::<Vec<_>>();
+
+        assert_eq!(popped, expected);
+
+        drop(frontier);
+
+        assert!(!dir.exists());
+    }
+}
@@ -9,19 +9,47 @@
 
 use error::*;
 
+pub use checkpoint::Checkpoint;
+pub use control::{Control, State};
+pub use error::FetchError;
+pub use fetcher::FetchOptions;
+pub use frontier::{
+    DiskFrontier, Frontier, MemoryFrontier, Priority, PriorityFrontier, Queued, DEFAULT_SCORE,
+};
+pub use page::{content_hash, Page, Response};
+pub use policy::{Cidr, NetworkPolicy};
+pub use progress::{Progress, Stats};
 pub use reqwest::IntoUrl;
-pub use ty::{Fetcher, Parser, Urls};
+pub use robots::Robots;
+pub use seen::{url_hash, BloomSeen, HashSeen, Seen};
+pub use ty::{ConditionalFetcher, Fetched, Fetcher, Parser, Scorer, Seed, Text, Urls, Validators};
 pub use url::Url;
 
+use futures::sync::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
 use futures::{Async, AsyncSink, Future, Poll, Sink, Stream};
-use ty::Token;
+use hashbrown::{HashMap, HashSet};
+use page::extract_title;
+use progress::Counters;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use ty::{FetchBuffer, Headers, Token};
+use url::Origin;
 
+mod checkpoint;
+mod client;
+mod control;
 mod error;
 #[macro_use]
 mod macros;
 mod fetcher;
+mod frontier;
+mod page;
 mod parser;
+mod policy;
 pub mod prelude;
+mod progress;
+mod robots;
+mod seen;
 pub mod ty;
 mod url_parser;
 
@@ -62,17 +90,49 @@ mod url_parser;
 /// ```
 
 pub struct Crawler {
-    /// all crawled urls, including redirected ones
-    crawled: Urls,
+    /// all queued and crawled urls, including redirected ones
+    seen: Box<dyn Seen>,
     /// urls to be crawled
-    queue: Urls,
-    /// the resulting urls, without redirects
-    effective: Urls,
+    queue: Box<dyn Frontier>,
+    /// the resulting urls, without redirects, unless they're not kept
+    effective: Option<Urls>,
+    /// number of the resulting urls
+    found: usize,
+    /// urls being fetched, at most as many as the concurrent requests
+    in_flight: Arc<Mutex<HashMap<Url, Queued>>>,
+    /// the starting urls
+    seeds: Vec<Seed>,
+    /// origins of the urls to be crawled, unrestricted if None
+    scope: Option<HashSet<Origin>>,
     /// active tasks counter
     token: Token,
+    /// pause/resume/cancel handle
+    control: Control,
+    /// live statistics
+    counters: Arc<Counters>,
+    /// subscribers notified about new resulting urls
+    listeners: Vec<UnboundedSender<Url>>,
+    /// subscribers notified about crawled documents
+    page_listeners: Vec<UnboundedSender<Page>>,
+    /// subscribers notified about raw HTTP exchanges
+    response_listeners: Vec<UnboundedSender<Response>>,
+    /// subscriber notified with a checkpoint every given number of processed documents
+    checkpoints: Option<(usize, UnboundedSender<Checkpoint>)>,
+    /// processed documents counter
+    processed: usize,
+    /// requested documents counter, including the results of a resumed crawl
+    requested: usize,
+    /// maximum number of the requested documents, unlimited if None
+    limit: Option<usize>,
+    /// maximum depth of the followed links, unlimited if None
+    max_depth: Option<usize>,
+    /// robots.txt rules of the crawled sites
+    robots: HashMap<Origin, Robots>,
 
     /// a document fetching function
     fetcher: Fetcher,
+    /// a conditional document fetching function, with the pages of the previous crawl
+    history: Option<(ConditionalFetcher, Arc<HashMap<Url, Page>>)>,
     /// document parser
     parser: Parser,
 }
@@ -86,30 +146,365 @@ impl Crawler {
     /// parser: a Parser used for Url extraction
 
     pub fn new(start: impl IntoUrl, fetcher: Fetcher, parser: Parser) -> Result<Self> {
-        let start = start.into_url()?;
+        Self::seeded(vec![Seed::new(start, true)?], fetcher, parser)
+    }
+
+    /// Create new Crawler, starting from multiple urls
+    ///
+    /// Only the urls sharing the origin of one of the seeds with the `scope` flag
+    /// are crawled, besides the seeds themselves.
+    /// The pages record which seed led to them, see [Page::seed](struct.Page.html#structfield.seed).
+    ///
+    /// # Arguments
+    /// seeds: starting urls to be used as seeds for the crawler
+    /// fetcher: a Fetcher used for linked documents retrieval
+    /// parser: a Parser used for Url extraction
+
+    pub fn seeded(
+        seeds: impl IntoIterator<Item = Seed>,
+        fetcher: Fetcher,
+        parser: Parser,
+    ) -> Result<Self> {
+        let seeds = seeds.into_iter().collect::<Vec<_>>();
+
+        if seeds.is_empty() {
+            return Err(err_msg("no seeds to start crawling from"));
+        }
+
+        let mut queue = Vec::<Queued>::with_capacity(seeds.len());
+
+        for (index, seed) in seeds.iter().enumerate() {
+            // a duplicated seed is attributed to the first occurrence
+            if !queue.iter().any(|queued| queued.url == seed.url) {
+                queue.push(Queued::new(seed.url.clone(), 0, index));
+            }
+        }
+
+        Self::resume(
+            Checkpoint {
+                queue,
+                seeds,
+                ..Checkpoint::default()
+            },
+            fetcher,
+            parser,
+        )
+    }
+
+    /// Create new Crawler, continuing from given checkpoint
+    ///
+    /// The urls that were already part of the result are not sent to the subscribers again.
+    ///
+    /// # Arguments
+    /// checkpoint: a Crawler state snapshot
+    /// fetcher: a Fetcher used for linked documents retrieval
+    /// parser: a Parser used for Url extraction
 
-        let queue = {
-            let mut q = Urls::new();
-            q.insert(start);
-            q
+    pub fn resume(checkpoint: Checkpoint, fetcher: Fetcher, parser: Parser) -> Result<Self> {
+        Self::resume_with(checkpoint, MemoryFrontier::new(), fetcher, parser)
+    }
+
+    /// Create new Crawler, continuing from given checkpoint with given queue
+    ///
+    /// The spilled urls of the checkpoint are [restored](trait.Frontier.html#method.restore)
+    /// to the queue without reading them into memory, if the queue supports it.
+    ///
+    /// # Arguments
+    /// checkpoint: a Crawler state snapshot
+    /// frontier: an empty queue of the urls to be crawled
+    /// fetcher: a Fetcher used for linked documents retrieval
+    /// parser: a Parser used for Url extraction
+
+    pub fn resume_with(
+        checkpoint: Checkpoint,
+        mut frontier: impl Frontier + 'static,
+        fetcher: Fetcher,
+        parser: Parser,
+    ) -> Result<Self> {
+        let Checkpoint {
+            seen: hashes,
+            queue: urls,
+            segments,
+            seeds,
+            effective,
+            found,
+        } = checkpoint;
+
+        let scope = if seeds.is_empty() {
+            None
+        } else {
+            Some(
+                seeds
+                    .iter()
+                    .filter(|seed| seed.scope)
+                    .map(|seed| seed.url.origin())
+                    .collect(),
+            )
         };
 
+        let mut seen = HashSeen::new();
+
+        // the spilled urls are listed only by their hashes
+        if hashes.is_empty() {
+            for path in &segments {
+                for queued in frontier::read_segment(path)? {
+                    seen.insert(&queued.url);
+                }
+            }
+        }
+
+        for hash in hashes {
+            seen.insert_hash(hash);
+        }
+
+        for url in effective.iter() {
+            seen.insert(url);
+        }
+
+        for queued in urls {
+            seen.insert(&queued.url);
+            frontier.push(queued)?;
+        }
+
+        frontier.restore(&segments)?;
+
+        let found = found.max(effective.len());
+
+        let counters = Arc::new(Counters::default());
+        counters.set_queued(frontier.len());
+        counters.set_effective(found);
+
         Ok(Crawler {
-            crawled: Urls::new(),
-            queue,
-            effective: Urls::new(),
+            seen: Box::new(seen),
+            queue: Box::new(frontier),
+            effective: Some(effective),
+            found,
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+            seeds,
+            scope,
             token: Token::new(()),
+            control: Control::new(),
+            counters,
+            listeners: Vec::new(),
+            page_listeners: Vec::new(),
+            response_listeners: Vec::new(),
+            checkpoints: None,
+            processed: 0,
+            requested: found,
+            limit: None,
+            max_depth: None,
+            robots: HashMap::new(),
             fetcher,
+            history: None,
             parser,
         })
     }
 
+    /// Replace the queue of urls to be crawled, moving the already queued urls
+    ///
+    /// This allows using e.g. a [DiskFrontier](struct.DiskFrontier.html) for large sites,
+    /// or a [PriorityFrontier](struct.PriorityFrontier.html) for a custom crawling order.
+
+    pub fn with_frontier(mut self, mut frontier: impl Frontier + 'static) -> Result<Self> {
+        while let Some(queued) = self.queue.pop()? {
+            frontier.push(queued)?;
+        }
+
+        self.queue = Box::new(frontier);
+
+        Ok(self)
+    }
+
+    /// Replace the set of seen urls, moving the already seen ones
+    ///
+    /// This allows using e.g. a [BloomSeen](struct.BloomSeen.html) with fixed memory usage.
+
+    pub fn with_seen(mut self, mut seen: impl Seen + 'static) -> Self {
+        for hash in self.seen.hashes().unwrap_or_default() {
+            seen.insert_hash(hash);
+        }
+
+        self.seen = Box::new(seen);
+
+        self
+    }
+
+    /// Don't keep the resulting urls, e.g. when they're collected through
+    /// [subscribe](#method.subscribe)
+    ///
+    /// [into_result](#method.into_result) returns an empty set then,
+    /// and the checkpoints don't contain the resulting urls.
+
+    pub fn without_result(mut self) -> Self {
+        self.effective = None;
+
+        self
+    }
+
+    /// Stop crawling once given number of documents were requested
+    ///
+    /// The results of the crawl this one was resumed from count towards the limit.
+    /// The urls left in the queue are kept in the checkpoints.
+
+    pub fn with_page_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+
+        self
+    }
+
+    /// Don't follow the links of the documents at given depth
+    ///
+    /// The seeds are at depth 0, so a depth of 0 only fetches the seeds themselves.
+
+    pub fn with_max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+
+        self
+    }
+
+    /// Skip the urls disallowed by the robots.txt rules of their sites
+    ///
+    /// The seeds are fetched regardless of the rules.
+    /// The sites without rules are crawled without restrictions.
+
+    pub fn with_robots(mut self, robots: impl IntoIterator<Item = Robots>) -> Self {
+        self.robots.extend(
+            robots
+                .into_iter()
+                .map(|robots| (robots.origin().clone(), robots)),
+        );
+
+        self
+    }
+
+    /// Fetch the documents conditionally, based on the pages of a previous crawl
+    ///
+    /// Given fetcher is used instead of the one passed to the constructor.
+    /// The documents that weren't modified since the previous crawl are not parsed again,
+    /// their stored links are followed instead.
+    /// The pages of this crawl are available through
+    /// [subscribe_pages](#method.subscribe_pages), to be passed to the next one.
+
+    pub fn with_history(
+        mut self,
+        fetcher: ConditionalFetcher,
+        pages: impl IntoIterator<Item = Page>,
+    ) -> Self {
+        let pages = pages
+            .into_iter()
+            .map(|page| (page.url.clone(), page))
+            .collect();
+
+        self.history = Some((fetcher, Arc::new(pages)));
+
+        self
+    }
+
+    /// Return a handle that can be used to pause, resume or cancel this Crawler
+    ///
+    /// The handle remains valid after the Crawler is split into the stream and sink parts.
+
+    pub fn control(&self) -> Control {
+        self.control.clone()
+    }
+
+    /// Return a handle that can be used to read live statistics of this Crawler
+    ///
+    /// The handle remains valid after the Crawler is split into the stream and sink parts.
+
+    pub fn progress(&self) -> Progress {
+        Progress::new(self.counters.clone(), &self.token)
+    }
+
+    /// Subscribe to the resulting urls
+    ///
+    /// Every url added to the result is sent to the returned stream as soon as it's found.
+    /// The stream ends when the Crawler is dropped.
+
+    pub fn subscribe(&mut self) -> UnboundedReceiver<Url> {
+        let (tx, rx) = unbounded();
+
+        self.listeners.push(tx);
+
+        rx
+    }
+
+    /// Subscribe to the crawled documents
+    ///
+    /// Every successfully fetched document is sent to the returned stream, including
+    /// the ones that weren't modified since the previous crawl.
+    /// The stream ends when the Crawler is dropped.
+
+    pub fn subscribe_pages(&mut self) -> UnboundedReceiver<Page> {
+        let (tx, rx) = unbounded();
+
+        self.page_listeners.push(tx);
+
+        rx
+    }
+
+    /// Subscribe to the raw HTTP exchanges
+    ///
+    /// Every document fetched by the ConditionalFetcher, see
+    /// [with_history](#method.with_history), is sent to the returned stream with its headers.
+    /// The stream ends when the Crawler is dropped.
+
+    pub fn subscribe_responses(&mut self) -> UnboundedReceiver<Response> {
+        let (tx, rx) = unbounded();
+
+        self.response_listeners.push(tx);
+
+        rx
+    }
+
+    /// Subscribe to the Crawler state snapshots
+    ///
+    /// A checkpoint is sent to the returned stream every `every` processed documents.
+    /// The stream ends when the Crawler is dropped.
+    /// Subscribing again replaces the previous subscription.
+
+    pub fn checkpoints(&mut self, every: usize) -> UnboundedReceiver<Checkpoint> {
+        let (tx, rx) = unbounded();
+
+        self.checkpoints = Some((every.max(1), tx));
+
+        rx
+    }
+
+    /// Return current state snapshot
+    ///
+    /// The urls spilled by the queue are referred to by their segment files, see
+    /// [Frontier::checkpoint](trait.Frontier.html#method.checkpoint).
+
+    pub fn checkpoint(&mut self) -> Result<Checkpoint> {
+        let mut queue = self
+            .in_flight
+            .lock()
+            .expect("poisoned in-flight lock")
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let (queued, segments) = self.queue.checkpoint()?;
+
+        queue.extend(queued);
+
+        Ok(Checkpoint {
+            seen: self.seen.hashes().unwrap_or_default(),
+            queue,
+            segments,
+            seeds: self.seeds.clone(),
+            effective: self.effective.clone().unwrap_or_default(),
+            found: self.found,
+        })
+    }
+
     /// Return all extracted Urls
     ///
     /// Calling this method only makes sense after the Crawler finishes crawling.
 
     pub fn into_result(self) -> Urls {
-        self.effective
+        self.effective.unwrap_or_default()
     }
 }
 
@@ -118,38 +513,216 @@ impl Stream for Crawler {
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        let url = self.queue.iter().next().cloned();
+        self.counters.start();
+
+        match self.control.state() {
+            // stop producing new requests, the pending ones will still be processed
+            State::Cancelled => {
+                self.counters.finish();
+
+                return Ok(Async::Ready(None));
+            }
+            State::Paused => {
+                self.control.register();
 
-        if let Some(url) = url {
-            // remove from the queue
-            // unfortunately this performs additional lookup
-            // but it shouldn't a bottleneck
-            self.queue.remove(&url);
+                // state could have changed before the task got registered
+                if self.control.state() == State::Paused {
+                    return Ok(Async::NotReady);
+                } else {
+                    return self.poll();
+                }
+            }
+            State::Running => (),
+        }
+
+        let url = if self.limit.is_some_and(|limit| self.requested >= limit) {
+            // no more requests, the pending ones will still be processed
+            None
+        } else {
+            self.queue.pop()?
+        };
+
+        if let Some(queued) = url {
+            self.requested += 1;
+            self.counters.set_queued(self.queue.len());
 
-            self.crawled.insert(url.clone());
+            let Queued { url, depth, seed } = queued.clone();
+
+            self.in_flight
+                .lock()
+                .expect("poisoned in-flight lock")
+                .insert(url.clone(), queued);
+
+            let seed_url = self.seeds.get(seed).map(|seed| seed.url.clone());
 
             let parser = self.parser;
+            let in_flight = self.in_flight.clone();
 
             let token = self.token.clone();
+            let counters = self.counters.clone();
+
+            // the documents are copied only if anyone is listening
+            let archive = !self.response_listeners.is_empty();
+            let mut sent = Validators::default();
+
+            let document: Box<dyn Future<Item = Option<Document>, Error = Error> + Send> =
+                if let Some((fetcher, pages)) = &self.history {
+                    let previous = pages.get(&url).cloned();
+                    let validators = previous
+                        .as_ref()
+                        .map(|page| page.validators.clone())
+                        .unwrap_or_default();
+
+                    sent = validators.clone();
+
+                    Box::new(
+                        (fetcher)(url.clone(), validators).map(move |fetched| match fetched {
+                            Some(Fetched::Document {
+                                url,
+                                buffer,
+                                status,
+                                validators,
+                                headers,
+                            }) => Some(Document::Fetched(
+                                url,
+                                buffer,
+                                Some((status, validators, headers)),
+                            )),
+                            // not modified, although nothing was stored
+                            Some(Fetched::NotModified) => {
+                                previous.map(|page| Document::Unchanged(Box::new(page)))
+                            }
+                            None => None,
+                        }),
+                    )
+                } else {
+                    Box::new((self.fetcher)(url.clone()).map(|fetched| {
+                        fetched.map(|(url, buffer)| Document::Fetched(url, buffer, None))
+                    }))
+                };
 
             Ok(Async::Ready(Some(Box::new(
-                (self.fetcher)(url.clone()).and_then(move |opt| {
-                    if let Some((url, buffer)) = opt {
-                        (parser)(url.clone(), buffer.as_str()).map(move |parsed| {
-                            Some(CrawlerPayload::new(url.clone(), parsed, token))
-                        })
-                    } else {
-                        Ok(None)
-                    }
-                }),
+                document
+                    .and_then({
+                        let counters = counters.clone();
+                        let requested = url.clone();
+
+                        move |document| match document {
+                            Some(Document::Fetched(url, buffer, response)) => {
+                                let html = buffer.as_str();
+                                let body = buffer.as_bytes();
+                                let fetched = SystemTime::now();
+                                let requested_url = requested.clone();
+
+                                counters.fetched(html.len());
+
+                                (parser)(url.clone(), html).map(move |parsed| {
+                                    let (page, response) = match response {
+                                        Some((status, validators, headers)) => {
+                                            let content_type = headers
+                                                .iter()
+                                                .find(|(name, _)| {
+                                                    name.eq_ignore_ascii_case("content-type")
+                                                })
+                                                .map(|(_, value)| value.clone());
+
+                                            let response = if archive {
+                                                Some(Response {
+                                                    url: requested.clone(),
+                                                    effective: url.clone(),
+                                                    request_headers: sent.to_headers(),
+                                                    status,
+                                                    headers,
+                                                    body: body.to_vec(),
+                                                    fetched,
+                                                })
+                                            } else {
+                                                None
+                                            };
+
+                                            let page = Page {
+                                                url: requested,
+                                                effective: url.clone(),
+                                                seed: seed_url,
+                                                depth,
+                                                status,
+                                                content_type,
+                                                title: extract_title(html),
+                                                validators,
+                                                digest: content_hash(html),
+                                                links: parsed.clone(),
+                                                unchanged: false,
+                                            };
+
+                                            (Some(page), response)
+                                        }
+                                        None => (None, None),
+                                    };
+
+                                    Some(
+                                        CrawlerPayload::new(url.clone(), parsed, token)
+                                            .at_depth(depth)
+                                            .with_seed(seed)
+                                            .with_requested(requested_url)
+                                            .with_page(page)
+                                            .with_response(response),
+                                    )
+                                })
+                            }
+                            Some(Document::Unchanged(page)) => {
+                                counters.unchanged();
+
+                                let page = Page {
+                                    seed: seed_url,
+                                    depth,
+                                    unchanged: true,
+                                    ..*page
+                                };
+
+                                Ok(Some(
+                                    CrawlerPayload::new(
+                                        page.effective.clone(),
+                                        page.links.clone(),
+                                        token,
+                                    )
+                                    .at_depth(depth)
+                                    .with_seed(seed)
+                                    .with_requested(page.url.clone())
+                                    .with_page(Some(page)),
+                                ))
+                            }
+                            None => {
+                                counters.error();
+
+                                Ok(None)
+                            }
+                        }
+                    })
+                    .map_err(move |e| {
+                        counters.error();
+                        e
+                    })
+                    .then(move |result| {
+                        in_flight
+                            .lock()
+                            .expect("poisoned in-flight lock")
+                            .remove(&url);
+
+                        result
+                    }),
             ))))
         } else {
             // as the place when this is increased is here
             // there shouldn't be any problems with concurrent increments
             // 1 == only self
             if Token::strong_count(&self.token) > 1 {
+                // get notified if the crawl is cancelled while waiting
+                self.control.register();
+
                 Ok(Async::NotReady)
             } else {
+                self.counters.finish();
+
                 Ok(Async::Ready(None))
             }
         }
@@ -170,12 +743,79 @@ impl Sink for Crawler {
         if let Some(item) = item {
             let CrawlerPayload {
                 source,
+                requested,
                 urls,
+                depth,
+                seed,
+                page,
+                response,
                 token: _token,
             } = item;
-            self.crawled.insert(source.clone());
-            self.effective.insert(source);
-            self.queue.extend(urls.difference(&self.crawled).cloned());
+            // a redirect target shouldn't be crawled again, and it's reported
+            // only if it wasn't seen, otherwise it's reported once it's crawled
+            let found = requested.as_ref() == Some(&source) || self.seen.insert(&source);
+
+            let urls = if self.max_depth.is_some_and(|max| depth >= max) {
+                Urls::default()
+            } else {
+                urls
+            };
+
+            for url in urls {
+                let origin = url.origin();
+                let in_scope = self
+                    .scope
+                    .as_ref()
+                    .is_none_or(|scope| scope.contains(&origin));
+                let allowed = self
+                    .robots
+                    .get(&origin)
+                    .is_none_or(|robots| robots.allows(&url));
+
+                if in_scope && allowed && self.seen.insert(&url) {
+                    self.queue.push(Queued::new(url, depth + 1, seed))?;
+                }
+            }
+
+            if let Some(page) = page {
+                self.page_listeners
+                    .retain(|listener| listener.unbounded_send(page.clone()).is_ok());
+            }
+
+            if let Some(response) = response {
+                self.response_listeners
+                    .retain(|listener| listener.unbounded_send(response.clone()).is_ok());
+            }
+
+            if found {
+                self.found += 1;
+
+                if let Some(effective) = &mut self.effective {
+                    effective.insert(source.clone());
+                }
+
+                // forget the subscribers that are gone
+                self.listeners
+                    .retain(|listener| listener.unbounded_send(source.clone()).is_ok());
+            }
+
+            self.counters.set_effective(self.found);
+            self.counters.set_queued(self.queue.len());
+
+            self.processed += 1;
+
+            if let Some((every, _)) = self.checkpoints {
+                if self.processed % every == 0 {
+                    let checkpoint = self.checkpoint()?;
+
+                    if let Some((_, tx)) = &self.checkpoints {
+                        if tx.unbounded_send(checkpoint).is_err() {
+                            // nobody is listening anymore
+                            self.checkpoints = None;
+                        }
+                    }
+                }
+            }
         }
 
         Ok(AsyncSink::Ready)
@@ -186,6 +826,14 @@ impl Sink for Crawler {
     }
 }
 
+/// A document retrieved by a Fetcher or a ConditionalFetcher
+enum Document {
+    /// (effective_url, FetchBuffer, (status, validators, headers), if fetched conditionally)
+    Fetched(Url, FetchBuffer, Option<(u16, Validators, Headers)>),
+    /// the stored version of a document that wasn't modified
+    Unchanged(Box<Page>),
+}
+
 // this is required, because Debug cannot be auto-derived
 // for function pointers with reference arguments
 impl std::fmt::Debug for Crawler {
@@ -203,8 +851,18 @@ impl std::fmt::Debug for Crawler {
 pub struct CrawlerPayload {
     /// url of the source document for URLs
     source: Url,
+    /// the requested url the source document was fetched from, if any
+    requested: Option<Url>,
     /// parsed URLs
     urls: Urls,
+    /// depth of the source document
+    depth: usize,
+    /// index of the seed that led to the source document
+    seed: usize,
+    /// the source document, if it's recorded
+    page: Option<Page>,
+    /// the raw HTTP exchange of the source document, if it's archived
+    response: Option<Response>,
     /// queue processing token
     token: Token,
 }
@@ -220,8 +878,43 @@ impl CrawlerPayload {
     pub fn new(source: Url, urls: Urls, token: Token) -> Self {
         Self {
             source,
+            requested: None,
             urls,
+            depth: 0,
+            seed: 0,
+            page: None,
+            response: None,
             token,
         }
     }
+
+    /// Set the depth of the source document, i.e. the number of links followed
+    /// from the starting url
+    ///
+    /// The scraped URLs are queued one level deeper.
+
+    pub fn at_depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    fn with_seed(mut self, seed: usize) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    fn with_requested(mut self, url: Url) -> Self {
+        self.requested = Some(url);
+        self
+    }
+
+    fn with_page(mut self, page: Option<Page>) -> Self {
+        self.page = page;
+        self
+    }
+
+    fn with_response(mut self, response: Option<Response>) -> Self {
+        self.response = response;
+        self
+    }
 }
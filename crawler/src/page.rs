@@ -0,0 +1,112 @@
+//! Crawled documents, recorded for incremental re-crawls and archiving
+
+use crate::seen::fnv1a;
+use crate::ty::{Headers, Urls, Validators};
+use std::time::SystemTime;
+use url::Url;
+
+/// A crawled document
+///
+/// The pages of a finished crawl can be passed to the next one,
+/// see [Crawler::with_history](../struct.Crawler.html#method.with_history).
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    /// the requested url
+    pub url: Url,
+    /// the url the document was retrieved from, after redirects
+    pub effective: Url,
+    /// the starting url that led to the document, if the crawl was seeded
+    pub seed: Option<Url>,
+    /// number of links followed from the seed to the document
+    pub depth: usize,
+    /// HTTP status code of the response
+    pub status: u16,
+    /// value of the `Content-Type` header
+    pub content_type: Option<String>,
+    /// the document title
+    pub title: Option<String>,
+    /// cache validators of the document
+    pub validators: Validators,
+    /// hash of the document content, see [content_hash](../fn.content_hash.html)
+    pub digest: u64,
+    /// urls linked from the document
+    pub links: Urls,
+    /// true if the document wasn't modified since the previous crawl
+    pub unchanged: bool,
+}
+
+/// A raw HTTP exchange of a crawled document
+///
+/// See [Crawler::subscribe_responses](../struct.Crawler.html#method.subscribe_responses).
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    /// the requested url
+    pub url: Url,
+    /// the url the document was retrieved from, after redirects
+    pub effective: Url,
+    /// the conditional headers sent with the request
+    pub request_headers: Headers,
+    /// HTTP status code of the response
+    pub status: u16,
+    /// response headers
+    pub headers: Headers,
+    /// the document content, as received
+    pub body: Vec<u8>,
+    /// the time the response was received
+    pub fetched: SystemTime,
+}
+
+/// Return a stable 64-bit hash of given document (FNV-1a)
+pub fn content_hash(document: &str) -> u64 {
+    fnv1a(document)
+}
+
+/// Return the contents of the `<title>` element of given html document, with collapsed whitespace
+///
+/// This is a simple scan rather than a full parse, as it's done for every crawled document.
+
+pub(crate) fn extract_title(html: &str) -> Option<String> {
+    // both `<title` and `</title` are ascii, so the slicing below is safe
+    let find = |from: usize, tag: &str| {
+        html[from..]
+            .match_indices('<')
+            .map(|(pos, _)| from + pos)
+            .find(|pos| {
+                html.as_bytes()
+                    .get(*pos..*pos + tag.len())
+                    .is_some_and(|prefix| prefix.eq_ignore_ascii_case(tag.as_bytes()))
+            })
+    };
+
+    let open = find(0, "<title")?;
+    let start = open + html[open..].find('>')? + 1;
+    let end = find(start, "</title")?;
+
+    let title = html[start..end]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if title.is_empty() {
+        None
+    } else {
+        Some(title)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn title() {
+        assert_eq!(
+            extract_title("<html><head><TITLE lang=\"en\">\n  Some   page\n</Title></head>"),
+            Some("Some page".to_owned())
+        );
+        assert_eq!(extract_title("<html><title></title></html>"), None);
+        assert_eq!(extract_title("<html><body>no title</body></html>"), None);
+    }
+}
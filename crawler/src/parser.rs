@@ -1,5 +1,6 @@
 use crate::error::*;
-use crate::ty::{UrlParser, Urls};
+use crate::ty::{Text, UrlParser, Urls};
+use hashbrown::HashMap;
 use log::*;
 use scraper::{Html, Selector};
 use url::Url;
@@ -42,6 +43,97 @@ pub fn parse(base: Url, html: &str, url_parser: UrlParser) -> Result<Urls> {
         .collect())
 }
 
+/// Text extractor
+///
+/// # Arguments
+/// html: a html document to be parsed
+///
+/// Return value: the title, headings and body text of the document, with collapsed whitespace.
+/// The contents of scripts, styles and other non-rendered elements are skipped.
+
+pub fn parse_text(html: &str) -> Result<Text> {
+    const SKIPPED: &[&str] = &["head", "script", "style", "noscript", "template"];
+
+    fn collapse<'a>(text: impl Iterator<Item = &'a str>) -> String {
+        text.flat_map(str::split_whitespace)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    let doc = Html::parse_document(html);
+    let title = Selector::parse("title").map_err(|_| err_msg("failed to parse selector"))?;
+    let headings = Selector::parse("h1, h2, h3, h4, h5, h6")
+        .map_err(|_| err_msg("failed to parse selector"))?;
+
+    let body = doc.tree.root().descendants().filter_map(|node| {
+        let text = node.value().as_text()?;
+
+        let rendered = !node.ancestors().any(|parent| {
+            parent
+                .value()
+                .as_element()
+                .is_some_and(|element| SKIPPED.contains(&element.name()))
+        });
+
+        if rendered {
+            Some(&**text)
+        } else {
+            None
+        }
+    });
+
+    Ok(Text {
+        title: doc
+            .select(&title)
+            .next()
+            .map(|title| collapse(title.text()))
+            .filter(|title| !title.is_empty()),
+        headings: doc
+            .select(&headings)
+            .map(|heading| collapse(heading.text()))
+            .filter(|heading| !heading.is_empty())
+            .collect(),
+        body: collapse(body),
+    })
+}
+
+/// Sitemap parser
+///
+/// # Arguments
+/// sitemap: a sitemap xml document to be parsed
+///
+/// Return value: a map of the listed urls to their priorities, ready to be used with
+/// [Priority::Scores](../enum.Priority.html#variant.Scores).
+/// The urls without a valid priority get the default one, 0.5.
+
+pub fn parse_sitemap(sitemap: &str) -> Result<HashMap<Url, f64>> {
+    let doc = Html::parse_document(sitemap);
+    let url = Selector::parse("url").map_err(|_| err_msg("failed to parse selector"))?;
+    let loc = Selector::parse("loc").map_err(|_| err_msg("failed to parse selector"))?;
+    let priority = Selector::parse("priority").map_err(|_| err_msg("failed to parse selector"))?;
+
+    let text = |element: scraper::ElementRef| element.text().collect::<String>().trim().to_owned();
+
+    Ok(doc
+        .select(&url)
+        .filter_map(|entry| {
+            let location = entry.select(&loc).next().map(text)?;
+
+            let url = Url::parse(&location)
+                .map_err(|_| debug!("Skipping sitemap url: {:?}", location))
+                .ok()?;
+
+            let priority = entry
+                .select(&priority)
+                .next()
+                .and_then(|priority| text(priority).parse::<f64>().ok())
+                .unwrap_or(crate::frontier::DEFAULT_SCORE);
+
+            Some((url, priority))
+        })
+        .collect())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -110,4 +202,55 @@ mod tests {
         assert_eq!(parsed, hashset! { url!("http://example.com/bar.html") })
     }
 
+    #[test]
+    fn text() {
+        let data = r#"<!doctype html>
+        <html>
+            <head>
+                <title> Parse
+                    test </title>
+                <style>body { color: red; }</style>
+            </head>
+            <body>
+                <h1>First <em>heading</em></h1>
+                <p>Some   <a href="testing.html">linked</a> text.</p>
+                <script>var ignored = true;</script>
+                <h2></h2>
+                <h3>Second</h3>
+            </body>
+        </html>
+        "#;
+
+        let text = parse_text(data).unwrap();
+
+        assert_eq!(text.title.as_deref(), Some("Parse test"));
+        assert_eq!(text.headings, vec!["First heading", "Second"]);
+        assert_eq!(text.body, "First heading Some linked text. Second");
+    }
+
+    #[test]
+    fn sitemap() {
+        let data = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+            <url>
+                <loc>http://example.com/</loc>
+                <priority>1.0</priority>
+            </url>
+            <url>
+                <loc>http://example.com/about.html</loc>
+                <lastmod>2019-05-01</lastmod>
+            </url>
+            <url>
+                <loc>not a url</loc>
+                <priority>0.8</priority>
+            </url>
+        </urlset>
+        "#;
+
+        let parsed = parse_sitemap(data).unwrap();
+
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[&url!("http://example.com/")], 1.0);
+        assert_eq!(parsed[&url!("http://example.com/about.html")], 0.5);
+    }
 }
@@ -0,0 +1,405 @@
+//! Network policy of the fetched urls, guarding against server-side request forgery
+
+use crate::error::*;
+use crate::fetcher;
+use futures::future::{self, Either};
+use futures::Future;
+use hyper::client::connect::dns::{GaiResolver, Name, Resolve};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use url::{Host, Url};
+
+/// Address blocks refused by default: private, loopback, link-local, multicast and reserved ones
+const BLOCKED: &[&str] = &[
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.0.0.0/24",
+    "192.168.0.0/16",
+    "198.18.0.0/15",
+    "224.0.0.0/4",
+    "240.0.0.0/4",
+    "::/128",
+    "::1/128",
+    "2001:db8::/32",
+    "fc00::/7",
+    "fe80::/10",
+    "fec0::/10",
+    "ff00::/8",
+];
+
+/// Number of the threads resolving the host names
+const RESOLVER_THREADS: usize = 4;
+
+/// The policy used by the fetchers, if installed
+static INSTALLED: OnceLock<NetworkPolicy> = OnceLock::new();
+
+/// A block of IP addresses in CIDR notation, e.g. `10.0.0.0/8` or `fd00::/8`
+///
+/// A bare address is a block of its own.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Return true if given address belongs to the block
+    ///
+    /// An IPv6 address embedding an IPv4 one, e.g. `64:ff9b::a00:1` or `2002:a00:1::`,
+    /// belongs to the IPv4 blocks of the embedded address.
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V4(_), IpAddr::V6(ip)) => {
+                embedded(ip).is_some_and(|ip| self.contains(IpAddr::V4(ip)))
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = Error;
+
+    fn from_str(cidr: &str) -> Result<Self> {
+        let invalid = || err_msg(format!("invalid CIDR block {:?}", cidr));
+
+        let (network, prefix) = match cidr.split_once('/') {
+            Some((network, prefix)) => (network, Some(prefix)),
+            None => (cidr, None),
+        };
+
+        let network = unmapped(network.parse::<IpAddr>().map_err(|_| invalid())?);
+        let bits = if network.is_ipv4() { 32 } else { 128 };
+
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u8>().map_err(|_| invalid())?,
+            None => bits,
+        };
+
+        if prefix > bits {
+            return Err(invalid());
+        }
+
+        Ok(Self { network, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// Rules deciding which addresses the fetched urls may lead to
+///
+/// The private, loopback, link-local, multicast and reserved addresses are refused,
+/// unless they're explicitly allowed. The denied addresses are refused in any case.
+///
+/// Once [installed](#method.install), the policy is enforced by the built-in fetchers and
+/// [send](prelude/fn.send.html) when connecting: a host name is resolved once, and the connection
+/// is made only to its checked addresses, so it can't be re-resolved to a refused one in between.
+/// The same goes for every redirect. The IPv4 addresses embedded in the IPv6 ones, e.g. by NAT64
+/// or 6to4, are checked as well.
+
+#[derive(Debug, Clone)]
+pub struct NetworkPolicy {
+    allowed: Vec<Cidr>,
+    denied: Vec<Cidr>,
+    blocked: Vec<Cidr>,
+}
+
+impl NetworkPolicy {
+    /// Create new policy, refusing the non-public addresses
+    pub fn new() -> Self {
+        Self {
+            allowed: Vec::new(),
+            denied: Vec::new(),
+            blocked: BLOCKED
+                .iter()
+                .map(|cidr| cidr.parse().expect("invalid built-in CIDR block"))
+                .collect(),
+        }
+    }
+
+    /// Allow given address blocks, even if they're not public
+    pub fn with_allowed(mut self, allowed: impl IntoIterator<Item = Cidr>) -> Self {
+        self.allowed.extend(allowed);
+        self
+    }
+
+    /// Refuse given address blocks
+    pub fn with_denied(mut self, denied: impl IntoIterator<Item = Cidr>) -> Self {
+        self.denied.extend(denied);
+        self
+    }
+
+    /// Return true if given address may be fetched from
+    pub fn permits(&self, ip: IpAddr) -> bool {
+        let within = |blocks: &[Cidr]| blocks.iter().any(|block| block.contains(ip));
+
+        !within(&self.denied) && (within(&self.allowed) || !within(&self.blocked))
+    }
+
+    /// Return true if the host of given url is an address that isn't permitted
+    ///
+    /// Allows refusing such urls upfront; the host names are checked only when resolved.
+
+    pub fn refuses_address(&self, url: &Url) -> bool {
+        match url.host() {
+            Some(Host::Ipv4(ip)) => !self.permits(ip.into()),
+            Some(Host::Ipv6(ip)) => !self.permits(ip.into()),
+            _ => false,
+        }
+    }
+
+    /// Resolve the host of given url, failing if any of its addresses isn't permitted
+    pub fn check(&self, url: &Url) -> impl Future<Item = (), Error = Error> + Send {
+        match url.host() {
+            Some(Host::Domain(domain)) => Either::B(self.resolve(domain).map(|_| ())),
+            Some(Host::Ipv4(ip)) => Either::A(future::result(self.verify(url.as_str(), ip.into()))),
+            Some(Host::Ipv6(ip)) => Either::A(future::result(self.verify(url.as_str(), ip.into()))),
+            None => Either::A(future::err(Failed::error(
+                FetchError::Dns,
+                format!("no host in {}", url),
+            ))),
+        }
+    }
+
+    /// Resolve given host name, failing if any of its addresses isn't permitted
+    pub(crate) fn resolve(
+        &self,
+        host: &str,
+    ) -> impl Future<Item = Vec<IpAddr>, Error = Error> + Send {
+        let policy = self.clone();
+        let host = host.to_owned();
+
+        resolve(&host).and_then(move |addresses| {
+            for ip in &addresses {
+                policy.verify(&host, *ip)?;
+            }
+
+            Ok(addresses)
+        })
+    }
+
+    /// Install as the policy of the built-in fetchers
+    ///
+    /// Fails if a policy was already installed, or the fetchers already sent a request.
+
+    pub fn install(self) -> Result<()> {
+        if fetcher::started() {
+            return Err(err_msg("fetchers already started"));
+        }
+
+        INSTALLED
+            .set(self)
+            .map_err(|_| err_msg("network policy already installed"))
+    }
+
+    /// Return the installed policy, if any
+    pub fn installed() -> Option<&'static NetworkPolicy> {
+        INSTALLED.get()
+    }
+
+    /// Fail if given address of given host isn't permitted
+    pub(crate) fn verify(&self, host: &str, ip: IpAddr) -> Result<()> {
+        if self.permits(ip) {
+            Ok(())
+        } else {
+            Err(Failed::error(
+                FetchError::Refused,
+                format!(
+                    "refusing to connect to {} at a restricted address {}",
+                    host, ip
+                ),
+            ))
+        }
+    }
+}
+
+impl Default for NetworkPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Resolve given host name, failing if it has no addresses
+pub(crate) fn resolve(host: &str) -> impl Future<Item = Vec<IpAddr>, Error = Error> + Send {
+    static RESOLVER: OnceLock<GaiResolver> = OnceLock::new();
+
+    let resolver = RESOLVER
+        .get_or_init(|| GaiResolver::new(RESOLVER_THREADS))
+        .clone();
+    let host = host.to_owned();
+
+    future::result(Name::from_str(&host).map_err(|e| Failed::error(FetchError::Dns, e.to_string())))
+        .and_then(move |name| {
+            resolver
+                .resolve(name)
+                .map_err(|e| Failed::error(FetchError::Dns, e.to_string()))
+        })
+        .and_then(move |addresses| {
+            let addresses = addresses.collect::<Vec<_>>();
+
+            if addresses.is_empty() {
+                Err(Failed::error(
+                    FetchError::Dns,
+                    format!("no addresses of {}", host),
+                ))
+            } else {
+                Ok(addresses)
+            }
+        })
+}
+
+/// Return the IPv4 address embedded in given IPv6 one, if any
+///
+/// These are the IPv4-mapped (`::ffff:0:0/96`), IPv4-compatible (`::/96`), NAT64
+/// (`64:ff9b::/96`) and 6to4 (`2002::/16`) addresses.
+
+fn embedded(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let bits = u128::from(ip);
+
+    match ip.segments() {
+        [0, 0, 0, 0, 0, 0xffff, ..] | [0, 0, 0, 0, 0, 0, ..] | [0x64, 0xff9b, 0, 0, 0, 0, ..] => {
+            Some(Ipv4Addr::from(bits as u32))
+        }
+        [0x2002, ..] => Some(Ipv4Addr::from((bits >> 80) as u32)),
+        _ => None,
+    }
+}
+
+/// Return the IPv4 address of an IPv4-mapped IPv6 one, e.g. `::ffff:127.0.0.1`
+fn unmapped(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(ip) => match Ipv6Addr::to_ipv4_mapped(&ip) {
+            Some(ip) => IpAddr::V4(ip),
+            None => IpAddr::V6(ip),
+        },
+        ip => ip,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn cidr() {
+        let block = "10.1.0.0/16".parse::<Cidr>().unwrap();
+
+        assert!(block.contains(ip("10.1.255.1")));
+        assert!(block.contains(ip("::ffff:10.1.0.1")));
+        assert!(!block.contains(ip("10.2.0.1")));
+        assert!(!block.contains(ip("::1")));
+
+        assert!("0.0.0.0/0".parse::<Cidr>().unwrap().contains(ip("8.8.8.8")));
+        assert!("fd00::/8".parse::<Cidr>().unwrap().contains(ip("fd12::1")));
+        assert_eq!(
+            "192.168.0.1".parse::<Cidr>().unwrap().to_string(),
+            "192.168.0.1/32"
+        );
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("example.com/8".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn permits() {
+        let policy = NetworkPolicy::new()
+            .with_allowed(vec!["10.0.0.0/8".parse().unwrap()])
+            .with_denied(vec![
+                "10.1.0.0/16".parse().unwrap(),
+                "93.184.216.0/24".parse().unwrap(),
+            ]);
+
+        for refused in &[
+            "127.0.0.1",
+            "169.254.169.254",
+            "192.168.1.1",
+            "172.20.0.1",
+            "224.0.0.1",
+            "0.0.0.0",
+            "::1",
+            "::ffff:127.0.0.1",
+            "fe80::1",
+            "fd00::1",
+            "10.1.2.3",
+            "93.184.216.34",
+        ] {
+            assert!(!policy.permits(ip(refused)), "{} is permitted", refused);
+        }
+
+        for permitted in &["8.8.8.8", "10.2.3.4", "2606:4700::1111"] {
+            assert!(policy.permits(ip(permitted)), "{} is refused", permitted);
+        }
+    }
+
+    #[test]
+    fn check() {
+        let policy = NetworkPolicy::new();
+
+        assert!(policy
+            .check(&url!("http://127.0.0.1:8000/"))
+            .wait()
+            .is_err());
+        assert!(policy.check(&url!("http://[::1]/")).wait().is_err());
+
+        for refused in &[
+            "http://[64:ff9b::a9fe:a9fe]/",
+            "http://[2002:7f00:1::1]/",
+            "http://[::7f00:1]/",
+            "http://[fec0::1]/",
+            "http://[2001:db8::1]/",
+        ] {
+            assert!(
+                policy.check(&url!(*refused)).wait().is_err(),
+                "{} is permitted",
+                refused
+            );
+        }
+
+        // the public addresses are reachable through NAT64 and 6to4
+        assert!(policy
+            .check(&url!("http://[64:ff9b::5db8:d822]/"))
+            .wait()
+            .is_ok());
+        assert!(policy
+            .check(&url!("http://[2002:5db8:d822::1]/"))
+            .wait()
+            .is_ok());
+
+        let refused = policy.check(&url!("http://10.0.0.1/")).wait().unwrap_err();
+        assert_eq!(FetchError::of(&refused), FetchError::Refused);
+        assert!(policy.check(&url!("http://93.184.216.34/")).wait().is_ok());
+
+        assert!(policy.refuses_address(&url!("http://169.254.169.254/latest/meta-data/")));
+        assert!(!policy.refuses_address(&url!("http://localhost/")));
+    }
+}
@@ -1,10 +1,11 @@
 //! This module contains all useful imports for this crate
 
-pub use crate::ty::{Fetcher, Parser, Urls};
-pub use crate::Crawler;
+pub use crate::ty::{ConditionalFetcher, Fetcher, Parser, Text, Urls};
+pub use crate::{Checkpoint, Control, Crawler, Page, Progress, Seed};
+pub use crate::{Frontier, Seen};
 
-pub use crate::fetcher::fetch;
-pub use crate::parser::parse;
+pub use crate::fetcher::{fetch, fetch_conditional, send, try_fetch_conditional};
+pub use crate::parser::{parse, parse_sitemap, parse_text};
 pub use crate::url_parser::parse_url;
 
 pub use reqwest::IntoUrl;
@@ -0,0 +1,180 @@
+//! Live crawl statistics
+
+use crate::ty::Token;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+/// A handle used for reading live statistics of a Crawler
+///
+/// The handle is cheap to clone, and it remains valid after the Crawler finishes,
+/// reporting the final statistics.
+
+#[derive(Debug, Clone)]
+pub struct Progress {
+    counters: Arc<Counters>,
+    /// used to count the tasks in flight, without keeping the token alive
+    token: Weak<()>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    queued: AtomicUsize,
+    fetched: AtomicUsize,
+    unchanged: AtomicUsize,
+    effective: AtomicUsize,
+    errors: AtomicUsize,
+    bytes: AtomicU64,
+    /// (start, finish) of the crawl
+    timing: Mutex<(Option<Instant>, Option<Instant>)>,
+}
+
+/// A snapshot of the crawl statistics
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    /// urls waiting in the queue
+    pub queued: usize,
+    /// requests being processed
+    pub in_flight: usize,
+    /// successfully fetched documents
+    pub fetched: usize,
+    /// fetched documents not modified since the previous crawl, included in `fetched`
+    pub unchanged: usize,
+    /// unique resulting urls
+    pub effective: usize,
+    /// failed fetches
+    pub errors: usize,
+    /// downloaded bytes
+    pub bytes: u64,
+    /// time elapsed since the crawl started
+    pub elapsed: Duration,
+    /// average number of fetched documents per second
+    pub pages_per_sec: f64,
+}
+
+impl Progress {
+    pub(crate) fn new(counters: Arc<Counters>, token: &Token) -> Self {
+        Self {
+            counters,
+            token: Arc::downgrade(token),
+        }
+    }
+
+    /// Create a handle reporting given, final statistics
+    ///
+    /// This is useful for restoring the statistics of a crawl that is not running anymore.
+
+    pub fn detached(stats: &Stats) -> Self {
+        let now = Instant::now();
+
+        let counters = Counters {
+            queued: AtomicUsize::new(stats.queued),
+            fetched: AtomicUsize::new(stats.fetched),
+            unchanged: AtomicUsize::new(stats.unchanged),
+            effective: AtomicUsize::new(stats.effective),
+            errors: AtomicUsize::new(stats.errors),
+            bytes: AtomicU64::new(stats.bytes),
+            timing: Mutex::new((now.checked_sub(stats.elapsed), Some(now))),
+        };
+
+        Self {
+            counters: Arc::new(counters),
+            token: Weak::new(),
+        }
+    }
+
+    /// Return current statistics
+    pub fn stats(&self) -> Stats {
+        let c = &self.counters;
+
+        let elapsed = match *c.timing.lock().expect("poisoned progress lock") {
+            (Some(start), Some(finish)) => finish - start,
+            (Some(start), None) => start.elapsed(),
+            _ => Duration::default(),
+        };
+
+        let fetched = c.fetched.load(Ordering::Relaxed);
+
+        let secs = elapsed.as_secs_f64();
+        let pages_per_sec = if secs > 0.0 {
+            fetched as f64 / secs
+        } else {
+            0.0
+        };
+
+        Stats {
+            queued: c.queued.load(Ordering::Relaxed),
+            // 1 == only the crawler
+            in_flight: self.token.strong_count().saturating_sub(1),
+            fetched,
+            unchanged: c.unchanged.load(Ordering::Relaxed),
+            effective: c.effective.load(Ordering::Relaxed),
+            errors: c.errors.load(Ordering::Relaxed),
+            bytes: c.bytes.load(Ordering::Relaxed),
+            elapsed,
+            pages_per_sec,
+        }
+    }
+}
+
+impl Counters {
+    pub(crate) fn start(&self) {
+        let mut timing = self.timing.lock().expect("poisoned progress lock");
+
+        if timing.0.is_none() {
+            timing.0 = Some(Instant::now());
+        }
+    }
+
+    pub(crate) fn finish(&self) {
+        let mut timing = self.timing.lock().expect("poisoned progress lock");
+
+        if timing.1.is_none() {
+            timing.1 = Some(Instant::now());
+        }
+    }
+
+    pub(crate) fn set_queued(&self, queued: usize) {
+        self.queued.store(queued, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_effective(&self, effective: usize) {
+        self.effective.store(effective, Ordering::Relaxed);
+    }
+
+    pub(crate) fn fetched(&self, bytes: usize) {
+        self.fetched.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn unchanged(&self) {
+        self.fetched.fetch_add(1, Ordering::Relaxed);
+        self.unchanged.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detached() {
+        let stats = Stats {
+            queued: 0,
+            in_flight: 0,
+            fetched: 10,
+            unchanged: 3,
+            effective: 8,
+            errors: 2,
+            bytes: 1024,
+            elapsed: Duration::from_secs(5),
+            pages_per_sec: 2.0,
+        };
+
+        assert_eq!(Progress::detached(&stats).stats(), stats);
+    }
+}
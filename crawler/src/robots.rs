@@ -0,0 +1,221 @@
+//! Rules of the robots exclusion protocol, see RFC 9309
+
+use url::{Origin, Url};
+
+/// The rules of a site's `/robots.txt` applying to a crawler
+///
+/// The group of the crawler's product token, e.g. `examplebot` of `ExampleBot/1.0`, is used
+/// if there's one, and the `*` group otherwise. The longest matching rule decides, with `Allow`
+/// winning a tie. The patterns may contain `*` wildcards and end with `$`.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Robots {
+    origin: Origin,
+    /// the patterns with true for the allowing ones
+    rules: Vec<(String, bool)>,
+}
+
+impl Robots {
+    /// Parse the `/robots.txt` of given url's site for the crawler of given user agent
+    pub fn parse(url: &Url, text: &str, user_agent: &str) -> Self {
+        let token = user_agent
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+
+        let mut specific = Vec::new();
+        let mut general = Vec::new();
+
+        // the agents of the current group, and whether its rules started
+        let mut agents = Vec::<String>::new();
+        let mut in_rules = false;
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim().to_ascii_lowercase(), value.trim()),
+                None => continue,
+            };
+
+            match key.as_str() {
+                "user-agent" => {
+                    if in_rules {
+                        agents.clear();
+                        in_rules = false;
+                    }
+
+                    agents.push(value.to_ascii_lowercase());
+                }
+                "allow" | "disallow" => {
+                    in_rules = true;
+
+                    // an empty `Disallow` allows everything, which is the default anyway
+                    if value.is_empty() {
+                        continue;
+                    }
+
+                    let rule = (value.to_owned(), key == "allow");
+
+                    if !token.is_empty() && agents.contains(&token) {
+                        specific.push(rule);
+                    } else if agents.iter().any(|agent| agent == "*") {
+                        general.push(rule);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let rules = if specific.is_empty() && !mentions(text, &token) {
+            general
+        } else {
+            specific
+        };
+
+        Self {
+            origin: url.origin(),
+            rules,
+        }
+    }
+
+    /// Return the origin of the site the rules apply to
+    pub fn origin(&self) -> &Origin {
+        &self.origin
+    }
+
+    /// Return true if given url may be crawled
+    ///
+    /// The urls of other sites are always allowed.
+
+    pub fn allows(&self, url: &Url) -> bool {
+        if url.origin() != self.origin {
+            return true;
+        }
+
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_owned(),
+        };
+
+        self.rules
+            .iter()
+            .filter(|(pattern, _)| matches(pattern, &path))
+            .max_by_key(|(pattern, allow)| (pattern.len(), *allow))
+            .is_none_or(|(_, allow)| *allow)
+    }
+}
+
+/// Return true if there's a group of given product token, even without any rules
+fn mentions(text: &str, token: &str) -> bool {
+    !token.is_empty()
+        && text.lines().any(|line| {
+            let line = line.split('#').next().unwrap_or_default();
+
+            match line.split_once(':') {
+                Some((key, value)) => {
+                    key.trim().eq_ignore_ascii_case("user-agent")
+                        && value.trim().eq_ignore_ascii_case(token)
+                }
+                None => false,
+            }
+        })
+}
+
+/// Return true if given path matches a pattern with `*` wildcards and an optional `$` end
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+
+    let mut rest = match path.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+
+    let parts = parts.collect::<Vec<_>>();
+
+    for (index, part) in parts.iter().enumerate() {
+        if anchored && index == parts.len() - 1 {
+            // the last part has to match the end of the path
+            return rest.ends_with(part);
+        }
+
+        match rest.find(part) {
+            Some(position) => rest = &rest[position + part.len()..],
+            None => return false,
+        }
+    }
+
+    !anchored || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS: &str = "
+        # comments are ignored
+        User-agent: *
+        Disallow: /private/
+        Disallow: /*.pdf$
+        Allow: /private/public.html
+
+        User-agent: examplebot
+        User-agent: otherbot
+        Disallow: /
+        Allow: /docs/
+
+        User-agent: emptybot
+        Disallow:
+    ";
+
+    fn allows(robots: &Robots, url: &str) -> bool {
+        robots.allows(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn groups() {
+        let site = url!("http://example.com/robots.txt");
+
+        let general = Robots::parse(&site, ROBOTS, "webcrawl");
+
+        assert!(allows(&general, "http://example.com/"));
+        assert!(!allows(&general, "http://example.com/private/secret.html"));
+        assert!(allows(&general, "http://example.com/private/public.html"));
+        assert!(!allows(&general, "http://example.com/files/report.pdf"));
+        assert!(allows(
+            &general,
+            "http://example.com/files/report.pdf?page=2"
+        ));
+        assert!(allows(&general, "http://example.org/private/secret.html"));
+
+        let specific = Robots::parse(&site, ROBOTS, "ExampleBot/1.0 (+http://example.com/bot)");
+
+        assert!(!allows(&specific, "http://example.com/"));
+        assert!(!allows(&specific, "http://example.com/private/public.html"));
+        assert!(allows(&specific, "http://example.com/docs/index.html"));
+
+        let empty = Robots::parse(&site, ROBOTS, "emptybot");
+
+        assert!(allows(&empty, "http://example.com/private/secret.html"));
+    }
+
+    #[test]
+    fn patterns() {
+        assert!(matches("/", "/anything"));
+        assert!(matches("/a*c", "/abbbc/d"));
+        assert!(matches("/*.php$", "/index.php"));
+        assert!(!matches("/*.php$", "/index.php5"));
+        assert!(matches("/exact$", "/exact"));
+        assert!(!matches("/exact$", "/exactly"));
+        assert!(matches("/*?*sort=", "/list?page=1&sort=asc"));
+        assert!(!matches("/docs", "/doc"));
+    }
+}
@@ -0,0 +1,192 @@
+//! Compact sets of already seen urls
+
+use hashbrown::HashSet;
+use url::Url;
+
+/// A set of urls that were already seen by the Crawler, allowing for pluggable implementations
+///
+/// Implementations are free to store only the url hashes, see [url_hash](fn.url_hash.html).
+
+pub trait Seen: Send {
+    /// Mark given url hash as seen, returning true if it wasn't seen before
+    fn insert_hash(&mut self, hash: u64) -> bool;
+
+    /// Return the number of inserted hashes
+    fn len(&self) -> usize;
+
+    /// Return true if nothing was inserted yet
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return all seen hashes, if the implementation is able to list them
+    fn hashes(&self) -> Option<Vec<u64>>;
+
+    /// Mark given url as seen, returning true if it wasn't seen before
+    fn insert(&mut self, url: &Url) -> bool {
+        self.insert_hash(url_hash(url))
+    }
+}
+
+/// Return a stable 64-bit hash of given url (FNV-1a)
+///
+/// The hash doesn't depend on the process or the compiler version,
+/// so it can be safely persisted.
+
+pub fn url_hash(url: &Url) -> u64 {
+    fnv1a(url.as_str())
+}
+
+pub(crate) fn fnv1a(data: &str) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    data.bytes().fold(OFFSET, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
+
+/// An exact seen-set, storing 64-bit url hashes
+#[derive(Debug, Default)]
+pub struct HashSeen {
+    hashes: HashSet<u64>,
+}
+
+impl HashSeen {
+    /// Create new, empty set
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Seen for HashSeen {
+    fn insert_hash(&mut self, hash: u64) -> bool {
+        self.hashes.insert(hash)
+    }
+
+    fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    fn hashes(&self) -> Option<Vec<u64>> {
+        Some(self.hashes.iter().cloned().collect())
+    }
+}
+
+/// A probabilistic seen-set with fixed memory usage
+///
+/// A url may be falsely reported as seen with the configured probability,
+/// in which case it won't be crawled.
+/// The hashes can't be listed, so the checkpoints of a Crawler using this set
+/// don't contain the seen urls.
+
+#[derive(Debug, Clone)]
+pub struct BloomSeen {
+    bits: Vec<u64>,
+    /// number of bits
+    size: u64,
+    /// number of hash functions
+    hashes: u32,
+    count: usize,
+}
+
+impl BloomSeen {
+    /// Create new filter sized for given number of urls and false positive probability
+    pub fn new(capacity: usize, false_positive: f64) -> Self {
+        let capacity = capacity.max(1) as f64;
+        let false_positive = false_positive.clamp(f64::MIN_POSITIVE, 0.5);
+
+        let ln2 = std::f64::consts::LN_2;
+        let size = (-capacity * false_positive.ln() / (ln2 * ln2))
+            .ceil()
+            .max(64.0) as u64;
+        let hashes = ((size as f64 / capacity) * ln2).round().max(1.0) as u32;
+
+        Self {
+            bits: vec![0; size.div_ceil(64) as usize],
+            size,
+            hashes,
+            count: 0,
+        }
+    }
+}
+
+impl Seen for BloomSeen {
+    fn insert_hash(&mut self, hash: u64) -> bool {
+        // double hashing, deriving all bit positions from the two halves of the hash
+        let h1 = hash & 0xffff_ffff;
+        let h2 = (hash >> 32) | 1;
+
+        let mut inserted = false;
+
+        for i in 0..u64::from(self.hashes) {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) % self.size;
+            let (word, mask) = ((bit / 64) as usize, 1 << (bit % 64));
+
+            if self.bits[word] & mask == 0 {
+                self.bits[word] |= mask;
+                inserted = true;
+            }
+        }
+
+        if inserted {
+            self.count += 1;
+        }
+
+        inserted
+    }
+
+    fn len(&self) -> usize {
+        self.count
+    }
+
+    fn hashes(&self) -> Option<Vec<u64>> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_is_stable() {
+        assert_eq!(
+            url_hash(&url!("http://example.com/")),
+            0x3252_2fc5_fdfe_06f1
+        );
+    }
+
+    #[test]
+    fn hash_seen() {
+        let mut seen = HashSeen::new();
+
+        assert!(seen.insert(&url!("http://example.com/a.html")));
+        assert!(seen.insert(&url!("http://example.com/b.html")));
+        assert!(!seen.insert(&url!("http://example.com/a.html")));
+        assert_eq!(seen.len(), 2);
+    }
+
+    #[test]
+    fn bloom_seen() {
+        let mut seen = BloomSeen::new(1000, 0.01);
+
+        let urls = (0..1000)
+            .map(|i| Url::parse(&format!("http://example.com/{}.html", i)).unwrap())
+            .collect::<Vec<_>>();
+
+        for url in &urls {
+            seen.insert(url);
+        }
+
+        // no false negatives
+        assert!(urls.iter().all(|url| !seen.insert(url)));
+
+        let false_positives = (1000..2000)
+            .map(|i| Url::parse(&format!("http://example.com/{}.html", i)).unwrap())
+            .filter(|url| !seen.clone().insert(url))
+            .count();
+
+        assert!(false_positives < 50, "{} false positives", false_positives);
+    }
+}
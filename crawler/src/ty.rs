@@ -3,6 +3,7 @@
 use crate::error::*;
 use futures::Future;
 use hashbrown::HashSet;
+use reqwest::IntoUrl;
 use std::sync::Arc;
 use url::Url;
 
@@ -10,22 +11,119 @@ use url::Url;
 pub type Urls = HashSet<Url>;
 pub(crate) type Token = Arc<()>;
 
+/// HTTP headers as (name, value) pairs, in the order of appearance
+pub type Headers = Vec<(String, String)>;
+
 /// An opaque type that can be converted to &str for parsing
 pub type FetchBuffer = Box<dyn AsStr + Send>;
 
 /// A document fetcher type, allowing for pluggable custom fetcher implementations
 pub type Fetcher =
     fn(url: Url) -> Box<dyn Future<Item = Option<(Url, FetchBuffer)>, Error = Error> + Send>;
+/// A conditional document fetcher type, sending the validators of the previously fetched version
+pub type ConditionalFetcher = fn(
+    url: Url,
+    validators: Validators,
+) -> Box<dyn Future<Item = Option<Fetched>, Error = Error> + Send>;
 /// A document parser type, allowing for pluggable custom parser implementations
 pub type Parser = for<'a> fn(base: Url, html: &'a str) -> Result<Urls>;
+/// A url scoring function, allowing for custom crawling order; higher scores are crawled first
+pub type Scorer = for<'a> fn(url: &'a Url, depth: usize) -> f64;
 /// A url parser to be used by the selected Parser
 pub type UrlParser =
     for<'a, 'b> fn(base: &'a Url, target: &'b str) -> std::result::Result<Url, UrlParseError>;
 
+/// A starting url of a crawl
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Seed {
+    /// the url to start crawling from
+    pub url: Url,
+    /// true if the urls sharing the origin of the seed are part of the crawl scope
+    ///
+    /// A seed outside of the scope is crawled by itself, and only the links leading
+    /// into the scope are followed.
+    pub scope: bool,
+}
+
+impl Seed {
+    /// Create new Seed
+    pub fn new(url: impl IntoUrl, scope: bool) -> Result<Self> {
+        Ok(Self {
+            url: url.into_url()?,
+            scope,
+        })
+    }
+}
+
+/// The readable text of a html document, see [parse_text](../prelude/fn.parse_text.html)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Text {
+    /// contents of the `<title>` element
+    pub title: Option<String>,
+    /// contents of the `<h1>`-`<h6>` elements, in the document order
+    pub headings: Vec<String>,
+    /// text of the `<body>`, without scripts and styles
+    pub body: String,
+}
+
+/// Cache validators of a fetched document, allowing for conditional requests
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Validators {
+    /// value of the `ETag` header
+    pub etag: Option<String>,
+    /// value of the `Last-Modified` header
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    /// Return true if there is nothing to validate the document with
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+
+    /// Return the conditional request headers sending the validators
+    pub fn to_headers(&self) -> Headers {
+        let etag = self
+            .etag
+            .iter()
+            .map(|etag| ("If-None-Match".to_owned(), etag.clone()));
+        let last_modified = self
+            .last_modified
+            .iter()
+            .map(|date| ("If-Modified-Since".to_owned(), date.clone()));
+
+        etag.chain(last_modified).collect()
+    }
+}
+
+/// A result of a ConditionalFetcher
+pub enum Fetched {
+    /// a retrieved document
+    Document {
+        /// the url the document was retrieved from, after redirects
+        url: Url,
+        /// the document content
+        buffer: FetchBuffer,
+        /// HTTP status code of the response
+        status: u16,
+        /// validators of the document
+        validators: Validators,
+        /// response headers
+        headers: Headers,
+    },
+    /// the document wasn't modified since it was fetched with the sent validators
+    NotModified,
+}
+
 /// Convert given type to &str
 pub trait AsStr {
     /// Return type's value as a string slice
     fn as_str(&self) -> &str;
+
+    /// Return type's value as received, before any conversion to a string
+    fn as_bytes(&self) -> &[u8] {
+        self.as_str().as_bytes()
+    }
 }
 
 impl AsStr for &'static str {
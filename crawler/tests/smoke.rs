@@ -4,6 +4,9 @@ type Result<T> = std::result::Result<T, Error>;
 
 use crawler::prelude::*;
 use crawler::ty::FetchBuffer;
+use crawler::{
+    BloomSeen, DiskFrontier, Fetched, Priority, PriorityFrontier, Robots, Seed, Validators,
+};
 use futures::lazy;
 use hashbrown::HashMap;
 
@@ -69,14 +72,28 @@ static PAGE4: &str = r#""<!doctype html>
 </html>
 "#;
 
+static LINKS: &str = r#""<!doctype html>
+<html>
+    <head>
+        <title>Parse test - links</title>
+    </head>
+    <body>
+        Outside of the scope: <a href="/other.html">other</a>
+        Inside of the scope: <a href="https://example.com/some/path/second.html">second</a>
+    </body>
+</html>
+"#;
+
 lazy_static! {
     static ref PAGES: HashMap<&'static str, &'static str> = {
-        let mut map = HashMap::with_capacity(5);
+        let mut map = HashMap::with_capacity(7);
         map.insert("https://example.com/index.html", PAGE0);
         map.insert("https://example.com/weird/path/first.html", PAGE1);
         map.insert("https://example.com/some/path/second.html", PAGE2);
         map.insert("https://example.com/three.html", PAGE3);
         map.insert("https://example.com/some/path/some/path/fourth.html", PAGE4);
+        map.insert("https://example.org/links.html", LINKS);
+        map.insert("https://example.org/other.html", PAGE4);
 
         map
     };
@@ -103,6 +120,35 @@ pub fn fetch(url: Url) -> Box<dyn Future<Item = Option<(Url, FetchBuffer)>, Erro
     }))
 }
 
+/// Fetch the document, unless the sent ETag matches its url
+pub fn fetch_conditional(
+    url: Url,
+    validators: Validators,
+) -> Box<dyn Future<Item = Option<Fetched>, Error = Error> + Send> {
+    if validators.etag.as_ref() == Some(&url.to_string()) {
+        return Box::new(future::ok(Some(Fetched::NotModified)));
+    }
+
+    let etag = url.to_string();
+
+    Box::new(fetch(url).map(move |fetched| {
+        fetched.map(|(url, buffer)| {
+            let validators = Validators {
+                etag: Some(etag),
+                last_modified: None,
+            };
+
+            Fetched::Document {
+                url,
+                buffer,
+                status: 200,
+                validators,
+                headers: vec![("content-type".to_owned(), "text/html".to_owned())],
+            }
+        })
+    }))
+}
+
 fn tokio_run<F>(f: F) -> std::result::Result<F::Item, F::Error>
 where
     F: IntoFuture,
@@ -165,3 +211,544 @@ fn smoke() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn cancelled() -> Result<()> {
+    let crawler = Crawler::new("https://example.com/index.html", fetch, |base, html| {
+        parse(base, html, parse_url)
+    })?;
+
+    crawler.control().cancel();
+
+    let (sink, stream) = crawler.split();
+
+    let fut = stream
+        .buffer_unordered(5)
+        .forward(sink)
+        .and_then(|(stream, sink)| {
+            let crawler = stream.into_inner().reunite(sink)?;
+
+            Ok(crawler.into_result())
+        });
+
+    let result = tokio_run(fut)?;
+
+    assert_eq!(result, urls! {});
+
+    Ok(())
+}
+
+#[test]
+fn page_limit() -> Result<()> {
+    let crawler = Crawler::new("https://example.com/index.html", fetch, |base, html| {
+        parse(base, html, parse_url)
+    })?
+    .with_page_limit(2);
+
+    let (sink, stream) = crawler.split();
+
+    let fut = stream
+        .buffer_unordered(5)
+        .forward(sink)
+        .and_then(|(stream, sink)| {
+            let crawler = stream.into_inner().reunite(sink)?;
+
+            Ok(crawler.into_result())
+        });
+
+    let result = tokio_run(fut)?;
+
+    let expected = urls! {
+        "https://example.com/index.html",
+        "https://example.com/weird/path/first.html",
+    };
+
+    assert_eq!(result, expected);
+
+    Ok(())
+}
+
+#[test]
+fn max_depth() -> Result<()> {
+    let crawler = Crawler::new("https://example.com/index.html", fetch, |base, html| {
+        parse(base, html, parse_url)
+    })?
+    .with_max_depth(2);
+
+    let (sink, stream) = crawler.split();
+
+    let fut = stream
+        .buffer_unordered(5)
+        .forward(sink)
+        .and_then(|(stream, sink)| {
+            let crawler = stream.into_inner().reunite(sink)?;
+
+            Ok(crawler.into_result())
+        });
+
+    let result = tokio_run(fut)?;
+
+    let expected = urls! {
+        "https://example.com/index.html",
+        "https://example.com/weird/path/first.html",
+        "https://example.com/three.html",
+        "https://example.com/some/path/second.html",
+    };
+
+    assert_eq!(result, expected);
+
+    Ok(())
+}
+
+#[test]
+fn robots() -> Result<()> {
+    let robots = Robots::parse(
+        &Url::parse("https://example.com/robots.txt")?,
+        "User-agent: *\nDisallow: /some/\nDisallow: /index.html",
+        "webcrawl",
+    );
+
+    let crawler = Crawler::new("https://example.com/index.html", fetch, |base, html| {
+        parse(base, html, parse_url)
+    })?
+    .with_robots(vec![robots]);
+
+    let (sink, stream) = crawler.split();
+
+    let fut = stream
+        .buffer_unordered(5)
+        .forward(sink)
+        .and_then(|(stream, sink)| {
+            let crawler = stream.into_inner().reunite(sink)?;
+
+            Ok(crawler.into_result())
+        });
+
+    let result = tokio_run(fut)?;
+
+    // the seed is fetched regardless of the rules
+    let expected = urls! {
+        "https://example.com/index.html",
+        "https://example.com/weird/path/first.html",
+        "https://example.com/three.html",
+    };
+
+    assert_eq!(result, expected);
+
+    Ok(())
+}
+
+#[test]
+fn progress() -> Result<()> {
+    let crawler = Crawler::new("https://example.com/index.html", fetch, |base, html| {
+        parse(base, html, parse_url)
+    })?;
+
+    let progress = crawler.progress();
+
+    assert_eq!(progress.stats().queued, 1);
+
+    let (sink, stream) = crawler.split();
+
+    let fut = stream
+        .buffer_unordered(5)
+        .forward(sink)
+        .and_then(|(stream, sink)| {
+            let crawler = stream.into_inner().reunite(sink)?;
+
+            Ok(crawler.into_result())
+        });
+
+    tokio_run(fut)?;
+
+    let stats = progress.stats();
+
+    assert_eq!(stats.queued, 0);
+    assert_eq!(stats.in_flight, 0);
+    // all pages, including the redirect
+    assert_eq!(stats.fetched, 6);
+    assert_eq!(stats.effective, 5);
+    // the missing page
+    assert_eq!(stats.errors, 1);
+    assert!(stats.bytes > 0);
+
+    Ok(())
+}
+
+#[test]
+fn subscribe() -> Result<()> {
+    let mut crawler = Crawler::new("https://example.com/index.html", fetch, |base, html| {
+        parse(base, html, parse_url)
+    })?;
+
+    let found = crawler.subscribe();
+
+    let (sink, stream) = crawler.split();
+
+    let fut = stream
+        .buffer_unordered(5)
+        .forward(sink)
+        .and_then(|(stream, sink)| {
+            let crawler = stream.into_inner().reunite(sink)?;
+
+            Ok(crawler.into_result())
+        })
+        .join(
+            found
+                .collect()
+                .map_err(|_| failure::err_msg("subscription failed")),
+        );
+
+    let (result, found) = tokio_run(fut)?;
+
+    assert_eq!(found.len(), result.len());
+    assert_eq!(found.into_iter().collect::<Urls>(), result);
+
+    Ok(())
+}
+
+#[test]
+fn resume() -> Result<()> {
+    let mut crawler = Crawler::new("https://example.com/index.html", fetch, |base, html| {
+        parse(base, html, parse_url)
+    })?;
+
+    let checkpoints = crawler.checkpoints(2);
+
+    let (sink, stream) = crawler.split();
+
+    let fut = stream
+        .buffer_unordered(5)
+        .forward(sink)
+        .and_then(|(stream, sink)| {
+            let crawler = stream.into_inner().reunite(sink)?;
+
+            Ok(crawler.into_result())
+        })
+        .join(
+            checkpoints
+                .collect()
+                .map_err(|_| failure::err_msg("subscription failed")),
+        );
+
+    let (expected, checkpoints) = tokio_run(fut)?;
+
+    assert!(!checkpoints.is_empty());
+
+    for checkpoint in checkpoints {
+        let crawler =
+            Crawler::resume(checkpoint, fetch, |base, html| parse(base, html, parse_url))?;
+
+        let (sink, stream) = crawler.split();
+
+        let fut = stream
+            .buffer_unordered(5)
+            .forward(sink)
+            .and_then(|(stream, sink)| {
+                let crawler = stream.into_inner().reunite(sink)?;
+
+                Ok(crawler.into_result())
+            });
+
+        assert_eq!(tokio_run(fut)?, expected);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn bounded_memory() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("smoke-frontier-{}", std::process::id()));
+
+    let crawler = Crawler::new("https://example.com/index.html", fetch, |base, html| {
+        parse(base, html, parse_url)
+    })?
+    .with_frontier(DiskFrontier::new(&dir, 1)?)?
+    .with_seen(BloomSeen::new(100, 0.001));
+
+    let (sink, stream) = crawler.split();
+
+    let fut = stream
+        .buffer_unordered(5)
+        .forward(sink)
+        .and_then(|(stream, sink)| {
+            let crawler = stream.into_inner().reunite(sink)?;
+
+            Ok(crawler.into_result())
+        });
+
+    let result = tokio_run(fut)?;
+
+    let expected = urls! {
+        "https://example.com/some/path/some/path/fourth.html",
+        "https://example.com/index.html",
+        "https://example.com/weird/path/first.html",
+        "https://example.com/three.html",
+        "https://example.com/some/path/second.html",
+    };
+
+    assert_eq!(result, expected);
+    assert!(!dir.exists());
+
+    Ok(())
+}
+
+#[test]
+fn resume_spilled() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("smoke-spilled-{}", std::process::id()));
+
+    let crawler = Crawler::new("https://example.com/index.html", fetch, |base, html| {
+        parse(base, html, parse_url)
+    })?
+    .with_frontier(DiskFrontier::new(&dir, 1)?.with_kept_segments())?
+    .with_page_limit(3);
+
+    let (sink, stream) = crawler.split();
+
+    let fut = stream
+        .buffer_unordered(1)
+        .forward(sink)
+        .and_then(|(stream, sink)| {
+            let mut crawler = stream.into_inner().reunite(sink)?;
+
+            crawler.checkpoint()
+        });
+
+    let checkpoint = tokio_run(fut)?;
+
+    // the queued urls stay on disk
+    assert!(!checkpoint.segments.is_empty());
+
+    let crawler = Crawler::resume_with(
+        checkpoint,
+        DiskFrontier::new(&dir, 1)?,
+        fetch,
+        |base, html| parse(base, html, parse_url),
+    )?;
+
+    let (sink, stream) = crawler.split();
+
+    let fut = stream
+        .buffer_unordered(1)
+        .forward(sink)
+        .and_then(|(stream, sink)| {
+            let crawler = stream.into_inner().reunite(sink)?;
+
+            Ok(crawler.into_result())
+        });
+
+    let expected = urls! {
+        "https://example.com/some/path/some/path/fourth.html",
+        "https://example.com/index.html",
+        "https://example.com/weird/path/first.html",
+        "https://example.com/three.html",
+        "https://example.com/some/path/second.html",
+    };
+
+    assert_eq!(tokio_run(fut)?, expected);
+    assert!(!dir.exists());
+
+    Ok(())
+}
+
+#[test]
+fn priority() -> Result<()> {
+    let mut crawler = Crawler::new("https://example.com/index.html", fetch, |base, html| {
+        parse(base, html, parse_url)
+    })?
+    .with_frontier(PriorityFrontier::new(Priority::ShortestUrl))?;
+
+    let found = crawler.subscribe();
+
+    let (sink, stream) = crawler.split();
+
+    // one request at a time, so the order is deterministic
+    let fut = stream
+        .buffer_unordered(1)
+        .forward(sink)
+        // the subscription ends once the crawler is dropped
+        .map(|_| ())
+        .join(
+            found
+                .collect()
+                .map_err(|_| failure::err_msg("subscription failed")),
+        );
+
+    let (_, found) = tokio_run(fut)?;
+
+    let found = found.iter().map(Url::as_str).collect::<Vec<_>>();
+
+    assert_eq!(
+        found,
+        [
+            "https://example.com/index.html",
+            "https://example.com/weird/path/first.html",
+            "https://example.com/three.html",
+            "https://example.com/some/path/second.html",
+            "https://example.com/some/path/some/path/fourth.html",
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn history() -> Result<()> {
+    let crawl = |pages: Vec<Page>| -> Result<_> {
+        let mut crawler = Crawler::new("https://example.com/index.html", fetch, |base, html| {
+            parse(base, html, parse_url)
+        })?
+        .with_history(fetch_conditional, pages);
+
+        let progress = crawler.progress();
+        let pages = crawler.subscribe_pages();
+        let responses = crawler.subscribe_responses();
+
+        let (sink, stream) = crawler.split();
+
+        let fut = stream
+            .buffer_unordered(5)
+            .forward(sink)
+            .and_then(|(stream, sink)| {
+                let crawler = stream.into_inner().reunite(sink)?;
+
+                Ok(crawler.into_result())
+            })
+            .join3(
+                pages
+                    .collect()
+                    .map_err(|_| failure::err_msg("subscription failed")),
+                responses
+                    .collect()
+                    .map_err(|_| failure::err_msg("subscription failed")),
+            );
+
+        let (result, pages, responses) = tokio_run(fut)?;
+
+        Ok((result, pages, responses, progress.stats()))
+    };
+
+    let (expected, pages, responses, stats) = crawl(Vec::new())?;
+
+    // including the redirect
+    assert_eq!(pages.len(), 6);
+    assert!(pages
+        .iter()
+        .all(|page| !page.unchanged && page.status == 200));
+    assert!(pages.iter().any(|page| page
+        .title
+        .as_ref()
+        .is_some_and(|title| title == "Parse test - index")));
+    assert_eq!(stats.unchanged, 0);
+
+    let depth = |url: &str| {
+        pages
+            .iter()
+            .find(|page| page.url.as_str() == url)
+            .map(|page| page.depth)
+    };
+
+    assert_eq!(depth("https://example.com/index.html"), Some(0));
+    assert_eq!(
+        depth("https://example.com/some/path/some/path/fourth.html"),
+        Some(3)
+    );
+    assert!(pages.iter().all(|page| page
+        .content_type
+        .as_ref()
+        .is_some_and(|ty| ty == "text/html")));
+
+    assert_eq!(responses.len(), 6);
+    assert!(responses
+        .iter()
+        .all(|response| response.request_headers.is_empty()
+            && response.headers.len() == 1
+            && String::from_utf8_lossy(&response.body).contains("Parse test")));
+
+    let (result, recrawled, responses, stats) = crawl(pages)?;
+
+    assert_eq!(result, expected);
+    assert_eq!(recrawled.len(), 6);
+    assert!(recrawled.iter().all(|page| page.unchanged));
+    assert_eq!(stats.unchanged, 6);
+    assert_eq!(stats.bytes, 0);
+    assert!(responses.is_empty());
+
+    Ok(())
+}
+
+/// Extract all links, regardless of their origin
+fn parse_any(base: Url, html: &str) -> Result<Urls> {
+    Ok(html
+        .split("href=\"")
+        .skip(1)
+        .filter_map(|link| base.join(link.split('"').next()?).ok())
+        .collect())
+}
+
+#[test]
+fn seeds() -> Result<()> {
+    let seeds = vec![
+        Seed::new("https://example.com/index.html", true)?,
+        Seed::new("https://example.org/links.html", false)?,
+    ];
+
+    let mut crawler =
+        Crawler::seeded(seeds, fetch, parse_any)?.with_history(fetch_conditional, None);
+
+    let pages = crawler.subscribe_pages();
+
+    let (sink, stream) = crawler.split();
+
+    let fut = stream
+        // process the documents one by one, so the attribution is deterministic
+        .buffer_unordered(1)
+        .forward(sink)
+        .and_then(|(stream, sink)| {
+            let crawler = stream.into_inner().reunite(sink)?;
+
+            Ok(crawler.into_result())
+        })
+        .join(
+            pages
+                .collect()
+                .map_err(|_| failure::err_msg("subscription failed")),
+        );
+
+    let (result, pages) = tokio_run(fut)?;
+
+    assert_eq!(
+        result,
+        urls! {
+            "https://example.com/some/path/some/path/fourth.html",
+            "https://example.com/index.html",
+            "https://example.com/weird/path/first.html",
+            "https://example.com/three.html",
+            "https://example.com/some/path/second.html",
+            "https://example.org/links.html",
+        }
+    );
+
+    let seed = |url: &str| {
+        pages
+            .iter()
+            .find(|page| page.url.as_str() == url)
+            .and_then(|page| page.seed.as_ref())
+            .map(Url::as_str)
+    };
+
+    assert_eq!(
+        seed("https://example.com/three.html"),
+        Some("https://example.com/index.html")
+    );
+    assert_eq!(
+        seed("https://example.com/some/path/second.html"),
+        Some("https://example.org/links.html")
+    );
+    assert_eq!(
+        seed("https://example.com/some/path/some/path/fourth.html"),
+        Some("https://example.org/links.html")
+    );
+
+    Ok(())
+}
@@ -0,0 +1,391 @@
+//! Authentication of the API requests
+
+use crate::config::REDACTED;
+use crate::error::*;
+use hashbrown::HashMap;
+use hyper::header::{HeaderMap, AUTHORIZATION};
+use hyper::StatusCode;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Header carrying the API key, as an alternative to the bearer token
+const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// Access granted by an API key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Scope {
+    /// retrieving the crawls and their results
+    Read,
+    /// scheduling and controlling the crawls
+    Write,
+}
+
+impl Scope {
+    fn name(self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Write => "write",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "read" => Some(Scope::Read),
+            "write" => Some(Scope::Write),
+            _ => None,
+        }
+    }
+}
+
+/// Limits of the crawls scheduled by a client
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct Quota {
+    /// maximum number of the crawls scheduled per day, unlimited if None
+    pub(super) crawls_per_day: Option<usize>,
+    /// maximum number of the pages fetched by a crawl, unlimited if None
+    pub(super) pages_per_crawl: Option<usize>,
+}
+
+impl Quota {
+    /// Parse comma-separated `crawls=N` and `pages=N` limits
+    fn parse(text: &str) -> Result<Self> {
+        let mut quota = Quota::default();
+
+        for limit in text.split(',') {
+            let (name, value) = limit
+                .split_once('=')
+                .ok_or_else(|| err_msg(format!("invalid API key quota {:?}", limit)))?;
+            let value = value
+                .parse()
+                .map_err(|_| err_msg(format!("invalid API key quota {:?}", limit)))?;
+
+            match name {
+                "crawls" => quota.crawls_per_day = Some(value),
+                "pages" => quota.pages_per_crawl = Some(value),
+                _ => return Err(err_msg(format!("unknown API key quota {:?}", name))),
+            }
+        }
+
+        Ok(quota)
+    }
+
+    /// Return this quota, with the limits it doesn't set taken from given one
+    pub(super) fn or(self, other: Quota) -> Self {
+        Self {
+            crawls_per_day: self.crawls_per_day.or(other.crawls_per_day),
+            pages_per_crawl: self.pages_per_crawl.or(other.pages_per_crawl),
+        }
+    }
+}
+
+/// An accepted API key
+#[derive(Debug, Clone, Default)]
+struct Key {
+    scopes: Vec<Scope>,
+    quota: Quota,
+}
+
+/// The client making a request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct Client {
+    /// digest of the API key, or `anonymous` if the API is open
+    pub(super) id: String,
+    /// limits of the key, if it has its own
+    pub(super) quota: Quota,
+}
+
+/// The accepted API keys with their scopes and quotas
+///
+/// Only the digests of the keys are kept, so looking them up doesn't depend on their content.
+/// No keys means the authentication is disabled.
+
+#[derive(Debug, Clone, Default)]
+pub(super) struct ApiKeys {
+    keys: HashMap<String, Key>,
+}
+
+impl ApiKeys {
+    /// Parse whitespace-separated `key:scope[,scope][:quota[,quota]]` entries,
+    /// with `#` starting a comment
+    pub(super) fn parse(text: &str) -> Result<Self> {
+        let mut keys = HashMap::new();
+
+        for entry in text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .flat_map(str::split_whitespace)
+        {
+            let (key, scopes) = entry
+                .split_once(':')
+                .filter(|(key, _)| !key.is_empty())
+                .ok_or_else(|| err_msg(format!("invalid API key entry {:?}", entry)))?;
+
+            let (scopes, quota) = match scopes.split_once(':') {
+                Some((scopes, quota)) => (scopes, Quota::parse(quota)?),
+                None => (scopes, Quota::default()),
+            };
+
+            let scopes = scopes
+                .split(',')
+                .map(|scope| {
+                    Scope::from_name(scope)
+                        .ok_or_else(|| err_msg(format!("unknown API key scope {:?}", scope)))
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            keys.insert(digest(key), Key { scopes, quota });
+        }
+
+        Ok(Self { keys })
+    }
+
+    /// Add the keys of given set, merging the scopes and quotas of the repeated ones
+    pub(super) fn extend(&mut self, other: ApiKeys) {
+        for (digest, key) in other.keys {
+            let entry = self.keys.entry(digest).or_default();
+
+            entry.scopes.extend(key.scopes);
+            entry.quota = key.quota.or(entry.quota);
+        }
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Return the entries of the keys, with the keys themselves redacted
+    pub(super) fn redacted(&self) -> Vec<String> {
+        let mut keys = self.keys.iter().collect::<Vec<_>>();
+        keys.sort_by_key(|(digest, _)| *digest);
+
+        keys.into_iter()
+            .map(|(_, key)| {
+                let scopes = key
+                    .scopes
+                    .iter()
+                    .map(|scope| scope.name())
+                    .collect::<Vec<_>>()
+                    .join(",");
+
+                let quota = [
+                    key.quota.crawls_per_day.map(|n| format!("crawls={}", n)),
+                    key.quota.pages_per_crawl.map(|n| format!("pages={}", n)),
+                ]
+                .iter()
+                .flatten()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(",");
+
+                if quota.is_empty() {
+                    format!("{}:{}", REDACTED, scopes)
+                } else {
+                    format!("{}:{}:{}", REDACTED, scopes, quota)
+                }
+            })
+            .collect()
+    }
+
+    /// Check the credentials of a request requiring given scope, returning its client
+    ///
+    /// Returns `401 Unauthorized` for missing or unknown keys,
+    /// and `403 Forbidden` for the keys without the scope.
+
+    pub(super) fn authorize(
+        &self,
+        headers: &HeaderMap,
+        scope: Scope,
+    ) -> std::result::Result<Client, StatusCode> {
+        if self.is_empty() {
+            return Ok(Client {
+                id: "anonymous".to_owned(),
+                quota: Quota::default(),
+            });
+        }
+
+        let bearer = headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let api_key = headers
+            .get(API_KEY_HEADER)
+            .and_then(|value| value.to_str().ok());
+
+        let key = bearer.or(api_key).ok_or(StatusCode::UNAUTHORIZED)?;
+
+        let id = digest(key.trim());
+        let key = self.keys.get(&id).ok_or(StatusCode::UNAUTHORIZED)?;
+
+        if key.scopes.contains(&scope) {
+            Ok(Client {
+                id,
+                quota: key.quota,
+            })
+        } else {
+            Err(StatusCode::FORBIDDEN)
+        }
+    }
+}
+
+/// Return hex-encoded SHA-256 digest of given key
+fn digest(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+
+    const KEYS: &str = "
+        # dashboards
+        reader:read
+        writer:read,write   partner:read,write:crawls=20,pages=10000
+    ";
+
+    fn headers(name: &'static str, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    fn bearer(key: &str) -> HeaderMap {
+        headers("authorization", &format!("Bearer {}", key))
+    }
+
+    #[test]
+    fn parse() {
+        let keys = ApiKeys::parse(KEYS).unwrap();
+
+        assert_eq!(keys.keys.len(), 3);
+        assert_eq!(keys.keys[&digest("reader")].scopes, vec![Scope::Read]);
+        assert_eq!(
+            keys.keys[&digest("writer")].scopes,
+            vec![Scope::Read, Scope::Write]
+        );
+        assert_eq!(keys.keys[&digest("writer")].quota, Quota::default());
+        assert_eq!(
+            keys.keys[&digest("partner")].quota,
+            Quota {
+                crawls_per_day: Some(20),
+                pages_per_crawl: Some(10000),
+            }
+        );
+
+        assert!(ApiKeys::parse("").unwrap().is_empty());
+        assert!(ApiKeys::parse("# no keys\n").unwrap().is_empty());
+
+        // only the scopes and the quotas are shown
+        let mut redacted = keys.redacted();
+        redacted.sort();
+        assert_eq!(
+            redacted,
+            vec![
+                format!("{}:read", REDACTED),
+                format!("{}:read,write", REDACTED),
+                format!("{}:read,write:crawls=20,pages=10000", REDACTED),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        for invalid in &[
+            "key",
+            ":read",
+            "key:",
+            "key:admin",
+            "key:read,",
+            "key:Read",
+            "key:read:crawls",
+            "key:read:crawls=x",
+            "key:read:crawls=-1",
+            "key:read:requests=5",
+            "key:read:crawls=1,",
+        ] {
+            assert!(
+                ApiKeys::parse(invalid).is_err(),
+                "{:?} is accepted",
+                invalid
+            );
+        }
+
+        let error = ApiKeys::parse("key:admin").unwrap_err();
+        assert!(error.to_string().contains("unknown API key scope"));
+    }
+
+    #[test]
+    fn authorize() {
+        let keys = ApiKeys::parse(KEYS).unwrap();
+
+        // missing or unknown keys
+        for headers in &[
+            HeaderMap::new(),
+            bearer("nobody"),
+            bearer(""),
+            headers("authorization", "Basic cmVhZGVyOg=="),
+            headers("authorization", "reader"),
+            headers("x-api-key", "READER"),
+        ] {
+            assert_eq!(
+                keys.authorize(headers, Scope::Read).unwrap_err(),
+                StatusCode::UNAUTHORIZED
+            );
+        }
+
+        // read-only keys on the write routes
+        assert_eq!(
+            keys.authorize(&bearer("reader"), Scope::Write).unwrap_err(),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            keys.authorize(&headers("x-api-key", "reader"), Scope::Write)
+                .unwrap_err(),
+            StatusCode::FORBIDDEN
+        );
+
+        let client = keys.authorize(&bearer("reader"), Scope::Read).unwrap();
+        assert_eq!(client.id, digest("reader"));
+        assert_eq!(client.quota, Quota::default());
+
+        let client = keys
+            .authorize(&headers("x-api-key", " partner "), Scope::Write)
+            .unwrap();
+        assert_eq!(client.id, digest("partner"));
+        assert_eq!(client.quota.pages_per_crawl, Some(10000));
+
+        // without keys, the API is open
+        let client = ApiKeys::default()
+            .authorize(&HeaderMap::new(), Scope::Write)
+            .unwrap();
+        assert_eq!(client.id, "anonymous");
+    }
+
+    #[test]
+    fn digests() {
+        // only the SHA-256 digests of the keys are kept, and identify the clients
+        assert_eq!(
+            digest("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        let keys = ApiKeys::parse("secret:read").unwrap();
+        assert!(!keys.keys.contains_key("secret"));
+        assert!(keys.keys.contains_key(&digest("secret")));
+
+        // a repeated key merges its scopes and quotas
+        let mut keys = ApiKeys::parse("secret:read:crawls=1,pages=5").unwrap();
+        keys.extend(ApiKeys::parse("secret:write:crawls=2 other:read").unwrap());
+
+        let key = &keys.keys[&digest("secret")];
+        assert_eq!(key.scopes, vec![Scope::Read, Scope::Write]);
+        assert_eq!(
+            key.quota,
+            Quota {
+                crawls_per_day: Some(2),
+                pages_per_crawl: Some(5),
+            }
+        );
+        assert_eq!(keys.keys.len(), 2);
+    }
+}
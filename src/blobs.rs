@@ -0,0 +1,73 @@
+//! Content-addressed storage of the fetched documents
+
+use crate::error::*;
+use crate::util::persist;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// zstd compression level, 0 selects the library default
+const COMPRESSION_LEVEL: i32 = 0;
+
+/// A store of zstd-compressed blobs, named after the SHA-256 digest of their content
+///
+/// Identical documents are stored only once, regardless of the crawl or url they come from.
+
+#[derive(Debug)]
+pub(super) struct BlobStore {
+    dir: PathBuf,
+}
+
+impl BlobStore {
+    pub(super) fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self { dir })
+    }
+
+    /// Store given content, unless it's already stored, and return its hex-encoded digest
+    pub(super) fn put(&self, content: &[u8]) -> Result<String> {
+        let digest = hex::encode(Sha256::digest(content));
+        let path = self.path(&digest);
+
+        if path.exists() {
+            return Ok(digest);
+        }
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+
+        // write to a temporary file first, so a crash won't leave a truncated blob
+        let mut file = File::create(&tmp)?;
+        file.write_all(&zstd::encode_all(content, COMPRESSION_LEVEL)?)?;
+        persist(file, tmp.as_ref(), &path)?;
+
+        Ok(digest)
+    }
+
+    /// Return the content of given digest, if it's stored
+    pub(super) fn get(&self, digest: &str) -> Result<Option<Vec<u8>>> {
+        // the digest becomes a file name, so nothing but hex is allowed
+        if digest.len() < 2 || !digest.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Ok(None);
+        }
+
+        match fs::read(self.path(digest)) {
+            Ok(compressed) => Ok(Some(zstd::decode_all(compressed.as_slice())?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Return the file of given digest, fanned out into subdirectories by its first byte
+    fn path(&self, digest: &str) -> PathBuf {
+        self.dir.join(&digest[..2]).join(format!("{}.zst", digest))
+    }
+}
@@ -1,15 +1,125 @@
-use clap::{app_from_crate, crate_authors, crate_description, crate_name, crate_version, App, Arg};
+use clap::{
+    app_from_crate, crate_authors, crate_description, crate_name, crate_version, App, AppSettings,
+    Arg, SubCommand,
+};
+use crawler::Cidr;
 use std::net::ToSocketAddrs;
 
 pub(super) fn setup_cli<'a, 'b>() -> App<'a, 'b> {
-    const DEFAULT_SERVER_BIND: &str = "localhost:8000";
-
-    app_from_crate!().arg(
+    app_from_crate!()
+    .subcommand(
+        SubCommand::with_name("config")
+            .about("Inspects the configuration")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                SubCommand::with_name("check")
+                    .about("Validates the configuration and prints the effective one"),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("crawl")
+            .about("Crawls the given urls without starting the server, writing the results to stdout")
+            .arg(
+                Arg::with_name("url")
+                    .help("Url to start crawling from; the other urls of its site are crawled too")
+                    .multiple(true)
+                    .required_unless("seed"),
+            )
+            .arg(
+                Arg::with_name("seed")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Url to start crawling from, following only its links leading to the sites of the other urls")
+                    .long("seed"),
+            )
+            .arg(
+                Arg::with_name("throttle")
+                    .takes_value(true)
+                    .help("Maximum number of concurrent requests, capped by --max-throttle [default: 10]")
+                    .validator(|s| {
+                        s.parse::<usize>()
+                            .ok()
+                            .filter(|throttle| *throttle > 0)
+                            .map(|_| ())
+                            .ok_or_else(|| "invalid number".to_owned())
+                    })
+                    .short("t")
+                    .long("throttle"),
+            )
+            .arg(
+                Arg::with_name("depth")
+                    .takes_value(true)
+                    .help("Maximum number of links followed from the starting urls")
+                    .validator(|s| {
+                        s.parse::<usize>()
+                            .map(|_| ())
+                            .map_err(|_| "invalid number".to_owned())
+                    })
+                    .long("depth"),
+            )
+            .arg(
+                Arg::with_name("max-pages")
+                    .takes_value(true)
+                    .help("Maximum number of fetched pages")
+                    .validator(|s| {
+                        s.parse::<usize>()
+                            .map(|_| ())
+                            .map_err(|_| "invalid number".to_owned())
+                    })
+                    .long("max-pages"),
+            )
+            .arg(
+                Arg::with_name("priority")
+                    .takes_value(true)
+                    .help("Crawling order [default: fifo]")
+                    .possible_values(&["fifo", "bfs", "dfs", "shortest", "sitemap"])
+                    .long("priority"),
+            )
+            .arg(
+                Arg::with_name("robots")
+                    .help("Skips the urls disallowed by the robots.txt of their sites")
+                    .long("robots"),
+            )
+            .arg(
+                Arg::with_name("format")
+                    .takes_value(true)
+                    .help("Format of the results")
+                    .possible_values(&["json", "csv", "ndjson"])
+                    .default_value("json")
+                    .short("f")
+                    .long("format"),
+            )
+            .arg(
+                Arg::with_name("output")
+                    .takes_value(true)
+                    .help("File to write the results to instead of stdout")
+                    .short("o")
+                    .long("output"),
+            )
+            .arg(
+                Arg::with_name("quiet")
+                    .help("Doesn't print the progress to stderr")
+                    .short("q")
+                    .long("quiet"),
+            ),
+    )
+    .arg(
+        Arg::with_name("config")
+            .takes_value(true)
+            .help("TOML configuration file; the command line and the environment override it")
+            .required(false)
+            .global(true)
+            .env("WEBCRAWL_CONFIG")
+            .short("c")
+            .long("config"),
+    )
+    .arg(
         Arg::with_name("address")
             .takes_value(true)
-            .help("Address to bind to (e.g. 'localhost:8888')")
+            .help("Address to bind to (e.g. 'localhost:8888') [default: localhost:8000]")
             .required(false)
-            .default_value(DEFAULT_SERVER_BIND)
+            .env("WEBCRAWL_ADDRESS")
             .validator(|s| {
                 s.to_socket_addrs()
                     .map(|_| ())
@@ -18,4 +128,236 @@ pub(super) fn setup_cli<'a, 'b>() -> App<'a, 'b> {
             .short("a")
             .long("address"),
     )
+    .arg(
+        Arg::with_name("data-dir")
+            .takes_value(true)
+            .help("Directory for storing crawl results; kept in memory only if not set")
+            .required(false)
+            .env("WEBCRAWL_DATA_DIR")
+            .short("d")
+            .long("data-dir"),
+    )
+    .arg(
+        Arg::with_name("checkpoint-every")
+            .takes_value(true)
+            .help("Number of documents processed between the checkpoints of a running crawl [default: 100]")
+            .required(false)
+            .env("WEBCRAWL_CHECKPOINT_EVERY")
+            .validator(|s| {
+                s.parse::<usize>()
+                    .map(|_| ())
+                    .map_err(|_| "invalid number".to_owned())
+            })
+            .long("checkpoint-every"),
+    )
+    .arg(
+        Arg::with_name("frontier-capacity")
+            .takes_value(true)
+            .help("Number of queued urls kept in memory by a crawl; the rest is spilled to disk")
+            .required(false)
+            .validator(|s| {
+                s.parse::<usize>()
+                    .map(|_| ())
+                    .map_err(|_| "invalid number".to_owned())
+            })
+            .env("WEBCRAWL_FRONTIER_CAPACITY")
+            .long("frontier-capacity"),
+    )
+    .arg(
+        Arg::with_name("bloom-filter")
+            .takes_value(true)
+            .help("Expected number of urls per crawl; tracks the seen urls with a fixed-size Bloom filter")
+            .required(false)
+            .validator(|s| {
+                s.parse::<usize>()
+                    .map(|_| ())
+                    .map_err(|_| "invalid number".to_owned())
+            })
+            .env("WEBCRAWL_BLOOM_FILTER")
+            .long("bloom-filter"),
+    )
+    .arg(
+        Arg::with_name("max-throttle")
+            .takes_value(true)
+            .help("Maximum number of concurrent requests of a crawl; higher requested throttles are capped [default: 100]")
+            .required(false)
+            .env("WEBCRAWL_MAX_THROTTLE")
+            .validator(|s| {
+                s.parse::<usize>()
+                    .ok()
+                    .filter(|throttle| *throttle > 0)
+                    .map(|_| ())
+                    .ok_or_else(|| "invalid number".to_owned())
+            })
+            .long("max-throttle"),
+    )
+    .arg(
+        Arg::with_name("max-crawls")
+            .takes_value(true)
+            .help("Number of workers running the crawls; the other scheduled ones wait in a queue")
+            .required(false)
+            .validator(|s| {
+                s.parse::<usize>()
+                    .ok()
+                    .filter(|workers| *workers > 0)
+                    .map(|_| ())
+                    .ok_or_else(|| "invalid number of workers".to_owned())
+            })
+            .env("WEBCRAWL_MAX_CRAWLS")
+            .long("max-crawls")
+            .alias("workers"),
+    )
+    .arg(
+        Arg::with_name("max-connections")
+            .takes_value(true)
+            .help("Maximum number of concurrent requests of all crawls together")
+            .required(false)
+            .validator(|s| {
+                s.parse::<usize>()
+                    .map(|_| ())
+                    .map_err(|_| "invalid number".to_owned())
+            })
+            .env("WEBCRAWL_MAX_CONNECTIONS")
+            .long("max-connections"),
+    )
+    .arg(
+        Arg::with_name("crawls-per-day")
+            .takes_value(true)
+            .help("Number of crawls a client can schedule per day, unless its API key sets its own quota")
+            .required(false)
+            .validator(|s| {
+                s.parse::<usize>()
+                    .map(|_| ())
+                    .map_err(|_| "invalid number".to_owned())
+            })
+            .env("WEBCRAWL_CRAWLS_PER_DAY")
+            .long("crawls-per-day"),
+    )
+    .arg(
+        Arg::with_name("pages-per-crawl")
+            .takes_value(true)
+            .help("Number of pages a crawl can fetch, unless the API key of its client sets its own quota")
+            .required(false)
+            .validator(|s| {
+                s.parse::<usize>()
+                    .map(|_| ())
+                    .map_err(|_| "invalid number".to_owned())
+            })
+            .env("WEBCRAWL_PAGES_PER_CRAWL")
+            .long("pages-per-crawl"),
+    )
+    .arg(
+        Arg::with_name("webhook-secret")
+            .takes_value(true)
+            .help("Secret used for signing the crawl callbacks with HMAC-SHA256")
+            .required(false)
+            .env("WEBCRAWL_WEBHOOK_SECRET")
+            .hide_env_values(true)
+            .long("webhook-secret"),
+    )
+    .arg(
+        Arg::with_name("shutdown-grace")
+            .takes_value(true)
+            .help("Number of seconds the running crawls get to finish on SIGINT or SIGTERM, before they're checkpointed to be resumed on the next start [default: 30]")
+            .required(false)
+            .validator(|s| {
+                s.parse::<u64>()
+                    .map(|_| ())
+                    .map_err(|_| "invalid number".to_owned())
+            })
+            .env("WEBCRAWL_SHUTDOWN_GRACE")
+            .long("shutdown-grace"),
+    )
+    .arg(
+        Arg::with_name("api-keys")
+            .takes_value(true)
+            .help("Accepted API keys, as whitespace-separated 'key:scope[,scope][:quota[,quota]]' entries; scopes are 'read' and 'write', quotas 'crawls=N' and 'pages=N'")
+            .required(false)
+            .env("WEBCRAWL_API_KEYS")
+            .hide_env_values(true)
+            .long("api-keys"),
+    )
+    .arg(
+        Arg::with_name("api-keys-file")
+            .takes_value(true)
+            .help("File with the accepted API keys, one 'key:scope[,scope][:quota[,quota]]' entry per line")
+            .required(false)
+            .env("WEBCRAWL_API_KEYS_FILE")
+            .long("api-keys-file"),
+    )
+    .arg(
+        Arg::with_name("allow-cidr")
+            .takes_value(true)
+            .multiple(true)
+            .use_delimiter(true)
+            .number_of_values(1)
+            .help("Address blocks the crawls may fetch from even if they're private, loopback or link-local (e.g. '10.1.0.0/16')")
+            .required(false)
+            .validator(|s| s.parse::<Cidr>().map(|_| ()).map_err(|e| e.to_string()))
+            .env("WEBCRAWL_ALLOW_CIDR")
+            .long("allow-cidr"),
+    )
+    .arg(
+        Arg::with_name("deny-cidr")
+            .takes_value(true)
+            .multiple(true)
+            .use_delimiter(true)
+            .number_of_values(1)
+            .help("Address blocks the crawls may never fetch from (e.g. '203.0.113.0/24')")
+            .required(false)
+            .validator(|s| s.parse::<Cidr>().map(|_| ()).map_err(|e| e.to_string()))
+            .env("WEBCRAWL_DENY_CIDR")
+            .long("deny-cidr"),
+    )
+    .arg(
+        Arg::with_name("user-agent")
+            .takes_value(true)
+            .help("User-Agent header sent by the crawls")
+            .required(false)
+            .env("WEBCRAWL_USER_AGENT")
+            .long("user-agent"),
+    )
+    .arg(
+        Arg::with_name("timeout")
+            .takes_value(true)
+            .help("Number of seconds before a request of a crawl is given up on")
+            .required(false)
+            .validator(|s| {
+                s.parse::<u64>()
+                    .map(|_| ())
+                    .map_err(|_| "invalid number".to_owned())
+            })
+            .env("WEBCRAWL_TIMEOUT")
+            .long("timeout"),
+    )
+    .arg(
+        Arg::with_name("connect-timeout")
+            .takes_value(true)
+            .help("Number of seconds before connecting to a server is given up on")
+            .required(false)
+            .validator(|s| {
+                s.parse::<u64>()
+                    .map(|_| ())
+                    .map_err(|_| "invalid number".to_owned())
+            })
+            .env("WEBCRAWL_CONNECT_TIMEOUT")
+            .long("connect-timeout"),
+    )
+    .arg(
+        Arg::with_name("tls-certificate")
+            .takes_value(true)
+            .help("PKCS #12 archive with the certificate and the private key of the server; serves HTTPS if set")
+            .required(false)
+            .env("WEBCRAWL_TLS_CERTIFICATE")
+            .long("tls-certificate"),
+    )
+    .arg(
+        Arg::with_name("tls-password")
+            .takes_value(true)
+            .help("Password of the TLS certificate archive")
+            .required(false)
+            .env("WEBCRAWL_TLS_PASSWORD")
+            .hide_env_values(true)
+            .long("tls-password"),
+    )
 }
@@ -0,0 +1,181 @@
+//! Configuration file of the server
+
+use crate::error::*;
+use crate::storage::CrawlPriority;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Replacement of the secrets in the printed configuration
+pub(super) const REDACTED: &str = "<redacted>";
+
+/// Settings read from a TOML file, overridden by the command line and the environment
+///
+/// All settings are optional; the missing ones fall back to the defaults of the server.
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct Config {
+    /// address to bind to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) address: Option<String>,
+    /// directory for storing crawl results
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) data_dir: Option<PathBuf>,
+    /// filter of the logged messages, as in `RUST_LOG`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) log: Option<String>,
+    /// number of documents processed between the checkpoints of a running crawl
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) checkpoint_every: Option<usize>,
+    /// number of queued urls kept in memory by a crawl
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) frontier_capacity: Option<usize>,
+    /// expected number of urls per crawl
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) bloom_filter: Option<usize>,
+    /// secret for signing the crawl callbacks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) webhook_secret: Option<String>,
+    /// seconds the running crawls get to finish on shutdown
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) shutdown_grace: Option<u64>,
+    pub(super) fetch: FetchConfig,
+    pub(super) limits: LimitsConfig,
+    pub(super) network: NetworkConfig,
+    pub(super) auth: AuthConfig,
+    pub(super) tls: TlsConfig,
+    /// defaults of the requested crawls
+    pub(super) crawl: CrawlDefaults,
+}
+
+/// Options of the requests sent by the crawls
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct FetchConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) user_agent: Option<String>,
+    /// seconds before giving up on a request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) timeout: Option<u64>,
+    /// seconds before giving up on connecting to a server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) connect_timeout: Option<u64>,
+}
+
+/// Limits of the crawls and their clients
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct LimitsConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) max_throttle: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) max_crawls: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) max_connections: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) crawls_per_day: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) pages_per_crawl: Option<usize>,
+}
+
+/// Address blocks the crawls may or may not fetch from
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct NetworkConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) allow_cidr: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) deny_cidr: Option<Vec<String>>,
+}
+
+/// API keys accepted by the server
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct AuthConfig {
+    /// `key:scope[,scope][:quota[,quota]]` entries
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) api_keys: Option<Vec<String>>,
+    /// file with one entry per line
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) api_keys_file: Option<PathBuf>,
+}
+
+/// Certificate of the HTTPS server
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct TlsConfig {
+    /// PKCS #12 archive with the certificate chain and the private key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) certificate: Option<PathBuf>,
+    /// password of the archive
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) password: Option<String>,
+}
+
+/// Values of the crawl request fields the clients leave out
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct CrawlDefaults {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) throttle: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) priority: Option<CrawlPriority>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) warc: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) index: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) snapshots: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) queue_priority: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) depth: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) robots: Option<bool>,
+}
+
+impl CrawlDefaults {
+    /// Fill in the fields missing in given crawl request
+    ///
+    /// Anything but a JSON object is returned as it is.
+
+    pub(super) fn apply(&self, mut request: Value) -> Value {
+        if let (Some(fields), Ok(Value::Object(defaults))) =
+            (request.as_object_mut(), serde_json::to_value(self))
+        {
+            for (name, value) in defaults {
+                fields.entry(name).or_insert(value);
+            }
+        }
+
+        request
+    }
+}
+
+impl Config {
+    /// Read the configuration from given file
+    pub(super) fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|e| {
+            err_msg(format!(
+                "failed to read configuration from {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+
+        toml::from_str(&text).map_err(|e| {
+            err_msg(format!(
+                "invalid configuration in {}: {}",
+                path.display(),
+                e
+            ))
+        })
+    }
+
+    /// Return the configuration as TOML
+    pub(super) fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+}
@@ -0,0 +1,293 @@
+//! Crawls run from the command line, without the server
+
+use crate::error::*;
+use crate::export::{csv_header, Format, Row};
+use crate::settings::Settings;
+use crate::storage::CrawlPriority;
+use crate::{fetch_robots, limits, parse_links, prioritize};
+use clap::{value_t, ArgMatches};
+use crawler::prelude::*;
+use crawler::{NetworkPolicy, Page, PriorityFrontier, Robots, Seed, Stats};
+use futures::future::Either;
+use hashbrown::HashSet;
+use std::fs;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::prelude::*;
+use tokio::runtime::Runtime;
+use tokio::timer::Interval;
+use url::Url;
+
+/// Number of concurrent requests, unless requested or configured otherwise
+const DEFAULT_THROTTLE: usize = 10;
+
+/// Options of a crawl run from the command line
+#[derive(Debug)]
+pub(super) struct Options {
+    seeds: Vec<Seed>,
+    throttle: usize,
+    priority: CrawlPriority,
+    /// maximum depth of the followed links, unlimited if None
+    max_depth: Option<usize>,
+    /// maximum number of the fetched pages, unlimited if None
+    max_pages: Option<usize>,
+    /// whether the robots.txt rules of the crawled sites are obeyed
+    robots: bool,
+    format: Format,
+    /// file the results are written to, stdout if None
+    output: Option<PathBuf>,
+    /// whether the progress is left out of stderr
+    quiet: bool,
+}
+
+impl Options {
+    /// Read the options from the arguments of the `crawl` subcommand
+    ///
+    /// The options left out take the crawl defaults of the settings.
+
+    pub(super) fn from_args(args: &ArgMatches, settings: &Settings) -> Result<Self> {
+        let number = |name| -> Result<Option<usize>> {
+            if args.is_present(name) {
+                Ok(Some(value_t!(args, name, usize)?))
+            } else {
+                Ok(None)
+            }
+        };
+
+        let seed = |url: &str, scope| {
+            Seed::new(url, scope).map_err(|e| err_msg(format!("invalid url {:?}: {}", url, e)))
+        };
+
+        let seeds = args
+            .values_of("url")
+            .into_iter()
+            .flatten()
+            .map(|url| seed(url, true))
+            .chain(
+                args.values_of("seed")
+                    .into_iter()
+                    .flatten()
+                    .map(|url| seed(url, false)),
+            )
+            .collect::<Result<Vec<_>>>()?;
+
+        if seeds.is_empty() {
+            return Err(err_msg("no urls to crawl"));
+        }
+
+        if let Some(policy) = NetworkPolicy::installed() {
+            if let Some(seed) = seeds.iter().find(|seed| policy.refuses_address(&seed.url)) {
+                return Err(err_msg(format!(
+                    "refusing to crawl {}, its address isn't allowed",
+                    seed.url
+                )));
+            }
+        }
+
+        let throttle = number("throttle")?
+            .or(settings.crawl_defaults.throttle)
+            .unwrap_or(DEFAULT_THROTTLE)
+            .clamp(1, settings.max_throttle);
+
+        let priority = match args.value_of("priority") {
+            Some(name) => CrawlPriority::from_name(name)
+                .ok_or_else(|| err_msg(format!("unknown priority {:?}", name)))?,
+            None => settings.crawl_defaults.priority.unwrap_or_default(),
+        };
+
+        let format = match args.value_of("format") {
+            Some(name) => Format::from_name(name)
+                .filter(|format| [Format::Json, Format::Csv, Format::Ndjson].contains(format))
+                .ok_or_else(|| err_msg(format!("unsupported format {:?}", name)))?,
+            None => Format::Json,
+        };
+
+        Ok(Self {
+            seeds,
+            throttle,
+            priority,
+            max_depth: number("depth")?.or(settings.crawl_defaults.depth),
+            max_pages: number("max-pages")?,
+            robots: args.is_present("robots") || settings.crawl_defaults.robots.unwrap_or(false),
+            format,
+            output: args.value_of("output").map(PathBuf::from),
+            quiet: args.is_present("quiet"),
+        })
+    }
+}
+
+/// Crawl the requested sites, writing the results as the pages come
+pub(super) fn run(options: Options, settings: &Settings) -> Result<()> {
+    let output: Box<dyn Write + Send> = match &options.output {
+        Some(path) => Box::new(
+            fs::File::create(path)
+                .map_err(|e| err_msg(format!("failed to create {}: {}", path.display(), e)))?,
+        ),
+        None => Box::new(io::stdout()),
+    };
+
+    let mut output = BufWriter::new(output);
+
+    if options.format == Format::Csv {
+        output.write_all(csv_header().as_bytes())?;
+    }
+
+    let start = options.seeds[0].url.to_string();
+
+    let crawler = Crawler::seeded(options.seeds.clone(), limits::fetch, parse_links)?
+        // the conditional fetcher records the status and the content type of the pages
+        .with_history(limits::fetch_conditional, Vec::new());
+
+    let crawler = match options.priority.priority() {
+        Some(priority) => crawler.with_frontier(PriorityFrontier::new(priority))?,
+        None => crawler,
+    };
+
+    let crawler = match options.max_pages {
+        Some(limit) => crawler.with_page_limit(limit),
+        None => crawler,
+    };
+
+    let crawler = match options.max_depth {
+        Some(depth) => crawler.with_max_depth(depth),
+        None => crawler,
+    };
+
+    let progress = crawler.progress();
+
+    let robots: Box<dyn Future<Item = Vec<Robots>, Error = Error> + Send> = if options.robots {
+        Box::new(fetch_robots(&options.seeds, settings))
+    } else {
+        Box::new(future::ok(Vec::new()))
+    };
+
+    let throttle = options.throttle;
+    let priority = options.priority;
+    let format = options.format;
+    let quiet = options.quiet;
+
+    let crawl = robots
+        .and_then(move |robots| prioritize(crawler.with_robots(robots), priority, &start, None))
+        .and_then(move |mut crawler| {
+            // the subscription ends when the crawler is dropped
+            let written = crawler
+                .subscribe_pages()
+                .map_err(|_| err_msg("page subscription failed"))
+                .fold(
+                    (output, HashSet::new()),
+                    move |(mut output, mut written), page| -> Result<_> {
+                        // a redirect leads to an already written page
+                        if written.insert(page.effective.clone()) {
+                            let row = row(&page);
+
+                            match format {
+                                Format::Csv => output.write_all(row.to_csv().as_bytes())?,
+                                Format::Ndjson => output.write_all(row.to_ndjson().as_bytes())?,
+                                _ => (),
+                            }
+                        }
+
+                        Ok((output, written))
+                    },
+                );
+
+            let (sink, stream) = crawler.split();
+
+            stream
+                .buffer_unordered(throttle)
+                .forward(sink)
+                .and_then(|(stream, sink)| {
+                    let crawler = stream.into_inner().reunite(sink)?;
+
+                    Ok(crawler.into_result())
+                })
+                .join(written)
+        });
+
+    let crawl: Box<dyn Future<Item = _, Error = Error> + Send> = if quiet {
+        Box::new(crawl)
+    } else {
+        let terminal = io::stderr().is_terminal();
+        let progress = progress.clone();
+
+        let ticker = Interval::new_interval(Duration::from_secs(1))
+            .map_err(Error::from)
+            .for_each(move |_| {
+                report(&progress.stats(), terminal);
+                Ok(())
+            });
+
+        Box::new(crawl.select2(ticker).then(|result| match result {
+            Ok(Either::A((result, _))) => Ok(result),
+            Err(Either::A((e, _))) | Err(Either::B((e, _))) => Err(e),
+            // the ticker never ends on its own
+            Ok(Either::B(_)) => Err(err_msg("progress timer stopped")),
+        }))
+    };
+
+    let (urls, (mut output, _)) = Runtime::new()?.block_on(crawl)?;
+
+    if format == Format::Json {
+        let mut urls = urls.iter().map(Url::as_str).collect::<Vec<_>>();
+        urls.sort();
+
+        serde_json::to_writer(&mut output, &urls)?;
+        output.write_all(b"\n")?;
+    }
+
+    output.flush()?;
+
+    if !quiet {
+        let stats = progress.stats();
+
+        // end the overwritten progress line
+        if io::stderr().is_terminal() {
+            eprintln!();
+        }
+
+        eprintln!(
+            "Crawled {} urls in {:.1}s: {} fetched, {} errors, {} bytes",
+            stats.effective,
+            stats.elapsed.as_secs_f64(),
+            stats.fetched,
+            stats.errors,
+            stats.bytes
+        );
+    }
+
+    Ok(())
+}
+
+/// Print the progress of the crawl to stderr, overwriting the previous line on a terminal
+fn report(stats: &Stats, terminal: bool) {
+    let line = format!(
+        "{} urls, {} fetched, {} queued, {} in flight, {} errors, {:.1} pages/s",
+        stats.effective,
+        stats.fetched,
+        stats.queued,
+        stats.in_flight,
+        stats.errors,
+        stats.pages_per_sec
+    );
+
+    if terminal {
+        eprint!("\r{}\x1b[K", line);
+    } else {
+        eprintln!("{}", line);
+    }
+}
+
+/// Return the exported row of a crawled page
+fn row(page: &Page) -> Row {
+    Row {
+        url: page.effective.to_string(),
+        // 0 if unknown
+        status: Some(page.status).filter(|status| *status != 0),
+        content_type: page.content_type.clone(),
+        depth: Some(page.depth),
+        title: page.title.clone(),
+        seed: page.seed.as_ref().map(Url::to_string),
+        links: Some(page.links.len()),
+    }
+}
@@ -0,0 +1,327 @@
+//! Cron expressions of the scheduled crawls
+
+use crate::error::*;
+use crate::util::civil_date;
+use std::fmt;
+use std::str::FromStr;
+
+/// How far ahead the next run is looked for, enough for e.g. `0 0 29 2 *` to match
+const LOOKAHEAD_DAYS: u64 = 8 * 366;
+
+const MONTHS: &[&str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+/// Number of the days of each month, in a leap year
+const MONTH_DAYS: &[u64] = &[31, 29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+
+/// A standard 5-field cron expression, `minute hour day-of-month month day-of-week`, in UTC
+///
+/// The fields accept `*`, numbers, `a-b` ranges, `/step` suffixes and `,`-separated lists,
+/// the months and weekdays their three-letter names as well. Sunday is either `0` or `7`.
+/// If both day fields are restricted, a day matching either of them is matched,
+/// like in the traditional cron.
+/// The `@yearly`, `@monthly`, `@weekly`, `@daily` and `@hourly` shortcuts are supported too.
+/// The expressions which never match, like `0 0 31 2 *`, are rejected.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Cron {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// true if the day of month is restricted
+    some_days: bool,
+    /// true if the day of week is restricted
+    some_weekdays: bool,
+}
+
+impl Cron {
+    /// Return the unix timestamp of the first matching minute after given one
+    pub(super) fn next_after(&self, timestamp: u64) -> Option<u64> {
+        let first = timestamp / 60 + 1;
+        let first_day = first / 1_440;
+
+        for day in first_day..first_day + LOOKAHEAD_DAYS {
+            let (_, month, day_of_month) = civil_date(day);
+            // the epoch was a Thursday
+            let weekday = (day + 4) % 7;
+
+            let day_matches = is_set(self.days, u64::from(day_of_month));
+            let weekday_matches = is_set(self.weekdays, weekday);
+
+            let matches = if self.some_days && self.some_weekdays {
+                day_matches || weekday_matches
+            } else {
+                day_matches && weekday_matches
+            };
+
+            if !matches || !is_set(self.months, u64::from(month)) {
+                continue;
+            }
+
+            let start = if day == first_day { first % 1_440 } else { 0 };
+
+            for minute in start..1_440 {
+                if is_set(self.hours, minute / 60) && is_set(self.minutes, minute % 60) {
+                    return Some((day * 1_440 + minute) * 60);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl FromStr for Cron {
+    type Err = Error;
+
+    fn from_str(expression: &str) -> Result<Self> {
+        let expanded = match expression.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expression => expression,
+        };
+
+        let fields = expanded.split_whitespace().collect::<Vec<_>>();
+
+        if fields.len() != 5 {
+            return Err(err_msg(format!(
+                "invalid cron expression {:?}, expected 5 fields",
+                expression
+            )));
+        }
+
+        let mut weekdays = field(fields[4], 0, 7, WEEKDAYS)?;
+
+        // Sunday is both 0 and 7
+        if is_set(weekdays, 7) {
+            weekdays |= 1;
+        }
+
+        let cron = Self {
+            expression: expression.trim().to_owned(),
+            minutes: field(fields[0], 0, 59, &[])?,
+            hours: field(fields[1], 0, 23, &[])?,
+            days: field(fields[2], 1, 31, &[])?,
+            months: field(fields[3], 1, 12, MONTHS)?,
+            weekdays,
+            some_days: fields[2] != "*",
+            some_weekdays: fields[4] != "*",
+        };
+
+        // unless any weekday matches, the days must exist in the months
+        let exists = (1..=12).any(|month| {
+            is_set(cron.months, month)
+                && (1..=MONTH_DAYS[month as usize - 1]).any(|day| is_set(cron.days, day))
+        });
+
+        if cron.some_days && !cron.some_weekdays && !exists {
+            return Err(err_msg(format!(
+                "cron expression {:?} never matches",
+                expression
+            )));
+        }
+
+        Ok(cron)
+    }
+}
+
+impl fmt::Display for Cron {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+fn is_set(bits: u64, value: u64) -> bool {
+    bits & (1 << value) != 0
+}
+
+/// Parse a field of values between given bounds, with given names starting at `min`
+fn field(field: &str, min: u64, max: u64, names: &[&str]) -> Result<u64> {
+    let invalid = || err_msg(format!("invalid cron field {:?}", field));
+
+    let value = |value: &str| -> Result<u64> {
+        let value = match names
+            .iter()
+            .position(|name| value.eq_ignore_ascii_case(name))
+        {
+            Some(position) => position as u64 + min,
+            None => value.parse().map_err(|_| invalid())?,
+        };
+
+        if (min..=max).contains(&value) {
+            Ok(value)
+        } else {
+            Err(invalid())
+        }
+    };
+
+    let mut bits = 0;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u64>().map_err(|_| invalid())?),
+            None => (part, 1),
+        };
+
+        if step == 0 {
+            return Err(invalid());
+        }
+
+        let (first, last) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((first, last)) => (value(first)?, value(last)?),
+                // a single value with a step runs to the end, as in `5/15`
+                None if step > 1 => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+
+        if first > last {
+            return Err(invalid());
+        }
+
+        for value in (first..=last).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn next(expression: &str, timestamp: u64) -> u64 {
+        expression
+            .parse::<Cron>()
+            .unwrap()
+            .next_after(timestamp)
+            .unwrap()
+    }
+
+    fn values(bits: u64) -> Vec<u64> {
+        (0..64).filter(|value| is_set(bits, *value)).collect()
+    }
+
+    #[test]
+    fn fields() {
+        assert_eq!(
+            values(field("*", 1, 12, &[]).unwrap()),
+            (1..=12).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            values(field("*/15", 0, 59, &[]).unwrap()),
+            vec![0, 15, 30, 45]
+        );
+        assert_eq!(
+            values(field("5/15", 0, 59, &[]).unwrap()),
+            vec![5, 20, 35, 50]
+        );
+        assert_eq!(
+            values(field("10-20/5", 0, 59, &[]).unwrap()),
+            vec![10, 15, 20]
+        );
+        assert_eq!(
+            values(field("1,3-4,23", 0, 23, &[]).unwrap()),
+            vec![1, 3, 4, 23]
+        );
+        assert_eq!(
+            values(field("mon-fri", 0, 7, WEEKDAYS).unwrap()),
+            vec![1, 2, 3, 4, 5]
+        );
+        assert_eq!(
+            values(field("Jan,dec", 1, 12, MONTHS).unwrap()),
+            vec![1, 12]
+        );
+
+        for invalid in &["60", "5-1", "*/0", "1-", "x", "", "1,,2", "-1"] {
+            assert!(
+                field(invalid, 0, 59, &[]).is_err(),
+                "{:?} is accepted",
+                invalid
+            );
+        }
+
+        assert!(field("0", 1, 31, &[]).is_err());
+        assert!(field("sun", 1, 12, MONTHS).is_err());
+    }
+
+    #[test]
+    fn sunday() {
+        // the epoch was a Thursday, so the first Sunday midnight is 3 days later
+        for expression in &["0 0 * * 0", "0 0 * * 7", "0 0 * * sun", "@weekly"] {
+            assert_eq!(next(expression, 0), 3 * 86_400, "{}", expression);
+        }
+
+        assert_eq!(next("0 0 * * 6-7", 0), 2 * 86_400);
+    }
+
+    #[test]
+    fn days() {
+        // either the 13th or a Friday
+        assert_eq!(next("0 0 13 * fri", 0), 86_400);
+        assert_eq!(next("0 0 13 * fri", 86_400), 8 * 86_400);
+        assert_eq!(next("0 0 13 * fri", 8 * 86_400), 12 * 86_400);
+        assert_eq!(next("0 0 2 * mon", 0), 86_400);
+
+        // only a restricted field counts
+        assert_eq!(next("0 0 13 * *", 0), 12 * 86_400);
+        assert_eq!(next("0 0 * * fri", 0), 86_400);
+    }
+
+    #[test]
+    fn parse() {
+        for invalid in &["0 0 31 2 *", "0 0 30,31 2 *", "0 0 31 4,6,9,11 *"] {
+            let error = invalid.parse::<Cron>().unwrap_err();
+            assert!(error.to_string().contains("never matches"), "{}", invalid);
+        }
+
+        for invalid in &["", "0 0 * *", "0 0 * * * *", "@often", "0 24 * * *"] {
+            assert!(
+                invalid.parse::<Cron>().is_err(),
+                "{:?} is accepted",
+                invalid
+            );
+        }
+
+        // a weekday matches, or a day of some month
+        assert!("0 0 31 2 mon".parse::<Cron>().is_ok());
+        assert!("0 0 29 2 *".parse::<Cron>().is_ok());
+        assert!("0 0 31 1-2 *".parse::<Cron>().is_ok());
+
+        assert_eq!(" @daily ".parse::<Cron>().unwrap().to_string(), "@daily");
+        let daily = "@daily".parse::<Cron>().unwrap();
+        assert_eq!(daily.minutes, 1);
+        assert_eq!(daily.hours, 1);
+        assert!(!daily.some_days && !daily.some_weekdays);
+    }
+
+    #[test]
+    fn next_after() {
+        // 2024-01-31 23:59 to 2024-02-01 00:00
+        assert_eq!(next("0 0 * * *", 1_706_745_540), 1_706_745_600);
+        assert_eq!(next("* * * * *", 1_706_745_540), 1_706_745_600);
+        // 2024-01-15 to 2024-02-01
+        assert_eq!(next("0 0 1 * *", 1_705_276_800), 1_706_745_600);
+        // 2024-12-31 23:59 to 2025-01-01
+        assert_eq!(next("@yearly", 1_735_689_540), 1_735_689_600);
+
+        // 2023-03-01 to 2024-02-29, and 2024-03-01 to 2028-02-29
+        assert_eq!(next("0 0 29 2 *", 1_677_628_800), 1_709_164_800);
+        assert_eq!(next("0 0 29 2 *", 1_709_251_200), 1_835_395_200);
+
+        // strictly after, also within the same minute: 2024-02-29 12:30 to 2024-03-01 12:30
+        assert_eq!(next("30 12 * * *", 1_709_209_800), 1_709_296_200);
+        assert_eq!(next("30 12 * * *", 1_709_209_800 - 1), 1_709_209_800);
+        assert_eq!(next("*/15 * * * *", 1_709_209_800), 1_709_209_800 + 15 * 60);
+    }
+}
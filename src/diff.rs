@@ -0,0 +1,134 @@
+use crate::error::*;
+use crate::runs::{join, RunFiles};
+use crate::util::csv_field;
+use serde_derive::Serialize;
+use std::fmt::Display;
+
+/// Differences between the results of two runs of a crawl
+#[derive(Debug, Default, Serialize)]
+pub(super) struct Diff {
+    /// urls found only in the later run
+    added: Vec<String>,
+    /// urls found only in the earlier run
+    removed: Vec<String>,
+    /// changed HTTP status codes
+    status: Vec<Change<u16>>,
+    /// changed document titles
+    title: Vec<Change<Option<String>>>,
+    /// changed redirect targets
+    redirect: Vec<Change<String>>,
+}
+
+/// A change of a single page property
+#[derive(Debug, Serialize)]
+struct Change<T> {
+    url: String,
+    from: T,
+    to: T,
+}
+
+impl Diff {
+    /// Compare the results of the runs of given files
+    ///
+    /// The pages are matched by their requested urls. The status codes unknown
+    /// to either run are not compared. The files are sorted by url, so they're read only once.
+
+    pub(super) fn new(from: &RunFiles, to: &RunFiles) -> Result<Self> {
+        let mut diff = Diff::default();
+
+        join(
+            from.rows()?,
+            to.rows()?,
+            |row| &row.url,
+            |row| &row.url,
+            |before, after| match (before, after) {
+                (None, Some(after)) => diff.added.push(after.url),
+                (Some(before), None) => diff.removed.push(before.url),
+                _ => {}
+            },
+        )?;
+
+        join(
+            from.pages()?,
+            to.pages()?,
+            |page| &page.url,
+            |page| &page.url,
+            |previous, page| {
+                let (previous, page) = match (previous, page) {
+                    (Some(previous), Some(page)) => (previous, page),
+                    _ => return,
+                };
+
+                if previous.status != page.status && previous.status != 0 && page.status != 0 {
+                    diff.status.push(Change {
+                        url: page.url.clone(),
+                        from: previous.status,
+                        to: page.status,
+                    });
+                }
+
+                if previous.title != page.title {
+                    diff.title.push(Change {
+                        url: page.url.clone(),
+                        from: previous.title,
+                        to: page.title,
+                    });
+                }
+
+                if previous.effective != page.effective {
+                    diff.redirect.push(Change {
+                        url: page.url,
+                        from: previous.effective,
+                        to: page.effective,
+                    });
+                }
+            },
+        )?;
+
+        Ok(diff)
+    }
+
+    /// Render as CSV, with a `change,url,from,to` header
+    pub(super) fn to_csv(&self) -> String {
+        let mut csv = String::from("change,url,from,to\r\n");
+
+        let mut row = |change: &str, url: &str, from: &dyn Display, to: &dyn Display| {
+            csv.push_str(&format!(
+                "{},{},{},{}\r\n",
+                change,
+                csv_field(url),
+                csv_field(&from.to_string()),
+                csv_field(&to.to_string())
+            ));
+        };
+
+        for url in &self.added {
+            row("added", url, &"", &"");
+        }
+
+        for url in &self.removed {
+            row("removed", url, &"", &"");
+        }
+
+        for change in &self.status {
+            row("status", &change.url, &change.from, &change.to);
+        }
+
+        for change in &self.title {
+            let title = |title: &Option<String>| title.clone().unwrap_or_default();
+
+            row(
+                "title",
+                &change.url,
+                &title(&change.from),
+                &title(&change.to),
+            );
+        }
+
+        for change in &self.redirect {
+            row("redirect", &change.url, &change.from, &change.to);
+        }
+
+        csv
+    }
+}
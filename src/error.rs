@@ -1,3 +1,3 @@
-pub(crate) use failure::Error;
+pub(crate) use failure::{err_msg, Error};
 
 pub(crate) type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,268 @@
+//! Export formats of the crawl results
+
+use crate::util::{csv_field, find_query_param};
+use hyper::StatusCode;
+use regex::Regex;
+use serde_derive::{Deserialize, Serialize};
+use std::borrow::Cow;
+use url::Url;
+
+/// Export format of the crawl results
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Format {
+    /// a json list of urls
+    Json,
+    /// urls with the page metadata
+    Csv,
+    /// one json object per page
+    Ndjson,
+    /// one url per line
+    Text,
+    /// the archived HTTP exchanges
+    Warc,
+}
+
+impl Format {
+    /// Return the format of given `format` parameter
+    pub(super) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            "ndjson" => Some(Format::Ndjson),
+            "text" => Some(Format::Text),
+            "warc" => Some(Format::Warc),
+            _ => None,
+        }
+    }
+
+    /// Return the format matching given `Accept` header, if any
+    pub(super) fn from_accept(accept: &str) -> Option<Self> {
+        [
+            ("text/csv", Format::Csv),
+            ("application/x-ndjson", Format::Ndjson),
+            ("text/plain", Format::Text),
+            ("application/warc", Format::Warc),
+            ("application/json", Format::Json),
+        ]
+        .iter()
+        .find(|(content_type, _)| accept.contains(content_type))
+        .map(|(_, format)| *format)
+    }
+
+    pub(super) fn content_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Csv => "text/csv",
+            Format::Ndjson => "application/x-ndjson",
+            Format::Text => "text/plain",
+            Format::Warc => "application/warc",
+        }
+    }
+}
+
+/// A resulting url with the metadata of its page, if known
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct Row {
+    pub(super) url: String,
+    /// HTTP status code of the response
+    pub(super) status: Option<u16>,
+    /// value of the `Content-Type` header
+    pub(super) content_type: Option<String>,
+    /// number of links followed from the seed to the page
+    pub(super) depth: Option<usize>,
+    /// the document title
+    pub(super) title: Option<String>,
+    /// the starting url that led to the page
+    pub(super) seed: Option<String>,
+    /// number of the links found in the page
+    pub(super) links: Option<usize>,
+}
+
+impl Row {
+    /// Create a row without the page metadata
+    pub(super) fn bare(url: String) -> Self {
+        Self {
+            url,
+            status: None,
+            content_type: None,
+            depth: None,
+            title: None,
+            seed: None,
+            links: None,
+        }
+    }
+
+    /// Render as a CSV record, matching `csv_header`
+    pub(super) fn to_csv(&self) -> String {
+        let number =
+            |value: Option<usize>| value.map(|value| value.to_string()).unwrap_or_default();
+
+        format!(
+            "{},{},{},{},{},{},{}\r\n",
+            csv_field(&self.url),
+            number(self.status.map(usize::from)),
+            csv_field(self.content_type.as_deref().unwrap_or_default()),
+            number(self.depth),
+            csv_field(self.title.as_deref().unwrap_or_default()),
+            csv_field(self.seed.as_deref().unwrap_or_default()),
+            number(self.links),
+        )
+    }
+
+    /// Render as a json line
+    pub(super) fn to_ndjson(&self) -> String {
+        format!(
+            "{}\n",
+            serde_json::to_string(self).expect("failed to serialize row")
+        )
+    }
+}
+
+/// Return the header of the CSV export
+pub(super) fn csv_header() -> &'static str {
+    "url,status,content_type,depth,title,seed,links\r\n"
+}
+
+/// Criteria of the exported rows
+///
+/// The rows without the metadata don't match the criteria based on it.
+
+#[derive(Debug, Default)]
+pub(super) struct Filter {
+    /// prefix of the url path
+    prefix: Option<String>,
+    /// pattern matching the url
+    pattern: Option<Regex>,
+    status: Option<u16>,
+    /// prefix of the content type, e.g. `text/html`
+    content_type: Option<String>,
+    depth: Option<usize>,
+}
+
+impl Filter {
+    /// Parse the `prefix`, `regex`, `status`, `content_type` and `depth` query parameters
+    pub(super) fn from_query(url: &Url) -> std::result::Result<Self, StatusCode> {
+        let param = |name| find_query_param(url, name).ok().map(Cow::into_owned);
+
+        Ok(Self {
+            prefix: param("prefix"),
+            pattern: param("regex")
+                .map(|pattern| Regex::new(&pattern).map_err(|_| StatusCode::BAD_REQUEST))
+                .transpose()?,
+            status: param("status")
+                .map(|status| status.parse().map_err(|_| StatusCode::BAD_REQUEST))
+                .transpose()?,
+            content_type: param("content_type"),
+            depth: param("depth")
+                .map(|depth| depth.parse().map_err(|_| StatusCode::BAD_REQUEST))
+                .transpose()?,
+        })
+    }
+
+    /// Return true if given row matches all criteria
+    pub(super) fn matches(&self, row: &Row) -> bool {
+        let prefix = || {
+            self.prefix.as_ref().is_none_or(|prefix| {
+                Url::parse(&row.url).is_ok_and(|url| url.path().starts_with(prefix.as_str()))
+            })
+        };
+
+        let content_type = || {
+            self.content_type.as_ref().is_none_or(|expected| {
+                row.content_type
+                    .as_ref()
+                    .is_some_and(|content_type| content_type.starts_with(expected.as_str()))
+            })
+        };
+
+        self.status.is_none_or(|status| row.status == Some(status))
+            && self.depth.is_none_or(|depth| row.depth == Some(depth))
+            && self
+                .pattern
+                .as_ref()
+                .is_none_or(|pattern| pattern.is_match(&row.url))
+            && prefix()
+            && content_type()
+    }
+}
+
+/// Maximum number of the rows of a page
+const MAX_LIMIT: usize = 10_000;
+
+/// A range of the matching rows
+#[derive(Debug)]
+pub(super) struct Page {
+    pub(super) offset: usize,
+    pub(super) limit: usize,
+}
+
+impl Page {
+    /// Parse the `offset` and `limit` query parameters, with given default limit
+    ///
+    /// The limit is capped to at most 10000 rows.
+
+    pub(super) fn from_query(url: &Url, limit: usize) -> std::result::Result<Self, StatusCode> {
+        let number = |name| {
+            find_query_param(url, name)
+                .ok()
+                .map(|value| value.parse::<usize>().map_err(|_| StatusCode::BAD_REQUEST))
+                .transpose()
+        };
+
+        Ok(Self {
+            offset: number("offset")?.unwrap_or_default(),
+            limit: number("limit")?.unwrap_or(limit).min(MAX_LIMIT),
+        })
+    }
+
+    /// Return the rows of this page of given rows, with the total number of the matching rows
+    ///
+    /// The rows are consumed as they're read, failing on the first error.
+
+    pub(super) fn select<E>(
+        &self,
+        rows: impl Iterator<Item = std::result::Result<Row, E>>,
+        filter: &Filter,
+    ) -> std::result::Result<(Vec<Row>, usize), E> {
+        let mut total = 0;
+        let mut selected = Vec::new();
+
+        for row in rows {
+            let row = row?;
+
+            if !filter.matches(&row) {
+                continue;
+            }
+
+            if total >= self.offset && selected.len() < self.limit {
+                selected.push(row);
+            }
+
+            total += 1;
+        }
+
+        Ok((selected, total))
+    }
+
+    /// Return the offset of the following page, if there are more matching rows
+    pub(super) fn next(&self, total: usize) -> Option<usize> {
+        Some(self.offset + self.limit).filter(|next| *next < total)
+    }
+
+    /// Return the `Link` header pointing to the following page of given request, if any
+    pub(super) fn next_link(&self, url: &Url, total: usize) -> Option<String> {
+        let next = self.next(total)?;
+
+        let mut link = url.clone();
+        link.query_pairs_mut()
+            .clear()
+            .extend_pairs(url.query_pairs().filter(|(key, _)| key != "offset"))
+            .append_pair("offset", &next.to_string());
+
+        Some(format!(
+            "<{}?{}>; rel=\"next\"",
+            link.path(),
+            link.query().unwrap_or_default()
+        ))
+    }
+}
@@ -0,0 +1,144 @@
+//! Liveness and readiness probes of the server
+
+use crate::scheduler::Scheduler;
+use crate::shutdown;
+use crate::storage::Storage;
+use crate::{CrawlStatus, Registry};
+use hyper::{Body, Response, StatusCode};
+use log::*;
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+
+/// Version and build of the running binary
+#[derive(Debug, Serialize)]
+struct Build {
+    version: &'static str,
+    /// commit the binary was built from, if built from a git checkout
+    commit: Option<&'static str>,
+    target: &'static str,
+    profile: &'static str,
+}
+
+const BUILD: Build = Build {
+    version: env!("CARGO_PKG_VERSION"),
+    commit: option_env!("WEBCRAWL_COMMIT"),
+    target: env!("WEBCRAWL_TARGET"),
+    profile: env!("WEBCRAWL_PROFILE"),
+};
+
+/// Passed check
+const OK: &str = "ok";
+
+#[derive(Debug, Serialize)]
+struct Health {
+    /// true if all checks passed, always for the liveness probe
+    ready: bool,
+    /// failure of every failed check, or `ok`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    checks: BTreeMap<&'static str, String>,
+    build: &'static Build,
+    /// number of the started crawls that aren't done yet
+    active_crawls: usize,
+    /// number of the crawls waiting for a free worker
+    queued_crawls: usize,
+}
+
+/// Answer the liveness probe, successful as long as the server responds
+pub(super) fn liveness(registry: &Registry) -> Response<Body> {
+    let (active_crawls, queued_crawls) = crawls(registry);
+
+    respond(Health {
+        ready: true,
+        checks: BTreeMap::new(),
+        build: &BUILD,
+        active_crawls,
+        queued_crawls,
+    })
+}
+
+/// Answer the readiness probe, successful if the server can take new crawls
+///
+/// The storage has to be writable, the registry usable, and a worker free for a new crawl.
+
+pub(super) fn readiness(
+    registry: &Registry,
+    storage: &dyn Storage,
+    scheduler: &Scheduler,
+) -> Response<Body> {
+    let mut checks = BTreeMap::new();
+
+    let storage = match storage.check() {
+        Ok(()) => OK.to_owned(),
+        Err(e) => {
+            warn!("Storage check failed: {}", e);
+            e.to_string()
+        }
+    };
+
+    checks.insert("storage", storage);
+
+    let lock = if registry.is_poisoned() {
+        "poisoned lock"
+    } else {
+        OK
+    };
+
+    checks.insert("registry", lock.to_owned());
+
+    let workers = if scheduler.saturated() {
+        "all workers busy"
+    } else {
+        OK
+    };
+
+    checks.insert("workers", workers.to_owned());
+
+    let server = if shutdown::requested() {
+        "shutting down"
+    } else {
+        OK
+    };
+
+    checks.insert("server", server.to_owned());
+
+    let (active_crawls, queued_crawls) = crawls(registry);
+
+    respond(Health {
+        ready: checks.values().all(|check| check == OK),
+        checks,
+        build: &BUILD,
+        active_crawls,
+        queued_crawls,
+    })
+}
+
+/// Return the numbers of the running and the queued crawls
+fn crawls(registry: &Registry) -> (usize, usize) {
+    // the counts are still good after a panic of another request
+    let registry = registry.read().unwrap_or_else(|e| e.into_inner());
+
+    registry
+        .values()
+        .fold((0, 0), |(active, queued), crawl| match crawl.status {
+            CrawlStatus::Pending => (active + 1, queued),
+            CrawlStatus::Queued => (active, queued + 1),
+            _ => (active, queued),
+        })
+}
+
+fn respond(health: Health) -> Response<Body> {
+    let status = if health.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    let body = serde_json::to_string(&health).expect("failed to serialize health");
+
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .header("Cache-Control", "no-cache")
+        .body(Body::from(body))
+        .expect("failed to create response")
+}
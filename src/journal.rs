@@ -0,0 +1,278 @@
+use crate::error::*;
+use crate::util::blocking;
+use futures::stream::{self, Stream};
+use futures::sync::mpsc::{unbounded, UnboundedSender};
+use futures::Future;
+use log::*;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+/// Number of the urls between the indexed offsets of the journal file
+const STRIDE: usize = 1024;
+
+/// Number of the stored urls read at once by the subscriptions
+const BATCH: usize = 1024;
+
+/// An append-only log of urls found by a crawl, in the order of discovery
+///
+/// Positions in the journal never change, so they can be used as cursors
+/// for reading partial results of a running crawl. The urls are kept in a file,
+/// one json string per line, and only every `STRIDE`-th offset is kept in memory.
+
+#[derive(Debug, Clone)]
+pub(super) struct Journal {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    path: PathBuf,
+    /// opened by the first append
+    file: Option<BufWriter<File>>,
+    /// number of the urls
+    len: usize,
+    /// length of the file, in bytes, while it's appended to
+    bytes: u64,
+    /// offsets of every `STRIDE`-th url, None until the file of a restored journal is read
+    offsets: Option<Vec<u64>>,
+    listeners: Vec<UnboundedSender<(usize, Url)>>,
+    closed: bool,
+}
+
+impl Journal {
+    /// Create empty journal appending to given file, removed beforehand by `RunFiles::clear`
+    pub(super) fn new(path: PathBuf) -> Self {
+        Self::with(path, 0, 0, Some(Vec::new()), false)
+    }
+
+    /// Continue given journal file, dropping the urls appended after given number of them
+    pub(super) fn resume(path: PathBuf, len: usize) -> Result<Self> {
+        let (offsets, bytes) = match OpenOptions::new().write(true).open(&path) {
+            Ok(file) => {
+                let (offsets, bytes) = index(&path, len)?;
+                file.set_len(bytes)?;
+                (offsets, bytes)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound && len == 0 => (Vec::new(), 0),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self::with(path, len, bytes, Some(offsets), false))
+    }
+
+    /// Create closed journal of given file containing given number of urls
+    pub(super) fn restore(path: PathBuf, len: usize) -> Self {
+        Self::with(path, len, 0, None, true)
+    }
+
+    fn with(
+        path: PathBuf,
+        len: usize,
+        bytes: u64,
+        offsets: Option<Vec<u64>>,
+        closed: bool,
+    ) -> Self {
+        let inner = Inner {
+            path,
+            file: None,
+            len,
+            bytes,
+            offsets,
+            listeners: Vec::new(),
+            closed,
+        };
+
+        Self {
+            inner: Arc::new(Mutex::new(inner)),
+        }
+    }
+
+    /// Append new urls, notifying all subscribers
+    pub(super) fn push(&self, urls: Vec<Url>) -> Result<()> {
+        let mut guard = self.inner.lock().expect("poisoned journal lock");
+        let inner = &mut *guard;
+
+        if inner.closed {
+            return Err(err_msg("journal is closed"));
+        }
+
+        if inner.file.is_none() {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&inner.path)?;
+
+            inner.file = Some(BufWriter::new(file));
+        }
+
+        let file = inner.file.as_mut().expect("journal file was just opened");
+
+        for url in urls {
+            let position = inner.len;
+
+            if let Some(offsets) = inner.offsets.as_mut() {
+                // the first url of the next stride
+                if offsets.len() * STRIDE == position {
+                    offsets.push(inner.bytes);
+                }
+            }
+
+            let line = serde_json::to_string(url.as_str())? + "\n";
+            file.write_all(line.as_bytes())?;
+
+            inner.bytes += line.len() as u64;
+            inner.len += 1;
+            inner
+                .listeners
+                .retain(|listener| listener.unbounded_send((position, url.clone())).is_ok());
+        }
+
+        // the readers take the urls from the file
+        file.flush()?;
+
+        Ok(())
+    }
+
+    /// Mark the journal as complete, ending all subscriptions
+    pub(super) fn close(&self) {
+        let mut inner = self.inner.lock().expect("poisoned journal lock");
+
+        inner.closed = true;
+        inner.file = None;
+        inner.listeners.clear();
+    }
+
+    /// Return the number of the urls
+    pub(super) fn len(&self) -> usize {
+        self.inner.lock().expect("poisoned journal lock").len
+    }
+
+    /// Return at most given number of urls starting at given position, with the journal length
+    ///
+    /// Returns None if the position is past the end of the journal.
+    /// The urls are read from the file, so this blocks.
+
+    pub(super) fn since(&self, position: usize, limit: usize) -> Result<Option<(Vec<Url>, usize)>> {
+        let mut inner = self.inner.lock().expect("poisoned journal lock");
+
+        if position > inner.len {
+            return Ok(None);
+        }
+
+        let end = inner.len.min(position.saturating_add(limit));
+
+        if position == end {
+            return Ok(Some((Vec::new(), inner.len)));
+        }
+
+        let offset = inner.offsets()?[position / STRIDE];
+
+        let mut file = BufReader::new(File::open(&inner.path)?);
+        file.seek(SeekFrom::Start(offset))?;
+
+        let mut urls = Vec::with_capacity(end - position);
+
+        for line in file.lines().skip(position % STRIDE).take(end - position) {
+            let url = serde_json::from_str::<String>(&line?)?;
+            urls.push(Url::parse(&url)?);
+        }
+
+        if urls.len() < end - position {
+            return Err(err_msg("journal is truncated"));
+        }
+
+        Ok(Some((urls, inner.len)))
+    }
+
+    /// Return a stream of (position, url) pairs, starting at given position
+    ///
+    /// The stream contains the already known urls, read from the file in batches,
+    /// followed by the new ones as they're found. It ends when the journal is closed.
+
+    pub(super) fn subscribe(
+        &self,
+        position: usize,
+    ) -> Option<impl Stream<Item = (usize, Url), Error = ()>> {
+        let (end, rx) = {
+            let mut inner = self.inner.lock().expect("poisoned journal lock");
+
+            if position > inner.len {
+                return None;
+            }
+
+            let (tx, rx) = unbounded();
+
+            if !inner.closed {
+                inner.listeners.push(tx);
+            }
+
+            (inner.len, rx)
+        };
+
+        let journal = self.clone();
+
+        let stored = stream::unfold(position, move |position| {
+            if position >= end {
+                return None;
+            }
+
+            let journal = journal.clone();
+
+            let batch = blocking(move || {
+                let limit = BATCH.min(end - position);
+                let (urls, _) = journal.since(position, limit)?.unwrap_or_default();
+
+                let urls = urls
+                    .into_iter()
+                    .enumerate()
+                    .map(|(offset, url)| (position + offset, url))
+                    .collect::<Vec<_>>();
+
+                Ok((urls, position + limit))
+            })
+            .map_err(|e: Error| error!("Failed to read journal: {}", e));
+
+            Some(batch)
+        });
+
+        Some(stored.map(stream::iter_ok).flatten().chain(rx))
+    }
+}
+
+impl Inner {
+    /// Return the offsets of every `STRIDE`-th url, reading the file if they aren't known
+    fn offsets(&mut self) -> Result<&[u64]> {
+        if self.offsets.is_none() {
+            self.offsets = Some(index(&self.path, self.len)?.0);
+        }
+
+        Ok(self.offsets.as_deref().unwrap_or_default())
+    }
+}
+
+/// Return the offsets of every `STRIDE`-th url of given journal file, and the length of
+/// its first given number of urls, in bytes
+fn index(path: &Path, len: usize) -> Result<(Vec<u64>, u64)> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut offsets = Vec::with_capacity(len / STRIDE + 1);
+    let (mut offset, mut line) = (0, Vec::new());
+
+    for position in 0..len {
+        if offsets.len() * STRIDE == position {
+            offsets.push(offset);
+        }
+
+        line.clear();
+        offset += file.read_until(b'\n', &mut line)? as u64;
+
+        // e.g. the crawl was interrupted before storing the urls of its checkpoint
+        if line.last() != Some(&b'\n') {
+            return Err(err_msg("journal is truncated"));
+        }
+    }
+
+    Ok((offsets, offset))
+}
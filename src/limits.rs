@@ -0,0 +1,385 @@
+//! Server-wide limits of the crawls and their connections
+
+use crate::error::*;
+use crate::metrics;
+use crate::storage::{Storage, UsageRecord};
+use crawler::prelude;
+use crawler::ty::{FetchBuffer, Fetched, Validators};
+use crawler::Url;
+use futures::task::{self, Task};
+use futures::{Async, Future, Poll};
+use log::*;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Length of a quota period, in seconds
+const DAY: u64 = 24 * 60 * 60;
+
+/// The limiter of the connections of all crawls
+static CONNECTIONS: OnceLock<Limiter> = OnceLock::new();
+
+/// A limit of the concurrent tasks, granting the permits in the order they were requested
+#[derive(Debug, Clone)]
+pub(super) struct Limiter {
+    inner: Arc<Mutex<LimiterInner>>,
+}
+
+#[derive(Debug)]
+struct LimiterInner {
+    /// maximum number of the permits, unlimited if None
+    max: Option<usize>,
+    /// number of the granted permits
+    active: usize,
+    /// tasks waiting for a permit, with their tickets
+    waiting: VecDeque<(usize, Task)>,
+    /// ticket of the next waiting task
+    next: usize,
+}
+
+impl LimiterInner {
+    fn has_room(&self) -> bool {
+        self.max.is_none_or(|max| self.active < max)
+    }
+
+    /// Wake up the first waiting task, if it can get a permit
+    fn notify(&self) {
+        if self.has_room() {
+            if let Some((_, task)) = self.waiting.front() {
+                task.notify();
+            }
+        }
+    }
+}
+
+impl Limiter {
+    pub(super) fn new(max: Option<usize>) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(LimiterInner {
+                max,
+                active: 0,
+                waiting: VecDeque::new(),
+                next: 0,
+            })),
+        }
+    }
+
+    /// Wait for a permit, released when it's dropped
+    pub(super) fn acquire(&self) -> Acquire {
+        Acquire {
+            limiter: self.clone(),
+            ticket: None,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LimiterInner> {
+        self.inner.lock().expect("poisoned limiter lock")
+    }
+}
+
+/// A future of a [Permit](struct.Permit.html)
+#[derive(Debug)]
+pub(super) struct Acquire {
+    limiter: Limiter,
+    /// the place in the queue, if waiting
+    ticket: Option<usize>,
+}
+
+impl Future for Acquire {
+    type Item = Permit;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut inner = self.limiter.lock();
+
+        let first = inner.waiting.front().map(|(ticket, _)| *ticket);
+        let turn = first.is_none() || first == self.ticket;
+
+        if turn && inner.has_room() {
+            if self.ticket.take().is_some() {
+                inner.waiting.pop_front();
+            }
+
+            inner.active += 1;
+
+            // there may be room for the next one as well
+            inner.notify();
+
+            return Ok(Async::Ready(Permit {
+                limiter: self.limiter.clone(),
+            }));
+        }
+
+        match self.ticket {
+            Some(ticket) => {
+                if let Some((_, task)) = inner.waiting.iter_mut().find(|(t, _)| *t == ticket) {
+                    *task = task::current();
+                }
+            }
+            None => {
+                let ticket = inner.next;
+
+                inner.next += 1;
+                inner.waiting.push_back((ticket, task::current()));
+
+                self.ticket = Some(ticket);
+            }
+        }
+
+        Ok(Async::NotReady)
+    }
+}
+
+impl Drop for Acquire {
+    fn drop(&mut self) {
+        if let Some(ticket) = self.ticket {
+            let mut inner = self.limiter.lock();
+
+            inner.waiting.retain(|(t, _)| *t != ticket);
+
+            // the dropped one might have been the next in line
+            inner.notify();
+        }
+    }
+}
+
+/// A permit of a [Limiter](struct.Limiter.html), released when dropped
+#[derive(Debug)]
+pub(super) struct Permit {
+    limiter: Limiter,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut inner = self.limiter.lock();
+
+        inner.active -= 1;
+        inner.notify();
+    }
+}
+
+/// Limit the number of the concurrent connections of all crawls
+///
+/// Has effect only before anything was fetched.
+
+pub(super) fn limit_connections(max: Option<usize>) {
+    let _ = CONNECTIONS.set(Limiter::new(max));
+}
+
+fn connection() -> Acquire {
+    CONNECTIONS.get_or_init(|| Limiter::new(None)).acquire()
+}
+
+/// Document fetcher, waiting for a connection of the global limit
+pub(super) fn fetch(
+    url: Url,
+) -> Box<dyn Future<Item = Option<(Url, FetchBuffer)>, Error = Error> + Send> {
+    Box::new(connection().and_then(move |permit| {
+        prelude::fetch(url).then(move |result| {
+            drop(permit);
+            result
+        })
+    }))
+}
+
+/// Conditional document fetcher, waiting for a connection of the global limit
+pub(super) fn fetch_conditional(
+    url: Url,
+    validators: Validators,
+) -> Box<dyn Future<Item = Option<Fetched>, Error = Error> + Send> {
+    Box::new(connection().and_then(move |permit| {
+        let started = Instant::now();
+
+        prelude::try_fetch_conditional(url, validators).then(move |result| {
+            drop(permit);
+            metrics::observe_fetch(started.elapsed(), &result);
+
+            // the crawler counts the failed requests itself
+            Ok(result.ok())
+        })
+    }))
+}
+
+/// Number of the crawls scheduled by each client in the current quota period
+#[derive(Debug, Default)]
+pub(super) struct Usage {
+    /// the counts, with the number of their updates
+    usage: Mutex<(UsageRecord, u64)>,
+    /// the number of the updates of the stored counts
+    stored: Mutex<u64>,
+}
+
+impl Usage {
+    /// Continue counting the stored crawls, if any
+    pub(super) fn new(usage: Option<UsageRecord>) -> Self {
+        Self {
+            usage: Mutex::new((usage.unwrap_or_default(), 0)),
+            stored: Mutex::new(0),
+        }
+    }
+
+    /// Count a crawl of given client, unless it already scheduled given number of them today
+    ///
+    /// Returns false if the limit is reached. The counts are stored, so they survive a restart,
+    /// and this blocks.
+
+    pub(super) fn take(&self, client: &str, limit: Option<usize>, storage: &dyn Storage) -> bool {
+        let today = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() / DAY)
+            .unwrap_or_default();
+
+        let (usage, update) = {
+            let mut guard = self.usage.lock().expect("poisoned usage lock");
+            let (usage, updates) = &mut *guard;
+
+            if usage.day != today {
+                // the counts of the past days are no longer needed
+                usage.day = today;
+                usage.crawls.clear();
+            }
+
+            let count = usage.crawls.entry(client.to_owned()).or_default();
+
+            if limit.is_some_and(|limit| *count >= limit) {
+                return false;
+            }
+
+            *count += 1;
+            *updates += 1;
+
+            (usage.clone(), *updates)
+        };
+
+        // the other crawls are counted meanwhile, so only the latest counts are stored
+        let mut stored = self.stored.lock().expect("poisoned usage lock");
+
+        if *stored < update {
+            if let Err(e) = storage.save_usage(&usage) {
+                warn!("Unable to store the quota usage: {}", e);
+            }
+
+            *stored = update;
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::FileStorage;
+    use futures::executor::{self, Notify, Spawn};
+
+    /// Records the tasks woken up
+    #[derive(Debug, Default)]
+    struct Woken(Mutex<Vec<usize>>);
+
+    impl Notify for Woken {
+        fn notify(&self, id: usize) {
+            self.0.lock().unwrap().push(id);
+        }
+    }
+
+    impl Woken {
+        fn take(&self) -> Vec<usize> {
+            std::mem::take(&mut *self.0.lock().unwrap())
+        }
+    }
+
+    fn poll(acquire: &mut Spawn<Acquire>, woken: &Arc<Woken>, id: usize) -> Option<Permit> {
+        match acquire.poll_future_notify(woken, id).unwrap() {
+            Async::Ready(permit) => Some(permit),
+            Async::NotReady => None,
+        }
+    }
+
+    #[test]
+    fn limiter() {
+        let woken = Arc::new(Woken::default());
+        let limiter = Limiter::new(Some(2));
+
+        let mut acquires = (0..5)
+            .map(|_| executor::spawn(limiter.acquire()))
+            .collect::<Vec<_>>();
+
+        let first = poll(&mut acquires[0], &woken, 0).unwrap();
+        let second = poll(&mut acquires[1], &woken, 1).unwrap();
+
+        for (id, acquire) in acquires.iter_mut().enumerate().skip(2) {
+            assert!(poll(acquire, &woken, id).is_none());
+        }
+
+        // the first waiting one is woken up, and the later ones can't overtake it
+        drop(first);
+        assert_eq!(woken.take(), vec![2]);
+        assert!(poll(&mut acquires[4], &woken, 4).is_none());
+        assert!(poll(&mut acquires[3], &woken, 3).is_none());
+
+        let third = poll(&mut acquires[2], &woken, 2).unwrap();
+        assert!(woken.take().is_empty());
+
+        // a dropped waiting one gives up its turn
+        drop(acquires.remove(3));
+        drop(second);
+        assert_eq!(woken.take(), vec![4]);
+
+        let fourth = poll(&mut acquires[3], &woken, 4).unwrap();
+        assert!(woken.take().is_empty());
+
+        drop((third, fourth));
+
+        // without a limit, the permits are granted right away
+        let limiter = Limiter::new(None);
+        let permits = (0..100)
+            .map(|id| poll(&mut executor::spawn(limiter.acquire()), &woken, id))
+            .collect::<Vec<_>>();
+
+        assert!(permits.iter().all(Option::is_some));
+        assert!(woken.take().is_empty());
+    }
+
+    #[test]
+    fn usage() {
+        let dir = std::env::temp_dir().join(format!("usage-{}", std::process::id()));
+        let storage = FileStorage::new(&dir).unwrap();
+
+        let today = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            / DAY;
+
+        // counted on a past day
+        let mut past = UsageRecord {
+            day: today - 1,
+            ..UsageRecord::default()
+        };
+        past.crawls.insert("client".to_owned(), 5);
+
+        let usage = Usage::new(Some(past));
+
+        assert!(usage.take("client", Some(2), &storage));
+        assert!(usage.take("client", Some(2), &storage));
+        assert!(!usage.take("client", Some(2), &storage));
+        assert!(!usage.take("client", Some(1), &storage));
+
+        // the clients and the unlimited ones are counted separately
+        assert!(usage.take("other", Some(1), &storage));
+        assert!(usage.take("client", None, &storage));
+        assert!(usage.take("client", Some(4), &storage));
+
+        let stored = storage.load_usage().unwrap().unwrap();
+        assert_eq!(stored.day, today);
+        assert_eq!(stored.crawls["client"], 4);
+        assert_eq!(stored.crawls["other"], 1);
+
+        // the stored counts continue after a restart
+        let usage = Usage::new(Some(stored));
+        assert!(!usage.take("other", Some(1), &storage));
+        assert!(usage.take("other", Some(2), &storage));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
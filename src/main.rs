@@ -1,5 +1,23 @@
 //! Web server-based web crawler
 //!
+//! When the server is started with API keys, every request needs a key: `GET` requests with the
+//! `read` scope, and the other ones with the `write` scope. The key is passed either as a bearer
+//! token (`Authorization: Bearer {key}`) or in the `X-Api-Key` header. The requests without a valid
+//! key get `401 Unauthorized`, and the ones with a key lacking the scope `403 Forbidden`.
+//! The health checks, `GET /healthz` and `GET /readyz`, don't need a key.
+//!
+//! The crawls don't fetch from private, loopback, link-local, multicast or other reserved
+//! addresses, unless they're allowed with `--allow-cidr`; the ones denied with `--deny-cidr` are
+//! never fetched from. The callbacks are subject to the same checks.
+//!
+//! The server limits the number of the running crawls and their connections, and the clients
+//! can have quotas on the crawls they schedule per day and the pages fetched by a crawl.
+//!
+//! A single crawl can also be run without the server, with `webcrawl crawl <url>...`; its results
+//! are written to stdout or to the `--output` file as JSON, CSV or NDJSON, and its progress to
+//! stderr. The crawl obeys the `/robots.txt` of the crawled sites with `--robots`, and follows at
+//! most `--depth` links from the starting urls.
+//!
 //! The API of the server is very simple:
 //!
 //! # Get all crawled domains
@@ -13,13 +31,205 @@
 //! ```json
 //! {
 //!     "url": "http://example.com",
+//!     "seeds": [
+//!         {"url": "http://blog.example.com", "scope": true},
+//!         {"url": "http://example.net/links.html", "scope": false}
+//!     ],
 //!     "throttle": 50,
+//!     "priority": "bfs",
+//!     "callback_url": "http://hooks.example.com/crawls",
+//!     "warc": true,
+//!     "index": true,
+//!     "snapshots": true,
+//!     "queue_priority": 0,
+//!     "depth": 3,
+//!     "robots": true
 //! }
 //! ```
 //!
 //! ### where:
 //! - `url`: an url to be crawled
-//! - `throttle`: a maximum number of concurrent requests
+//! - `seeds`: optional additional urls to be crawled, at least one of `url` and `seeds` is required
+//!   - `url`: the starting url
+//!   - `scope`: whether the urls of the seed's origin are crawled too (default `true`); the links
+//!     of a seed outside of the scope are followed only if they lead into it
+//! - `throttle`: a maximum number of concurrent requests, at least 1, capped by the server's
+//!   `--max-throttle`
+//! - `priority`: an optional crawling order, one of:
+//!   - `fifo` - in the order of discovery (default)
+//!   - `bfs` - the pages closest to the starting url first
+//!   - `dfs` - the pages farthest from the starting url first
+//!   - `shortest` - the shortest urls first
+//!   - `sitemap` - by the `<priority>` listed in the site's `/sitemap.xml`; the unlisted pages
+//!     get the default priority of 0.5
+//!
+//!   The prioritized queues spill their lowest priority urls to disk beyond `--frontier-capacity`.
+//! - `callback_url`: an optional url notified when the crawl is done
+//! - `warc`: whether to archive the fetched documents in a WARC file (default `false`), see
+//!   the `warc` format of the results
+//! - `index`: whether to index the text of the fetched documents for the full-text search
+//!   (default `false`)
+//! - `snapshots`: whether to keep the fetched documents, to be retrieved as snapshots (default
+//!   `false`)
+//! - `queue_priority`: the priority of the crawl while it waits for a free worker, higher first
+//!   (default `0`); the crawls of the same priority start in the order they were scheduled
+//! - `depth`: an optional maximum number of the links followed from the starting urls, `0` fetches
+//!   only the starting urls themselves
+//! - `robots`: whether to skip the urls disallowed by the `/robots.txt` of the scoped sites, matched
+//!   against the product token of `--user-agent` (default `false`); the starting urls are fetched
+//!   regardless
+//!
+//! The fields left out take the defaults configured in the `[crawl]` section of the server's
+//! configuration file, if any.
+//!
+//! Scheduling a crawl of a finished or cancelled `id` starts its next run. The run sends the
+//! `ETag`/`Last-Modified` validators of the pages crawled in the previous one, and the pages that
+//! weren't modified since (`304 Not Modified`) are not downloaded again; their stored links are
+//! followed instead.
+//!
+//! A crawl of a single site from its root, without a `depth` or `robots`, has the origin of the
+//! site as its `id`, e.g. `http://example.com`. The other crawls add a digest of their starting
+//! urls, their scopes and the `depth` and `robots` fields, e.g. `http://example.com#2f6c0a9d41e7b358`,
+//! so requesting the same crawl again starts its next run. The `id` is percent-encoded in the query
+//! strings.
+//!
+//! When a crawl with a `callback_url` finishes, fails or is cancelled, a summary of the run is
+//! POSTed to the callback:
+//!
+//! ```json
+//! {
+//!     "id": "http://example.com",
+//!     "run": 1,
+//!     "state": "failed",
+//!     "error": "No space left on device (os error 28)",
+//!     "fetched": 120,
+//!     "unchanged": 0,
+//!     "effective": 123,
+//!     "errors": 3,
+//!     "bytes": 1048576,
+//!     "elapsed": 5.0
+//! }
+//! ```
+//!
+//! where `state` is one of `finished`, `cancelled`, `failed`, and `error` is `null` unless it failed.
+//! The delivery is retried up to 5 times, with exponential backoff starting at 1 second, unless the
+//! callback responds with a client error other than `408` or `429`. With `--webhook-secret` set, the
+//! payload is signed with HMAC-SHA256, and the hex-encoded signature is sent in the
+//! `X-Webcrawl-Signature: sha256={signature}` header.
+//!
+//! ## Response:
+//!
+//! ```json
+//! {
+//!     "id": "http://example.com"
+//! }
+//! ```
+//!
+//! ## Additional status codes:
+//! - `400` - if the payload is malformed, or it contains invalid URL or callback URL, a URL of
+//!   an address refused by the network policy, or a `throttle` of 0
+//! - `409` - if the crawl is already queued or running
+//! - `429` - if the client already scheduled its daily quota of crawls
+//! - `503` - if the server is shutting down
+//!
+//! # Get changes since the previous run of a crawl
+//! `GET /api/crawl/{id}/changes`
+//!
+//! ## Response:
+//!
+//! ```json
+//! {
+//!     "id": "http://example.com",
+//!     "run": 2,
+//!     "previous": 1,
+//!     "added": ["http://example.com/new.html"],
+//!     "removed": ["http://example.com/old.html"],
+//!     "changed": ["http://example.com/"]
+//! }
+//! ```
+//!
+//! ### where:
+//! - `run`: a number of the latest run of the crawl, starting at 1
+//! - `previous`: a number of the compared run, `null` for the first one
+//! - `added`: urls found only in the latest run
+//! - `removed`: urls found only in the previous run
+//! - `changed`: urls of the documents whose content was modified
+//!
+//! ## Additional status codes:
+//! - `202` - if the crawl is pending and the changes are not yet available
+//! - `404` - if the `id` is not present in the results cache
+//!
+//! # Compare two runs of a crawl
+//! `GET /api/crawls/diff?id={id}[&from={run}][&to={run}]`
+//!
+//! Compares the results of two finished runs. `to` defaults to the latest finished run,
+//! and `from` to the one preceding `to`. The response is a CSV with `Accept: text/csv`.
+//!
+//! ## Response:
+//!
+//! ```json
+//! {
+//!     "id": "http://example.com",
+//!     "from": 1,
+//!     "to": 2,
+//!     "added": ["http://example.com/new.html"],
+//!     "removed": ["http://example.com/old.html"],
+//!     "status": [{"url": "http://example.com/gone.html", "from": 200, "to": 404}],
+//!     "title": [{"url": "http://example.com/", "from": "Welcome", "to": "Home"}],
+//!     "redirect": [
+//!         {
+//!             "url": "http://example.com/blog",
+//!             "from": "http://example.com/blog/",
+//!             "to": "http://blog.example.com/"
+//!         }
+//!     ]
+//! }
+//! ```
+//!
+//! or
+//!
+//! ```csv
+//! change,url,from,to
+//! added,http://example.com/new.html,,
+//! removed,http://example.com/old.html,,
+//! status,http://example.com/gone.html,200,404
+//! title,http://example.com/,Welcome,Home
+//! redirect,http://example.com/blog,http://example.com/blog/,http://blog.example.com/
+//! ```
+//!
+//! ### where:
+//! - `added`: urls found only in the `to` run
+//! - `removed`: urls found only in the `from` run
+//! - `status`: pages whose HTTP status code changed
+//! - `title`: pages whose title changed, `null` if there was no title
+//! - `redirect`: pages redirected to a different url
+//!
+//! ## Additional status codes:
+//! - `400` - if the `id` is missing, or a run is not a number
+//! - `404` - if the `id` is not present in the results cache, or either run is not finished
+//!
+//! # Cancel a crawl
+//! `DELETE /api/crawl/{id}`
+//!
+//! The `id` may be percent-encoded. Requests already in flight are allowed to finish,
+//! and the results gathered so far remain available. A queued crawl is cancelled right away.
+//!
+//! ## Response:
+//!
+//! ```json
+//! {
+//!     "id": "http://example.com"
+//! }
+//! ```
+//!
+//! ## Additional status codes:
+//! - `404` - if the `id` is not present in the results cache
+//! - `409` - if the crawl is not running anymore
+//!
+//! # Pause or resume a crawl
+//! `POST /api/crawl/{id}/pause`
+//!
+//! `POST /api/crawl/{id}/resume`
 //!
 //! ## Response:
 //!
@@ -30,22 +240,260 @@
 //! ```
 //!
 //! ## Additional status codes:
-//! - `400` - if the payload is malformed, or it contains invalid URL
-//! - `409` - if the crawl is already pending
+//! - `404` - if the `id` is not present in the results cache
+//! - `409` - if the crawl is not running, or it is already paused/resumed
+//!
+//! # Schedule recurring crawls
+//! `PUT /api/schedules/{name}`
+//!
+//! Saves a crawl started on the schedule of a cron expression, replacing the previous one of
+//! the same `name`. The `name` consists of up to 64 letters, digits, `.`, `-` and `_`. The crawls
+//! are started for the client that saved the schedule, counting towards its quotas. A run is
+//! skipped if the previous one is still going, and the runs due while the server was down are
+//! skipped as well. The schedules are kept in the data directory, if any.
+//!
+//! ## Payload:
+//!
+//! ```json
+//! {
+//!     "cron": "0 3 * * mon-fri",
+//!     "crawl": {
+//!         "url": "http://example.com",
+//!         "throttle": 50
+//!     }
+//! }
+//! ```
+//!
+//! ## where:
+//! - `cron`: a cron expression of the start times, `minute hour day-of-month month day-of-week`, in
+//!   UTC; the fields accept `*`, numbers, ranges, steps and lists, e.g. `*/15` or `1,15`, and the
+//!   months and weekdays their three-letter names; `@yearly`, `@monthly`, `@weekly`, `@daily` and
+//!   `@hourly` are accepted too
+//! - `crawl`: the crawl, as in the payload of `POST /api/crawl`
+//!
+//! ## Response:
+//!
+//! ```json
+//! {
+//!     "name": "nightly",
+//!     "next": 1561939200
+//! }
+//! ```
+//!
+//! ## where:
+//! - `next`: unix timestamp of the next start
+//!
+//! ## Additional status codes:
+//! - `400` - if the `name`, the expression or the crawl is invalid, or the expression never matches,
+//!   e.g. `0 0 31 2 *`
+//!
+//! # List recurring crawls
+//! `GET /api/schedules`
+//!
+//! `GET /api/schedules/{name}`
+//!
+//! ## Response:
+//!
+//! ```json
+//! {
+//!     "name": "nightly",
+//!     "cron": "0 3 * * mon-fri",
+//!     "crawl": {
+//!         "url": "http://example.com",
+//!         "throttle": 50,
+//!         ...
+//!     },
+//!     "next": 1561939200,
+//!     "history": [
+//!         {"time": 1561852800, "outcome": "started", "id": "http://example.com", "run": 3, "status": null, "state": "finished"},
+//!         {"time": 1561766400, "outcome": "skipped", "id": null, "run": null, "status": null, "state": null}
+//!     ]
+//! }
+//! ```
+//!
+//! A list of the schedules, without their `history`, for `GET /api/schedules`.
+//!
+//! ## where:
+//! - `history`: up to 100 latest runs, the oldest first
+//!   - `time`: unix timestamp of the run
+//!   - `outcome`: one of:
+//!     - `started` - the crawl was scheduled as the `run` of its `id`
+//!     - `skipped` - the previous run was still going
+//!     - `rejected` - the crawl couldn't be scheduled, e.g. over the quota, with the HTTP `status`
+//!       it would have been rejected with
+//!   - `state`: the state the started run ended in, `finished`, `cancelled` or `failed`, `null`
+//!     while it's going
+//!
+//! ## Additional status codes:
+//! - `400` - if the `name` is invalid
+//! - `404` - if there's no schedule of the `name`
+//!
+//! # Remove recurring crawls
+//! `DELETE /api/schedules/{name}`
+//!
+//! The running crawls are not affected.
+//!
+//! ## Response:
+//!
+//! ```json
+//! {
+//!     "name": "nightly",
+//!     "next": null
+//! }
+//! ```
+//!
+//! ## Additional status codes:
+//! - `400` - if the `name` is invalid
+//! - `404` - if there's no schedule of the `name`
+//!
+//! # Get live progress of a crawl
+//! `GET /api/crawl/{id}/status`
+//!
+//! ## Response:
+//!
+//! ```json
+//! {
+//!     "id": "http://example.com",
+//!     "run": 1,
+//!     "state": "running",
+//!     "queue_position": null,
+//!     "queued": 120,
+//!     "in_flight": 50,
+//!     "fetched": 300,
+//!     "unchanged": 0,
+//!     "effective": 290,
+//!     "errors": 4,
+//!     "bytes": 5242880,
+//!     "elapsed": 12.5,
+//!     "pages_per_sec": 24.0
+//! }
+//! ```
+//!
+//! ### where:
+//! - `run`: a number of the latest run of the crawl, starting at 1
+//! - `state`: one of `queued`, `running`, `paused`, `cancelling`, `finished`, `cancelled`, `failed`
+//! - `queue_position`: the position of a `queued` crawl among the ones waiting for a worker,
+//!   starting at 1, `null` otherwise
+//! - `queued`: a number of urls waiting to be fetched
+//! - `in_flight`: a number of requests being processed
+//! - `fetched`: a number of successfully fetched documents
+//! - `unchanged`: a number of fetched documents not modified since the previous run
+//! - `effective`: a number of unique resulting urls
+//! - `errors`: a number of failed fetches
+//! - `bytes`: a number of downloaded bytes
+//! - `elapsed`: seconds since the crawl started
+//! - `pages_per_sec`: average number of fetched documents per second
+//!
+//! ## Additional status codes:
+//! - `404` - if the `id` is not present in the results cache
 //!
 //! # Get results of the crawl
-//! `GET /api/results?id={id}`
+//! `GET /api/results?id={id}[&format={format}][&limit={limit}][&offset={offset}][&{filter}={value}...]`
 //!
 //! ## Response
 //!
-//! A json list of retrieved URLs
+//! Depending on the `format`, or the `Accept` header if it's not set:
+//! - `json` (`application/json`, default): a json list of retrieved URLs
+//! - `csv` (`text/csv`): the retrieved URLs with the metadata of their pages, in
+//!   `url,status,content_type,depth,title,seed,links` columns; the metadata is empty if it's unknown
+//! - `ndjson` (`application/x-ndjson`): the same, streamed as one json object per line
+//!   ```json
+//!   {"url": "http://example.com/", "status": 200, "content_type": "text/html", "depth": 0, "title": "Example", "seed": "http://example.com/", "links": 12}
+//!   ```
+//! - `text` (`text/plain`): the retrieved URLs, one per line
+//! - `warc` (`application/warc`): the WARC 1.0 archive of the latest run, with the request and
+//!   response records of every fetched document, if the crawl was scheduled with `warc` enabled.
+//!   The archives are stored under `warc` in the data directory, or in the system temporary
+//!   directory. The bodies are stored as received, the records are dated by the time of the
+//!   fetch, and the documents that weren't modified since the previous run are not archived again.
+//!
+//! The URLs are sorted, so the results can be paged through with `offset` (default 0) and `limit`
+//! (default 1000, at most 10000). They can be narrowed down by:
+//! - `prefix`: a prefix of the URL path, e.g. `/blog/`
+//! - `regex`: a regular expression matching the URL
+//! - `status`: the HTTP status code of the page
+//! - `content_type`: a prefix of the page's content type, e.g. `text/html`
+//! - `depth`: the number of links followed from the seed to the page
+//!
+//! The URLs without the metadata of their pages don't match the filters based on it. The number of
+//! all matching URLs is returned in the `X-Total-Count` header, and if there are more than the
+//! `limit`, the following page is linked in the `Link` header with `rel="next"`. The `warc` format
+//! is neither paged nor filtered.
+//!
+//! ## Additional status codes:
+//! - `202` - if the crawl is pending and the result is not yet available
+//! - `400` - if the `format` is unknown, the `regex` is invalid, or a number is malformed
+//! - `404` - if the `id` is not present in the results cache, or the crawl wasn't archived
+//!
+//! # Get partial results of the crawl
+//! `GET /api/results?id={id}&since={cursor}[&limit={limit}]`
+//!
+//! Returns the urls found so far, in the order of discovery, starting at the `cursor` position, as json.
+//! It's available also while the crawl is pending. Start with `since=0`, and pass the returned
+//! `next` value in the following requests. At most `limit` (default 1000, at most 10000) urls are
+//! returned at once.
+//!
+//! ## Response:
+//!
+//! ```json
+//! {
+//!     "urls": ["http://example.com/", "http://example.com/about.html"],
+//!     "next": 2,
+//!     "finished": false
+//! }
+//! ```
+//!
+//! ### where:
+//! - `urls`: urls found since the `cursor` position
+//! - `next`: a cursor for the following request
+//! - `finished`: `true` if the crawl is done and no more urls will be returned
+//!
+//! ## Additional status codes:
+//! - `400` - if the `cursor` is past the end of the results, or the `limit` is malformed
+//! - `404` - if the `id` is not present in the results cache
+//!
+//! # Get results of the crawl by seed
+//! `GET /api/results/seeds?id={id}`
+//!
+//! ## Response:
+//!
+//! ```json
+//! {
+//!     "http://blog.example.com/": ["http://blog.example.com/", "http://blog.example.com/about.html"],
+//!     "http://example.com/": ["http://example.com/"]
+//! }
+//! ```
+//!
+//! The resulting urls grouped by the starting urls that led to them, i.e. the ones from which
+//! they were first discovered.
 //!
 //! ## Additional status codes:
 //! - `202` - if the crawl is pending and the result is not yet available
 //! - `404` - if the `id` is not present in the results cache
 //!
+//! # Stream results of the crawl
+//! `GET /api/results/stream?id={id}[&since={cursor}][&format={format}]`
+//!
+//! Streams the urls found so far, followed by the newly discovered ones, until the crawl finishes.
+//! `format` is either `ndjson` (default) or `sse`, for Server-Sent Events. The latter is also
+//! selected with `Accept: text/event-stream`, and supports resuming with the `Last-Event-ID` header.
+//!
+//! ## Response:
+//!
+//! One json object per url:
+//!
+//! ```json
+//! {"position": 0, "url": "http://example.com/"}
+//! ```
+//!
+//! ## Additional status codes:
+//! - `400` - if the `cursor` is past the end of the results, or the `format` is unknown
+//! - `404` - if the `id` is not present in the results cache
+//!
 //! # Get number of results of the crawl
-//! `GET /api/results/count?id={id}`
+//! `GET /api/results/count?id={id}[&{filter}={value}...]`
+//!
+//! Counts the URLs matching the same filters as the results.
 //!
 //! ## Response:
 //!
@@ -57,16 +505,188 @@
 //!
 //! ## Additional status codes:
 //! - `202` - if the crawl is pending and the result is not yet available
+//! - `400` - if the `regex` is invalid, or a number is malformed
 //! - `404` - if the `id` is not present in the results cache
+//!
+//! # Search the crawled pages
+//! `GET /api/search?id={id}&q={query}[&limit={limit}][&offset={offset}]`
+//!
+//! Returns the pages containing all words of the `query`, the most relevant first, if the crawl was
+//! scheduled with `index` enabled. The title, headings and body text of the fetched html pages are
+//! indexed, and the matches in the title and the headings rank higher. The index of the latest
+//! finished or cancelled run is stored under `index` in the data directory, or in the system
+//! temporary directory; the failed runs aren't indexed. The page texts are stored apart from the index
+//! and read only for the snippets of the returned pages. Returns `limit` (default 10, at most 10000)
+//! pages starting at `offset` (default 0).
+//!
+//! ## Response:
+//!
+//! ```json
+//! {
+//!     "id": "http://example.com",
+//!     "run": 2,
+//!     "total": 1,
+//!     "hits": [
+//!         {
+//!             "url": "http://example.com/about.html",
+//!             "title": "About us",
+//!             "score": 3.21,
+//!             "snippet": "…we build web crawlers in Rust, and…"
+//!         }
+//!     ]
+//! }
+//! ```
+//!
+//! ### where:
+//! - `run`: the number of the indexed run
+//! - `total`: the number of all matching pages
+//! - `score`: relevance of the page, higher is better
+//! - `snippet`: an excerpt of the page text around the first match
+//!
+//! ## Additional status codes:
+//! - `202` - if the crawl is pending and wasn't indexed yet
+//! - `400` - if the `query` contains no words, or a number is malformed
+//! - `404` - if the `id` is not present in the results cache, or the crawl wasn't indexed
+//!
+//! # Get a stored snapshot of a page
+//! `GET /api/snapshot?id={id}&url={url}[&run={run}]`
+//!
+//! Returns the document fetched from the `url` in given `run` of the crawl, exactly as the crawler
+//! saw it, with its original `Content-Type`, if the crawl was scheduled with `snapshots` enabled.
+//! The `url` is either the requested one or the one the document was retrieved from, after
+//! redirects. `run` defaults to the latest one.
+//!
+//! The documents are stored compressed with zstd under `blobs` in the data directory, or in the
+//! system temporary directory, named after the SHA-256 digest of their content, so identical
+//! documents are stored only once. The digest is returned in the `ETag` header. The documents that
+//! weren't modified since the previous run keep their snapshots from it.
+//!
+//! ## Additional status codes:
+//! - `202` - if the crawl is pending and no `run` is given
+//! - `400` - if the `url` or the `run` is invalid
+//! - `404` - if the `id` is not present in the results cache, the run isn't finished or cancelled,
+//!   or no document of the `url` is stored
+//!
+//! # Get metrics of the server
+//! `GET /metrics`
+//!
+//! Returns the metrics of the server in the Prometheus text format:
+//!
+//! - `webcrawl_crawls{state}`: number of the crawls by state, as in the live progress
+//! - `webcrawl_queued_urls`: number of the urls waiting to be fetched by the running crawls
+//! - `webcrawl_in_flight_requests{crawl}`: number of the requests in flight by running crawl; the
+//!   crawls past the 50 busiest ones are summed up as `other`
+//! - `webcrawl_pages_fetched_total`, `webcrawl_pages_unchanged_total`: number of the retrieved
+//!   documents, and the ones not modified since the previous run
+//! - `webcrawl_downloaded_bytes_total`: number of the downloaded bytes of the documents
+//! - `webcrawl_fetch_duration_seconds`: histogram of the latency of the requests
+//! - `webcrawl_responses_total{status}`: number of the responses by status code, `other` for the
+//!   non-standard ones
+//! - `webcrawl_errors_total{kind}`: number of the failed requests by kind, one of `timeout`, `dns`,
+//!   `connect`, `refused` (by the network policy), `redirects` or `other`, and of the pages that
+//!   couldn't be parsed, as `parse`
+//!
+//! The counters cover all crawls since the server started. The requests of the `/sitemap.xml`
+//! and `/robots.txt` files are counted along with the pages.
+//!
+//! # Check that the server is alive
+//! `GET /healthz`
+//!
+//! Responds as long as the server is running, with the version and the build of the binary, and the
+//! numbers of the running and the queued crawls. Doesn't need an API key.
+//!
+//! ## Response:
+//!
+//! ```json
+//! {
+//!     "ready": true,
+//!     "build": {
+//!         "version": "0.1.0",
+//!         "commit": "1e7ee8f",
+//!         "target": "x86_64-unknown-linux-gnu",
+//!         "profile": "release"
+//!     },
+//!     "active_crawls": 2,
+//!     "queued_crawls": 0
+//! }
+//! ```
+//!
+//! ### where:
+//! - `commit`: commit the binary was built from, missing if it wasn't built from a git checkout
+//! - `active_crawls`: number of the started crawls that aren't done yet, including the paused ones
+//! - `queued_crawls`: number of the crawls waiting for a free worker
+//!
+//! # Check that the server is ready
+//! `GET /readyz`
+//!
+//! Checks whether the server can take new crawls, and responds as `GET /healthz` along with the
+//! result of every check, `ok` or the reason it failed. Doesn't need an API key.
+//!
+//! ## Response:
+//!
+//! ```json
+//! {
+//!     "ready": false,
+//!     "checks": {
+//!         "registry": "ok",
+//!         "server": "ok",
+//!         "storage": "No such file or directory (os error 2)",
+//!         "workers": "ok"
+//!     },
+//!     "build": {
+//!         "version": "0.1.0",
+//!         "commit": "1e7ee8f",
+//!         "target": "x86_64-unknown-linux-gnu",
+//!         "profile": "release"
+//!     },
+//!     "active_crawls": 2,
+//!     "queued_crawls": 0
+//! }
+//! ```
+//!
+//! ### where:
+//! - `registry`: the crawls in memory are usable, i.e. no request panicked while updating them
+//! - `server`: the server isn't shutting down
+//! - `storage`: the crawls can be stored in the data directory
+//! - `workers`: a worker is free for a new crawl, i.e. it wouldn't wait in the queue
+//!
+//! ## Additional status codes:
+//! - `503` - if any check failed
 
 use error::*;
 use log::*;
 
 use crawler::prelude::*;
+use crawler::{
+    content_hash, BloomSeen, Checkpoint, DiskFrontier, FetchOptions, Fetched, MemoryFrontier,
+    NetworkPolicy, Priority, PriorityFrontier, Robots, Seed, State, Stats, Validators,
+};
 
+use auth::{Client, Scope};
+use blobs::BlobStore;
 use cli::setup_cli;
-use util::{find_query_param, get_result};
-
+use cron::Cron;
+use diff::Diff;
+use export::{csv_header, Filter, Format, Page};
+use journal::Journal;
+use limits::Usage;
+use runs::{ChangesRecord, RunFiles};
+use scheduler::{Scheduler, Worker};
+use schedules::{Outcome, RunRecord, ScheduleRecord, Schedules};
+use search::{Document, Hit, Index, IndexBuilder};
+use settings::{Settings, Tls};
+use storage::{
+    timestamp, CheckpointRecord, CrawlPriority, FileStorage, MemoryStorage, Record, RecordState,
+    Storage,
+};
+use util::{
+    batches, blocking, find_query_param, get_denied, get_result, get_result_as, get_result_with,
+    get_stream, schedule_name, split_crawl_path,
+};
+use warc::WarcWriter;
+use webhook::Summary;
+
+use futures::sync::oneshot;
 use hyper::rt::Future;
 use hyper::service::service_fn;
 use hyper::{Body, Method, Request, Response, Server, StatusCode};
@@ -74,95 +694,1812 @@ use hyper::{Body, Method, Request, Response, Server, StatusCode};
 use serde_derive::{Deserialize, Serialize};
 use serde_json;
 use tokio::prelude::*;
+use tokio::timer::Interval;
 use url::Url;
 
-use hashbrown::{hash_map::Entry, HashMap};
-use std::borrow::Borrow;
-use std::net::ToSocketAddrs;
-use std::sync::{Arc, RwLock};
+use hashbrown::{HashMap, HashSet};
+use std::borrow::Borrow;
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use std::str::from_utf8;
+
+use env_logger;
+
+mod auth;
+mod blobs;
+mod cli;
+mod config;
+mod crawl;
+mod cron;
+mod diff;
+mod error;
+mod export;
+mod health;
+mod journal;
+mod limits;
+mod metrics;
+mod runs;
+mod scheduler;
+mod schedules;
+mod search;
+mod settings;
+mod shutdown;
+mod storage;
+mod util;
+mod warc;
+mod webhook;
+
+#[derive(Debug)]
+enum CrawlStatus {
+    /// waiting for a free worker
+    Queued,
+    Pending,
+    Finished,
+    Cancelled,
+    /// with the urls found before the failure
+    Failed,
+}
+
+impl CrawlStatus {
+    /// Return true if the crawl is done, so its results are available
+    fn done(&self) -> bool {
+        match self {
+            CrawlStatus::Queued | CrawlStatus::Pending => false,
+            CrawlStatus::Finished | CrawlStatus::Cancelled | CrawlStatus::Failed => true,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Crawl {
+    status: CrawlStatus,
+    /// pause/resume/cancel handle of the running crawler
+    control: Control,
+    /// live statistics of the crawler
+    progress: Progress,
+    /// urls found so far, in the order of discovery
+    journal: Journal,
+    /// number of the latest run, its results are kept in the files of the run
+    run: usize,
+}
+
+impl Crawl {
+    /// Return human-readable state of the crawl
+    fn state(&self) -> &'static str {
+        match (&self.status, self.control.state()) {
+            (CrawlStatus::Queued, State::Cancelled) => "cancelling",
+            (CrawlStatus::Queued, _) => "queued",
+            (CrawlStatus::Pending, State::Running) => "running",
+            (CrawlStatus::Pending, State::Paused) => "paused",
+            (CrawlStatus::Pending, State::Cancelled) => "cancelling",
+            (CrawlStatus::Finished, _) => "finished",
+            (CrawlStatus::Cancelled, _) => "cancelled",
+            (CrawlStatus::Failed, _) => "failed",
+        }
+    }
+}
+
+type Registry = Arc<RwLock<HashMap<String, Crawl>>>;
+
+/// Number of the concurrent TLS handshakes
+const TLS_HANDSHAKES: usize = 64;
+
+#[derive(Debug, Serialize)]
+struct CrawlResponse<'a> {
+    id: &'a str,
+}
+
+/// A requested crawl
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CrawlRequest {
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default)]
+    seeds: Vec<SeedRequest>,
+    throttle: usize,
+    #[serde(default)]
+    priority: CrawlPriority,
+    #[serde(default)]
+    callback_url: Option<String>,
+    #[serde(default)]
+    warc: bool,
+    #[serde(default)]
+    index: bool,
+    #[serde(default)]
+    snapshots: bool,
+    #[serde(default)]
+    queue_priority: i32,
+    #[serde(default)]
+    depth: Option<usize>,
+    #[serde(default)]
+    robots: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SeedRequest {
+    url: String,
+    #[serde(default = "in_scope")]
+    scope: bool,
+}
+
+fn in_scope() -> bool {
+    true
+}
+
+impl CrawlRequest {
+    /// Return the seeds of the crawl, failing if there are none or they can't be fetched
+    fn seeds(&self) -> std::result::Result<Vec<Seed>, StatusCode> {
+        let seeds = self
+            .url
+            .iter()
+            .map(|url| Seed::new(url.as_str(), true))
+            .chain(
+                self.seeds
+                    .iter()
+                    .map(|seed| Seed::new(seed.url.as_str(), seed.scope)),
+            )
+            .collect::<std::result::Result<Vec<_>, _>>()
+            // invalid url in the payload
+            .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+        if seeds.is_empty() {
+            // nothing to crawl
+            return Err(StatusCode::BAD_REQUEST);
+        }
+
+        if let Some(policy) = NetworkPolicy::installed() {
+            if seeds.iter().any(|seed| policy.refuses_address(&seed.url)) {
+                // restricted address in the payload
+                return Err(StatusCode::BAD_REQUEST);
+            }
+        }
+
+        Ok(seeds)
+    }
+
+    /// Return the id of the crawl of given seeds
+    ///
+    /// A crawl of a single site from its root is identified by the site's origin, the other crawls
+    /// add a digest of their seeds and the options limiting the crawled urls, so the same crawl
+    /// requested again gets the same id.
+
+    fn id(&self, seeds: &[Seed]) -> String {
+        let origin = seeds[0].url.origin().ascii_serialization();
+
+        let single = match seeds {
+            [seed] => seed.scope && seed.url.path() == "/" && seed.url.query().is_none(),
+            _ => false,
+        };
+
+        if single && self.depth.is_none() && !self.robots {
+            return origin;
+        }
+
+        let mut key = seeds
+            .iter()
+            .map(|seed| format!("{} {}\n", seed.url, seed.scope))
+            .collect::<String>();
+        key += &format!("{:?} {}", self.depth, self.robots);
+
+        format!("{}#{:016x}", origin, content_hash(&key))
+    }
+
+    /// Return the requested concurrency capped by given maximum, failing if it's 0
+    fn throttle(&self, max: usize) -> std::result::Result<usize, StatusCode> {
+        if self.throttle == 0 {
+            // no requests would ever be sent
+            return Err(StatusCode::BAD_REQUEST);
+        }
+
+        Ok(self.throttle.min(max))
+    }
+
+    /// Return the normalized callback url, if any
+    fn callback_url(&self) -> std::result::Result<Option<String>, StatusCode> {
+        match &self.callback_url {
+            Some(callback) => Ok(Some(
+                Url::parse(callback)
+                    .ok()
+                    .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+                    // restricted address of the callback
+                    .filter(|url| {
+                        NetworkPolicy::installed().is_none_or(|policy| !policy.refuses_address(url))
+                    })
+                    // invalid callback url in the payload
+                    .ok_or(StatusCode::BAD_REQUEST)?
+                    .into_string(),
+            )),
+            None => Ok(None),
+        }
+    }
+}
+
+/// A crawl started on a schedule
+#[derive(Debug, Deserialize)]
+struct ScheduleRequest {
+    cron: String,
+    /// the crawl, before the defaults are applied
+    crawl: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+struct ScheduleResponse<'a> {
+    name: &'a str,
+    cron: &'a str,
+    crawl: &'a CrawlRequest,
+    /// unix timestamp of the next start, if any
+    next: Option<u64>,
+    /// the latest runs, the oldest first
+    #[serde(skip_serializing_if = "Option::is_none")]
+    history: Option<&'a VecDeque<RunRecord>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ScheduledResponse<'a> {
+    name: &'a str,
+    /// unix timestamp of the next start, if any
+    next: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct StatusResponse<'a> {
+    id: &'a str,
+    run: usize,
+    state: &'a str,
+    /// position in the queue of the crawls waiting for a worker, starting at 1
+    queue_position: Option<usize>,
+    queued: usize,
+    in_flight: usize,
+    fetched: usize,
+    unchanged: usize,
+    effective: usize,
+    errors: usize,
+    bytes: u64,
+    /// seconds since the crawl started
+    elapsed: f64,
+    pages_per_sec: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct ChangesResponse<'a> {
+    id: &'a str,
+    run: usize,
+    #[serde(flatten)]
+    changes: &'a ChangesRecord,
+}
+
+#[derive(Debug, Serialize)]
+struct DiffResponse<'a> {
+    id: &'a str,
+    from: usize,
+    to: usize,
+    #[serde(flatten)]
+    diff: &'a Diff,
+}
+
+#[derive(Debug, Serialize)]
+struct PartialResponse<'a> {
+    urls: Vec<&'a str>,
+    /// cursor to be used for retrieving the following urls
+    next: usize,
+    /// true if no more urls will be found
+    finished: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct SearchResponse<'a> {
+    id: &'a str,
+    /// number of the indexed run
+    run: usize,
+    /// number of all matching documents
+    total: usize,
+    hits: Vec<Hit<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct PageRecord<'a> {
+    position: usize,
+    url: &'a str,
+}
+
+/// Schedule a crawl requested by given client, returning its id and run
+fn submit(
+    apireq: &CrawlRequest,
+    client: &Client,
+    registry: &Registry,
+    storage: &Arc<dyn Storage>,
+    settings: &Arc<Settings>,
+    scheduler: &Scheduler,
+    usage: &Usage,
+) -> std::result::Result<(String, usize), StatusCode> {
+    if shutdown::requested() {
+        // the crawl would wait for the next start
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    let seeds = apireq.seeds()?;
+    let callback_url = apireq.callback_url()?;
+    // the requested concurrency is capped by the server
+    let throttle = apireq.throttle(settings.max_throttle)?;
+
+    let id = apireq.id(&seeds);
+
+    let quota = client.quota.or(settings.quota);
+    let mut record = Record::new(id.clone(), &seeds, throttle, apireq.priority);
+    record.callback_url = callback_url;
+    record.warc = apireq.warc;
+    record.index = apireq.index;
+    record.snapshots = apireq.snapshots;
+    record.queue_priority = apireq.queue_priority;
+    record.max_pages = quota.pages_per_crawl;
+    record.max_depth = apireq.depth;
+    record.robots = apireq.robots;
+
+    let crawler = Crawler::seeded(seeds, limits::fetch, parse_links)
+        // unable to construct the crawler
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    // the crawl is reserved first, so the storage isn't accessed while holding the lock
+    let (run, journal, previous) = {
+        let mut reg = registry
+            .write()
+            // unable to acquire lock
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        let run = match reg.get(&id) {
+            // already scheduled
+            Some(Crawl {
+                status: CrawlStatus::Queued,
+                ..
+            })
+            | Some(Crawl {
+                status: CrawlStatus::Pending,
+                ..
+            }) => return Err(StatusCode::CONFLICT),
+            // re-crawl of a finished one
+            Some(crawl) => crawl.run + 1,
+            None => 1,
+        };
+
+        let journal = Journal::new(RunFiles::new(settings.run_dir(), &id, run).journal());
+
+        let previous = reg.insert(
+            id.clone(),
+            Crawl {
+                status: CrawlStatus::Queued,
+                control: crawler.control(),
+                progress: crawler.progress(),
+                journal: journal.clone(),
+                run,
+            },
+        );
+
+        (run, journal, previous)
+    };
+
+    record.run = run;
+
+    let prepared = RunFiles::new(settings.run_dir(), &id, run)
+        .clear()
+        .and_then(|()| frontier(&record, settings, &[]))
+        .and_then(|frontier| crawler.with_frontier(frontier))
+        .and_then(|crawler| prepare(crawler, &record, settings, &**storage))
+        // unable to set up the crawler
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+        .and_then(|crawler| {
+            if usage.take(&client.id, quota.crawls_per_day, &**storage) {
+                Ok(crawler)
+            } else {
+                // daily quota exceeded
+                Err(StatusCode::TOO_MANY_REQUESTS)
+            }
+        });
+
+    let crawler = match prepared {
+        Ok(crawler) => crawler,
+        Err(status) => {
+            // the previous crawl is back, as if the request never came
+            let mut reg = registry
+                .write()
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+            match previous {
+                Some(previous) => reg.insert(id, previous),
+                None => reg.remove(&id),
+            };
+
+            return Err(status);
+        }
+    };
+
+    schedule(
+        record,
+        crawler,
+        journal,
+        registry.clone(),
+        storage.clone(),
+        settings.clone(),
+        scheduler,
+    );
+
+    Ok((id, run))
+}
+
+/// Record the states the started scheduled runs ended in
+fn finish_runs(schedules: &Schedules, registry: &Registry, storage: &Arc<dyn Storage>) {
+    for (name, id, number) in schedules.running() {
+        let current = match registry.read() {
+            Ok(registry) => match registry.get(&id) {
+                Some(crawl) if crawl.run == number => match crawl.status {
+                    // still going
+                    CrawlStatus::Queued | CrawlStatus::Pending => continue,
+                    CrawlStatus::Finished => Some(RecordState::Finished),
+                    CrawlStatus::Cancelled => Some(RecordState::Cancelled),
+                    CrawlStatus::Failed => Some(RecordState::Failed),
+                },
+                // followed by another run already
+                _ => None,
+            },
+            Err(_) => return,
+        };
+
+        let state = match current {
+            Some(state) => state,
+            None => match storage.load_run(&id, number) {
+                Ok(Some(record)) => record.state,
+                // the failed runs aren't stored
+                Ok(None) => RecordState::Failed,
+                Err(e) => {
+                    warn!("Failed to load run {} of {}: {}", number, id, e);
+                    continue;
+                }
+            },
+        };
+
+        if let Some(record) = schedules.finish_run(&name, &id, number, state) {
+            if let Err(e) = storage.save_schedule(&record) {
+                error!("Unable to save schedule {}: {}", name, e);
+            }
+        }
+    }
+}
+
+/// Start the scheduled crawls which are due, recording their outcome
+fn start_due(
+    schedules: &Schedules,
+    registry: &Registry,
+    storage: &Arc<dyn Storage>,
+    settings: &Arc<Settings>,
+    scheduler: &Scheduler,
+    usage: &Usage,
+) {
+    let now = timestamp();
+
+    for due in schedules.due(now) {
+        let submitted = submit(
+            &due.crawl, &due.owner, registry, storage, settings, scheduler, usage,
+        );
+
+        let (outcome, id, run, status) = match submitted {
+            Ok((id, run)) => {
+                info!("Started run {} of {} for schedule {}", run, id, due.name);
+                (Outcome::Started, Some(id), Some(run), None)
+            }
+            Err(StatusCode::CONFLICT) => {
+                info!(
+                    "Skipping schedule {}, its previous run is still going",
+                    due.name
+                );
+                (Outcome::Skipped, None, None, None)
+            }
+            Err(status) => {
+                warn!("Schedule {} rejected with {}", due.name, status);
+                (Outcome::Rejected, None, None, Some(status.as_u16()))
+            }
+        };
+
+        let run = RunRecord {
+            time: now,
+            outcome,
+            id,
+            run,
+            status,
+            state: None,
+        };
+
+        if let Some(record) = schedules.record_run(&due.name, run) {
+            if let Err(e) = storage.save_schedule(&record) {
+                error!("Unable to save schedule {}: {}", due.name, e);
+            }
+        }
+    }
+}
+
+/// Extract links from a crawled document
+fn parse_links(base: Url, html: &str) -> Result<Urls> {
+    parse(base, html, parse_url).inspect_err(|_| metrics::parse_error())
+}
+
+/// Store given crawl and queue it to be started, so this blocks
+fn schedule(
+    record: Record,
+    crawler: Crawler,
+    journal: Journal,
+    registry: Registry,
+    storage: Arc<dyn Storage>,
+    settings: Arc<Settings>,
+    scheduler: &Scheduler,
+) {
+    let origin = record.id.clone();
+
+    info!("Scheduling crawl of {}", origin);
+
+    if let Err(e) = storage.save(&record) {
+        error!("Failed to store crawl of {}: {}", origin, e);
+    }
+
+    let priority = record.queue_priority;
+
+    // the crawl waits in the queue for a free worker
+    scheduler.submit(origin, priority, move |worker| {
+        start(
+            record, crawler, journal, registry, storage, settings, worker,
+        )
+    });
+}
+
+/// Run a queued crawl, keeping given worker busy until it's done
+fn start(
+    mut record: Record,
+    crawler: Crawler,
+    journal: Journal,
+    registry: Registry,
+    storage: Arc<dyn Storage>,
+    settings: Arc<Settings>,
+    worker: Worker,
+) {
+    // the server waits for the crawl to be stored before shutting down
+    let running = shutdown::Running::new();
+
+    tokio::spawn(future::lazy(move || {
+        let origin = record.id.clone();
+
+        info!("Starting crawl of {}", origin);
+
+        if let Some(crawl) = registry
+            .write()
+            .expect("failed to write to registry")
+            .get_mut(&origin)
+        {
+            if let CrawlStatus::Queued = crawl.status {
+                crawl.status = CrawlStatus::Pending;
+            }
+        }
+
+        let control = crawler.control();
+        let progress = crawler.progress();
+
+        let spill = settings
+            .frontier_capacity
+            .map(|capacity| (settings.frontier_dir(&origin), capacity));
+
+        let robots: Box<dyn Future<Item = Vec<Robots>, Error = Error> + Send> = if record.robots {
+            Box::new(fetch_robots(&record.seeds(), &settings))
+        } else {
+            Box::new(future::ok(Vec::new()))
+        };
+
+        let priority = record.priority;
+        let start = record.url.clone();
+        let files = RunFiles::new(settings.run_dir(), &origin, record.run);
+
+        robots
+            .and_then(move |robots| {
+                prioritize(crawler.with_robots(robots), priority, &start, spill)
+            })
+            .and_then({
+                let journal = journal.clone();
+                let storage = storage.clone();
+                let origin = origin.clone();
+                let files = files.clone();
+                let throttle = record.throttle;
+                let checkpoint_every = settings.checkpoint_every;
+                let warc = if record.warc {
+                    Some(settings.warc_path(&origin, record.run))
+                } else {
+                    None
+                };
+                let index = if record.index {
+                    IndexBuilder::new(&settings.index_path(&origin), record.run)
+                        .map_err(|e| error!("Failed to index crawl of {}: {}", origin, e))
+                        .ok()
+                } else {
+                    None
+                };
+                let blobs = if record.snapshots {
+                    Some(settings.blob_dir())
+                } else {
+                    None
+                };
+
+                move |mut crawler| {
+                    // the subscriptions end when the crawler is dropped,
+                    // so the journal and the logs are complete once all futures finish
+                    let found = batches(crawler.subscribe())
+                        .map_err(|_| err_msg("journal subscription failed"))
+                        .for_each({
+                            let journal = journal.clone();
+
+                            move |urls| {
+                                let journal = journal.clone();
+
+                                blocking(move || journal.push(urls))
+                            }
+                        });
+
+                    // allows fetching only the modified documents in the next run
+                    let pages = batches(crawler.subscribe_pages())
+                        .map_err(|_| err_msg("page subscription failed"))
+                        .fold(None, {
+                            let files = files.clone();
+
+                            move |mut log, pages| {
+                                let files = files.clone();
+
+                                blocking(move || {
+                                    files.log_pages(&mut log, &pages)?;
+                                    Ok::<_, Error>(log)
+                                })
+                            }
+                        });
+
+                    // the fetched documents are archived, indexed and stored as they come
+                    let captured = capture(&mut crawler, origin.clone(), files, warc, index, blobs);
+
+                    // allows resuming the crawl after a restart
+                    let checkpoints = crawler.checkpoints(checkpoint_every).for_each({
+                        let storage = storage.clone();
+                        let origin = origin.clone();
+
+                        move |checkpoint| {
+                            let storage = storage.clone();
+                            let origin = origin.clone();
+
+                            blocking(move || {
+                                // the found urls are kept by the journal, so only their number
+                                // is stored
+                                let checkpoint = CheckpointRecord::from(&checkpoint);
+
+                                if let Err(e) = storage.save_checkpoint(&origin, &checkpoint) {
+                                    error!("Failed to store checkpoint of {}: {}", origin, e);
+                                }
+
+                                Ok(())
+                            })
+                        }
+                    });
+
+                    let (sink, stream) = crawler.split();
+
+                    stream
+                        .buffer_unordered(throttle)
+                        .forward(sink)
+                        .and_then(|(stream, sink)| {
+                            let mut crawler = stream.into_inner().reunite(sink)?;
+
+                            // cancelled by the shutdown, to be resumed on the next start
+                            if shutdown::interrupting()
+                                && crawler.control().state() == State::Cancelled
+                            {
+                                Ok(Some(crawler.checkpoint()?))
+                            } else {
+                                Ok(None)
+                            }
+                        })
+                        .join5(
+                            found,
+                            checkpoints.map_err(|_| err_msg("checkpoint subscription failed")),
+                            pages,
+                            captured,
+                        )
+                        .then(move |result| {
+                            // the next queued crawl can start
+                            drop(worker);
+                            result
+                        })
+                }
+            })
+            .then(move |result| {
+                // the logs are sorted into the results off the reactor
+                blocking(move || {
+                    // stored by the end of this closure
+                    let _running = running;
+
+                    journal.close();
+
+                    // the record stays pending, so the crawl continues from the checkpoint
+                    if let Ok((Some(checkpoint), ..)) = result {
+                        let checkpoint = CheckpointRecord::from(&checkpoint);
+
+                        if let Err(e) = storage.save_checkpoint(&origin, &checkpoint) {
+                            error!("Failed to store checkpoint of {}: {}", origin, e);
+                        }
+
+                        info!(
+                            "Interrupted crawling domain {}, retrieved {} urls so far",
+                            origin,
+                            journal.len()
+                        );
+
+                        return Ok(None);
+                    }
+
+                    // the journal is complete at this point
+                    let found = journal.len();
+
+                    let (status, state, index, failure) = match result {
+                        Ok((_, _, _, _, index)) => {
+                            if control.state() == State::Cancelled {
+                                info!(
+                                    "Cancelled crawling domain {}, retrieved {} urls",
+                                    origin, found
+                                );
+
+                                (CrawlStatus::Cancelled, RecordState::Cancelled, index, None)
+                            } else {
+                                info!(
+                                    "Finished crawling domain {}, retrieved {} urls",
+                                    origin, found
+                                );
+
+                                (CrawlStatus::Finished, RecordState::Finished, index, None)
+                            }
+                        }
+                        Err(e) => {
+                            error!("Failed crawling domain {}: {}", origin, e);
+
+                            // the urls found so far remain available
+                            (CrawlStatus::Failed, RecordState::Failed, None, Some(e))
+                        }
+                    };
+
+                    let stats = progress.stats();
+
+                    // a failed run is incomplete, so it's not compared with the other ones
+                    let previous = match record.run {
+                        _ if failure.is_some() => None,
+                        1 => None,
+                        run => match storage.load_run(&origin, run - 1) {
+                            Ok(previous) => previous
+                                .map(|_| RunFiles::new(settings.run_dir(), &origin, run - 1)),
+                            Err(e) => {
+                                warn!("Failed to load previous run of {}: {}", origin, e);
+                                None
+                            }
+                        },
+                    };
+
+                    // the documents that weren't modified are taken from the previous run
+                    let (unchanged, sorted) = match files.finish(previous.as_ref()) {
+                        Ok(unchanged) => (unchanged, true),
+                        Err(e) => {
+                            // the logs are kept
+                            error!("Failed to sort results of {}: {}", origin, e);
+                            (HashSet::new(), false)
+                        }
+                    };
+
+                    // like the comparison, the index is built only from the complete runs
+                    if let Some(index) = index {
+                        update_index(&origin, index, &unchanged, &settings);
+                    }
+
+                    record.finish(state, &stats, found);
+
+                    if failure.is_none() {
+                        let compared = previous.as_ref().map(|files| (record.run - 1, files));
+
+                        if let Err(e) = files.compare(compared) {
+                            error!("Failed to compare run {} of {}: {}", record.run, origin, e);
+                        }
+
+                        if let Err(e) = storage.save_run(&record) {
+                            error!("Failed to store run {} of {}: {}", record.run, origin, e);
+                        }
+                    }
+
+                    if let Err(e) = storage.save(&record) {
+                        error!("Failed to store crawl of {}: {}", origin, e);
+                    }
+
+                    if let Err(e) = storage.remove_checkpoint(&origin) {
+                        error!("Failed to remove checkpoint of {}: {}", origin, e);
+                    }
+
+                    // the spilled urls were kept for the checkpoints
+                    let dir = settings.frontier_dir(&origin);
+
+                    if dir.exists() {
+                        if let Err(e) = fs::remove_dir_all(&dir) {
+                            error!("Failed to remove queue of {}: {}", origin, e);
+                        }
+                    }
+
+                    // the logs were sorted into the other files of the run
+                    if sorted {
+                        if let Err(e) = files.remove_logs() {
+                            error!("Failed to remove logs of {}: {}", origin, e);
+                        }
+                    }
+
+                    let state = {
+                        let mut reg = registry.write().expect("failed to write to registry");
+
+                        match reg.get_mut(&origin) {
+                            Some(crawl) => {
+                                crawl.status = status;
+                                crawl.state()
+                            }
+                            None => return Ok(None),
+                        }
+                    };
+
+                    let callback = match record.callback_url.as_ref().map(|url| Url::parse(url)) {
+                        Some(Ok(callback)) => callback,
+                        Some(Err(e)) => {
+                            error!("Invalid callback url of {}: {}", origin, e);
+                            return Ok(None);
+                        }
+                        None => return Ok(None),
+                    };
+
+                    let summary = Summary::new(
+                        &origin,
+                        record.run,
+                        state,
+                        failure.map(|e| e.to_string()),
+                        &stats,
+                    );
+
+                    Ok(Some(webhook::notify(
+                        callback,
+                        &summary,
+                        settings.webhook_secret.as_deref(),
+                    )))
+                })
+            })
+            .map(|notification| {
+                if let Some(notification) = notification {
+                    tokio::spawn(notification);
+                }
+            })
+    }));
+}
+
+/// Archive, index and store the fetched documents of given crawler, as requested
+///
+/// The stored documents are logged with the other files of the run, see `RunFiles`.
+
+fn capture(
+    crawler: &mut Crawler,
+    origin: String,
+    files: RunFiles,
+    warc: Option<PathBuf>,
+    index: Option<IndexBuilder>,
+    blobs: Option<PathBuf>,
+) -> Box<dyn Future<Item = Option<IndexBuilder>, Error = Error> + Send> {
+    if warc.is_none() && index.is_none() && blobs.is_none() {
+        // nothing needs the responses
+        return Box::new(future::ok(None));
+    }
+
+    let (writer, store) = match (
+        warc.map(|path| WarcWriter::open(&path)).transpose(),
+        blobs.map(BlobStore::new).transpose(),
+    ) {
+        (Ok(writer), Ok(store)) => (writer, store),
+        (Err(e), _) | (_, Err(e)) => return Box::new(future::err(e)),
+    };
+
+    Box::new(
+        batches(crawler.subscribe_responses())
+            .map_err(|_| err_msg("response subscription failed"))
+            .fold(
+                (writer, index, store, None),
+                move |(mut writer, mut index, store, mut log), responses| {
+                    let origin = origin.clone();
+                    let files = files.clone();
+
+                    // written off the reactor
+                    blocking(move || {
+                        for response in responses {
+                            if let Some(writer) = writer.as_mut() {
+                                if let Err(e) = writer.write(&response) {
+                                    error!("Failed to archive {}: {}", origin, e);
+                                }
+                            }
+
+                            if let Some(builder) = index.as_mut() {
+                                if let Some(document) = Document::from_response(&response) {
+                                    if let Err(e) = builder.add(&document) {
+                                        error!("Failed to index {}: {}", response.effective, e);
+                                    }
+                                }
+                            }
+
+                            if let Some(store) = store.as_ref() {
+                                let stored = store
+                                    .put(&response.body)
+                                    .and_then(|digest| files.log_blob(&mut log, &response, digest));
+
+                                if let Err(e) = stored {
+                                    error!("Failed to store {}: {}", response.effective, e);
+                                }
+                            }
+                        }
+
+                        Ok::<_, Error>((writer, index, store, log))
+                    })
+                },
+            )
+            .map(|(_, index, _, _)| index),
+    )
+}
+
+/// Complete the search index of a crawl run and store it
+///
+/// The documents that weren't modified since the previous run are taken from its index.
+
+fn update_index(
+    id: &str,
+    mut index: IndexBuilder,
+    unchanged: &HashSet<String>,
+    settings: &Settings,
+) {
+    let path = settings.index_path(id);
+
+    let previous = Index::cached(&path).unwrap_or_else(|e| {
+        warn!("Failed to load search index of {}: {}", id, e);
+        None
+    });
+
+    if let Some(previous) = previous.as_ref() {
+        if !unchanged.is_empty() {
+            if let Err(e) = index.carry(previous, unchanged) {
+                warn!("Failed to carry search index of {}: {}", id, e);
+            }
+        }
+    }
+
+    if let Err(e) = index.finish(previous.map(|previous| previous.run)) {
+        error!("Failed to store search index of {}: {}", id, e);
+    }
+}
+
+/// Fetch the robots.txt rules of the scoped seeds' sites for the configured user agent
+///
+/// The sites without a successfully fetched robots.txt are crawled without restrictions.
+
+fn fetch_robots(
+    seeds: &[Seed],
+    settings: &Settings,
+) -> impl Future<Item = Vec<Robots>, Error = Error> + Send {
+    /// product token matched against the robots.txt groups, unless a user agent is configured
+    const DEFAULT_ROBOTS_AGENT: &str = "webcrawl";
+
+    let agent = settings
+        .user_agent
+        .clone()
+        .unwrap_or_else(|| DEFAULT_ROBOTS_AGENT.to_owned());
+    let mut origins = HashSet::new();
+
+    let urls = seeds
+        .iter()
+        .filter(|seed| seed.scope && origins.insert(seed.url.origin()))
+        .filter_map(|seed| seed.url.join("/robots.txt").ok())
+        .collect::<Vec<_>>();
+
+    future::join_all(urls.into_iter().map(move |url| {
+        let agent = agent.clone();
+
+        limits::fetch_conditional(url.clone(), Validators::default()).map(move |fetched| {
+            match fetched {
+                Some(Fetched::Document { buffer, status, .. }) if (200..300).contains(&status) => {
+                    Some(Robots::parse(&url, buffer.as_str(), &agent))
+                }
+                _ => None,
+            }
+        })
+    }))
+    .map(|robots| robots.into_iter().flatten().collect())
+}
+
+/// Order the crawler queue by the sitemap priorities, if requested
+///
+/// A missing or invalid sitemap results in all urls having the same priority.
+/// The queue spills to given directory beyond given capacity.
+
+fn prioritize(
+    crawler: Crawler,
+    priority: CrawlPriority,
+    start: &str,
+    spill: Option<(PathBuf, usize)>,
+) -> Box<dyn Future<Item = Crawler, Error = Error> + Send> {
+    if priority != CrawlPriority::Sitemap {
+        return Box::new(future::ok(crawler));
+    }
+
+    let start = match Url::parse(start) {
+        Ok(start) => start,
+        Err(e) => return Box::new(future::err(e.into())),
+    };
+
+    let sitemap = match start.join("/sitemap.xml") {
+        Ok(sitemap) => sitemap,
+        Err(e) => return Box::new(future::err(e.into())),
+    };
+
+    let origin = start.origin().ascii_serialization();
+
+    Box::new(
+        // fetched like the pages, so the request is counted in the metrics
+        limits::fetch_conditional(sitemap, Validators::default())
+            .map(move |fetched| {
+                let scores = match fetched {
+                    Some(Fetched::Document { buffer, status, .. })
+                        if (200..300).contains(&status) =>
+                    {
+                        parse_sitemap(buffer.as_str()).unwrap_or_default()
+                    }
+                    _ => Default::default(),
+                };
+
+                info!("Found {} urls in the sitemap of {}", scores.len(), origin);
+
+                scores
+            })
+            .and_then(|scores| {
+                crawler.with_frontier(priority_frontier(Priority::Scores(scores), spill)?)
+            }),
+    )
+}
+
+/// Return the queue of given crawl, bounded by the frontier capacity
+///
+/// The spilled urls are kept for the checkpoints, and the leftovers of an interrupted crawl
+/// are removed, except for the given segments of its checkpoint.
+/// The sitemap queue is replaced once the sitemap is fetched, see `prioritize`.
+
+fn frontier(
+    record: &Record,
+    settings: &Settings,
+    segments: &[PathBuf],
+) -> Result<Box<dyn Frontier>> {
+    let dir = settings.frontier_dir(&record.id);
+
+    if dir.exists() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+
+            if !segments.contains(&path) {
+                fs::remove_file(path)?;
+            }
+        }
+    }
+
+    let spill = settings.frontier_capacity.map(|capacity| (dir, capacity));
+
+    if record.priority != CrawlPriority::Fifo {
+        // the sitemap urls are equal until the sitemap is fetched
+        let priority = record
+            .priority
+            .priority()
+            .unwrap_or_else(|| Priority::Scores(HashMap::new()));
+
+        return Ok(Box::new(priority_frontier(priority, spill)?));
+    }
+
+    Ok(match spill {
+        Some((dir, capacity)) => Box::new(DiskFrontier::new(dir, capacity)?.with_kept_segments()),
+        None => Box::new(MemoryFrontier::new()),
+    })
+}
+
+/// Create a queue of given priority, spilling to given directory beyond given capacity
+fn priority_frontier(
+    priority: Priority,
+    spill: Option<(PathBuf, usize)>,
+) -> Result<PriorityFrontier> {
+    let frontier = PriorityFrontier::new(priority);
+
+    Ok(match spill {
+        Some((dir, capacity)) => frontier.with_spill(dir, capacity)?.with_kept_segments(),
+        None => frontier,
+    })
+}
+
+/// Apply the crawl options and the server settings to a new crawler of given crawl
+fn prepare(
+    crawler: Crawler,
+    record: &Record,
+    settings: &Settings,
+    storage: &dyn Storage,
+) -> Result<Crawler> {
+    /// false positive probability of the Bloom filter
+    const BLOOM_FALSE_POSITIVE: f64 = 0.001;
+
+    // the documents of the previous run are fetched only if they were modified
+    let history = match record.run {
+        1 => Vec::new(),
+        run => match storage.load_run(&record.id, run - 1)? {
+            Some(_) => RunFiles::new(settings.run_dir(), &record.id, run - 1).history()?,
+            None => Vec::new(),
+        },
+    };
+
+    // the results are collected by the journal
+    let crawler = crawler
+        .with_history(limits::fetch_conditional, history)
+        .without_result();
+
+    let crawler = match record.max_pages {
+        Some(limit) => crawler.with_page_limit(limit),
+        None => crawler,
+    };
+
+    let crawler = match record.max_depth {
+        Some(depth) => crawler.with_max_depth(depth),
+        None => crawler,
+    };
+
+    Ok(if let Some(capacity) = settings.bloom_filter {
+        crawler.with_seen(BloomSeen::new(capacity, BLOOM_FALSE_POSITIVE))
+    } else {
+        crawler
+    })
+}
+
+/// A crawl interrupted by a restart, ready to be scheduled again
+type Interrupted = (Record, Crawler, Journal);
+
+/// Prepare given interrupted crawl to continue from its last checkpoint, or from the start
+/// if there's none
+fn reload(
+    record: &Record,
+    files: &RunFiles,
+    storage: &dyn Storage,
+    settings: &Settings,
+) -> Result<(Crawler, Journal)> {
+    let checkpoint = storage.load_checkpoint(&record.id).unwrap_or_else(|e| {
+        warn!("Failed to load checkpoint of {}: {}", record.id, e);
+        None
+    });
+
+    let resumed = checkpoint.and_then(|checkpoint| {
+        info!("Resuming crawl of {} from checkpoint", record.id);
+
+        let found = checkpoint.found;
+
+        (|| {
+            // the urls found after the checkpoint will be found again
+            let journal = Journal::resume(files.journal(), found)?;
+            let (effective, _) = journal.since(0, found)?.unwrap_or_default();
+
+            let checkpoint = Checkpoint {
+                seeds: record.seeds(),
+                effective: effective.into_iter().collect(),
+                ..Checkpoint::from(&checkpoint)
+            };
+
+            let frontier = frontier(record, settings, &checkpoint.segments)?;
+            let crawler = Crawler::resume_with(checkpoint, frontier, limits::fetch, parse_links)?;
+
+            Ok((crawler, journal))
+        })()
+        // e.g. the spilled urls are gone
+        .map_err(|e: Error| warn!("Failed to resume crawl of {}: {}", record.id, e))
+        .ok()
+    });
+
+    let (crawler, journal) = match resumed {
+        Some(resumed) => resumed,
+        None => {
+            info!("Restarting crawl of {}", record.id);
+
+            files.clear()?;
+
+            let crawler = Crawler::seeded(record.seeds(), limits::fetch, parse_links)?
+                .with_frontier(frontier(record, settings, &[])?)?;
+
+            (crawler, Journal::new(files.journal()))
+        }
+    };
+
+    // the found urls are dropped once the crawler knows them
+    let crawler = prepare(crawler, record, settings, storage)?;
+
+    Ok((crawler, journal))
+}
+
+/// Load crawls from the storage
+///
+/// Finished crawls are returned in the registry, and the interrupted ones
+/// are prepared to continue from their last checkpoint. The interrupted crawls that can't
+/// be continued are marked as failed.
+
+fn restore(
+    storage: &dyn Storage,
+    settings: &Settings,
+) -> Result<(HashMap<String, Crawl>, Vec<Interrupted>)> {
+    let mut crawls = HashMap::new();
+    let mut interrupted = Vec::new();
+
+    for mut record in storage.load()? {
+        let files = RunFiles::new(settings.run_dir(), &record.id, record.run);
+
+        let status = match record.state {
+            RecordState::Finished => CrawlStatus::Finished,
+            RecordState::Cancelled => CrawlStatus::Cancelled,
+            RecordState::Failed => CrawlStatus::Failed,
+            RecordState::Pending => match reload(&record, &files, storage, settings) {
+                Ok((crawler, journal)) => {
+                    crawls.insert(
+                        record.id.clone(),
+                        Crawl {
+                            status: CrawlStatus::Queued,
+                            control: crawler.control(),
+                            progress: crawler.progress(),
+                            journal: journal.clone(),
+                            run: record.run,
+                        },
+                    );
+
+                    interrupted.push((record, crawler, journal));
+
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Failed to restart crawl of {}: {}", record.id, e);
+
+                    // so it's not retried on every start
+                    record.finish(RecordState::Failed, &Stats::default(), 0);
+
+                    if let Err(e) = storage.save(&record) {
+                        warn!("Failed to store crawl of {}: {}", record.id, e);
+                    }
+
+                    if let Err(e) = storage.remove_checkpoint(&record.id) {
+                        warn!("Failed to remove checkpoint of {}: {}", record.id, e);
+                    }
+
+                    CrawlStatus::Failed
+                }
+            },
+        };
+
+        let stats = record.stats.as_ref().map(Stats::from).unwrap_or_default();
+
+        info!("Restored crawl of {}, {} urls", record.id, record.found);
+
+        crawls.insert(
+            record.id,
+            Crawl {
+                status,
+                control: Control::new(),
+                progress: Progress::detached(&stats),
+                journal: Journal::restore(files.journal(), record.found),
+                run: record.run,
+            },
+        );
+    }
+
+    Ok((crawls, interrupted))
+}
+
+/// Set up the network policy, the request options and the connection limit of the crawls
+fn install_fetching(settings: &Settings) -> Result<()> {
+    NetworkPolicy::new()
+        .with_allowed(settings.allowed_cidrs.iter().cloned())
+        .with_denied(settings.denied_cidrs.iter().cloned())
+        .install()?;
+
+    let mut options = FetchOptions::new();
+
+    if let Some(user_agent) = &settings.user_agent {
+        options = options.with_user_agent(user_agent)?;
+    }
+
+    if let Some(timeout) = settings.timeout {
+        options = options.with_timeout(timeout);
+    }
+
+    if let Some(timeout) = settings.connect_timeout {
+        options = options.with_connect_timeout(timeout);
+    }
+
+    options.install()?;
+
+    limits::limit_connections(settings.max_connections);
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args = setup_cli().get_matches();
+
+    if let ("config", Some(_)) = args.subcommand() {
+        // the only subcommand of `config` is `check`
+        match Settings::from_args(&args).and_then(|settings| settings.to_config().to_toml()) {
+            Ok(config) => print!("{}", config),
+            Err(e) => {
+                eprintln!("Invalid configuration: {}", e);
+                process::exit(1);
+            }
+        }
+
+        return Ok(());
+    }
+
+    if let ("crawl", Some(crawl_args)) = args.subcommand() {
+        let result = Settings::from_args(&args).and_then(|settings| {
+            let env =
+                env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, &settings.log);
+            env_logger::Builder::from_env(env).init();
+
+            install_fetching(&settings)?;
+
+            let options = crawl::Options::from_args(crawl_args, &settings)?;
+            crawl::run(options, &settings)
+        });
+
+        if let Err(e) = result {
+            eprintln!("Crawl failed: {}", e);
+            process::exit(1);
+        }
+
+        return Ok(());
+    }
+
+    let settings = Arc::new(Settings::from_args(&args)?);
+
+    let env = env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, &settings.log);
+    env_logger::Builder::from_env(env).init();
+
+    if settings.api_keys.is_empty() {
+        warn!("No API keys configured, the API is open to anyone who can reach it");
+    }
+
+    install_fetching(&settings)?;
+
+    let storage: Arc<dyn Storage> = match &settings.data_dir {
+        Some(dir) => Arc::new(FileStorage::new(dir)?),
+        None => Arc::new(MemoryStorage::default()),
+    };
+
+    let (crawls, interrupted) = restore(&*storage, &settings)?;
+
+    // storage for the results of the crawl operation
+    let registry: Registry = Arc::new(RwLock::new(crawls));
+
+    // runs the crawls, queueing the ones without a free worker
+    let scheduler = Scheduler::new(settings.max_crawls);
+    // crawls scheduled by the clients, for the daily quotas
+    let usage = storage.load_usage().unwrap_or_else(|e| {
+        // the crawls are counted from zero
+        warn!("Failed to load the quota usage: {}", e);
+        None
+    });
+
+    let usage = Arc::new(Usage::new(usage));
+
+    // the crawls started on their schedules, missing the runs due while the server was down
+    let schedules = Schedules::new(storage.load_schedules()?, timestamp());
+
+    // stops the server on SIGINT or SIGTERM
+    let (stop, stopped) = oneshot::channel();
+
+    let resume = {
+        let registry = registry.clone();
+        let storage = storage.clone();
+        let settings = settings.clone();
+        let scheduler = scheduler.clone();
+        let usage = usage.clone();
+        let schedules = schedules.clone();
+
+        future::lazy(move || {
+            tokio::spawn(shutdown::on_signal(
+                stop,
+                registry.clone(),
+                scheduler.clone(),
+                settings.shutdown_grace,
+            ));
+
+            let resumed = blocking({
+                let registry = registry.clone();
+                let storage = storage.clone();
+                let settings = settings.clone();
+                let scheduler = scheduler.clone();
+
+                move || {
+                    for (record, crawler, journal) in interrupted {
+                        schedule(
+                            record,
+                            crawler,
+                            journal,
+                            registry.clone(),
+                            storage.clone(),
+                            settings.clone(),
+                            &scheduler,
+                        );
+                    }
+
+                    Ok(())
+                }
+            });
+
+            tokio::spawn(resumed);
+
+            let ticker = Interval::new_interval(Duration::from_secs(1))
+                .take_while(|_| Ok(!shutdown::requested()))
+                .for_each(move |_| {
+                    let schedules = schedules.clone();
+                    let registry = registry.clone();
+                    let storage = storage.clone();
+                    let settings = settings.clone();
+                    let scheduler = scheduler.clone();
+                    let usage = usage.clone();
+
+                    // the runs are stored, and the due ones prepared, off the reactor
+                    blocking(move || {
+                        finish_runs(&schedules, &registry, &storage);
+
+                        start_due(
+                            &schedules, &registry, &storage, &settings, &scheduler, &usage,
+                        );
+
+                        Ok(())
+                    })
+                })
+                .map_err(|e| error!("schedule timer error: {}", e));
+
+            tokio::spawn(ticker);
+
+            Ok(())
+        })
+    };
+
+    let addr = settings.address;
+    let tls = settings.tls.as_ref().map(Tls::acceptor).transpose()?;
+
+    let api = move || {
+        let registry = registry.clone();
+        let storage = storage.clone();
+        let settings = settings.clone();
+        let scheduler = scheduler.clone();
+        let usage = usage.clone();
+        let schedules = schedules.clone();
+
+        move |req: Request<Body>|
+        -> Box<Future<Item=Response<Body>, Error=hyper::Error> + Send> {
+            let path = req.uri().path();
+            let method = req.method();
+
+            // the probes of the orchestrators come without a key
+            match (method, path) {
+                (&Method::GET, "/healthz") => {
+                    return Box::new(future::ok(health::liveness(&registry)));
+                }
+                (&Method::GET, "/readyz") => {
+                    let response = health::readiness(&registry, &*storage, &scheduler);
+                    return Box::new(future::ok(response));
+                }
+                _ => (),
+            }
+
+            // reading requires the `read` scope, anything else the `write` one
+            let scope = if method == Method::GET { Scope::Read } else { Scope::Write };
+
+            let client = match settings.api_keys.authorize(req.headers(), scope) {
+                Ok(client) => client,
+                Err(status) => return Box::new(future::ok(get_denied(status))),
+            };
+
+            let response = match (method, path) {
+                (&Method::GET, "/metrics") => {
+                    let result = metrics::render(&registry)
+                        .map(Body::from)
+                        .map_err(|e| {
+                            error!("Failed to render metrics: {}", e);
+                            StatusCode::INTERNAL_SERVER_ERROR
+                        });
+
+                    get_result_as("text/plain; version=0.0.4", result)
+                }
+                (&Method::GET, "/api/domains") => {
+
+                    let result = (|| {
+                        let registry = registry.read()
+                            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                        let names = registry.keys().collect::<Vec<_>>();
+                        let resp = serde_json::to_string(&names)
+                            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                        Ok(Body::from(resp))
+                    })();
+
+                    get_result(result)
+                }
+                (&Method::GET, "/api/results/count") => {
+                    let request = (|| {
+                        let uri = req.uri().to_string();
+                        let url = Url::parse("http://dummy")
+                            .and_then(|url| url.join(&uri))
+                            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                        let id = find_query_param(&url, "id")?.into_owned();
+                        let filter = Filter::from_query(&url)?;
+
+                        let run = {
+                            let registry = registry.read()
+                                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                            let crawl = registry.get(&id)
+                                .ok_or_else(|| StatusCode::NOT_FOUND)?;
+
+                            if !crawl.status.done() {
+                                return Err(StatusCode::ACCEPTED);
+                            }
+
+                            crawl.run
+                        };
+
+                        Ok((id, filter, run))
+                    })();
+
+                    let settings = settings.clone();
+
+                    // the results are read from disk
+                    let process = future::result(request)
+                        .and_then(move |(id, filter, run)| blocking(move || {
+                            let rows = RunFiles::new(settings.run_dir(), &id, run).rows()
+                                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                            let mut count = 0;
+
+                            for row in rows {
+                                let row = row.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                                if filter.matches(&row) {
+                                    count += 1;
+                                }
+                            }
+
+                            let resp = {
+                                let mut h = HashMap::with_capacity(1);
+                                h.insert(id.as_str(), count);
+                                h
+                            };
+
+                            let resp = serde_json::to_string(&resp)
+                                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                            Ok(Body::from(resp))
+                        }))
+                        .then(|result| Ok(get_result(result)));
+
+                    return Box::new(process)
+                }
+                (&Method::GET, "/api/results") => {
+                    let uri = req.uri().to_string();
+                    let accept = req.headers().get("Accept")
+                        .and_then(|accept| accept.to_str().ok())
+                        .map(str::to_owned);
+
+                    let registry = registry.clone();
+                    let settings = settings.clone();
+
+                    // the results are read from disk
+                    let process = blocking(move || {
+                        let url = Url::parse("http://dummy")
+                            .and_then(|url| url.join(&uri))
+                            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                        let id = find_query_param(&url, "id")?;
+
+                        let id: &str = id.borrow();
+
+                        /// number of the rows returned by default
+                        const RESULTS_LIMIT: usize = 1000;
+
+                        if let Ok(since) = find_query_param(&url, "since") {
+                            let since = since.parse::<usize>()
+                                .map_err(|_| StatusCode::BAD_REQUEST)?;
+                            // the cursor takes the place of the offset
+                            let page = Page::from_query(&url, RESULTS_LIMIT)?;
+
+                            let (journal, done) = {
+                                let registry = registry.read()
+                                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                                let crawl = registry.get(id)
+                                    .ok_or_else(|| StatusCode::NOT_FOUND)?;
+
+                                (crawl.journal.clone(), crawl.status.done())
+                            };
+
+                            let (urls, length) = journal.since(since, page.limit)
+                                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                                // cursor past the end of the results
+                                .ok_or_else(|| StatusCode::BAD_REQUEST)?;
+                            let next = since + urls.len();
+
+                            let resp = PartialResponse {
+                                urls: urls.iter().map(Url::as_str).collect(),
+                                next,
+                                finished: done && next == length,
+                            };
+
+                            let resp = serde_json::to_string(&resp)
+                                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                            return Ok((Body::from(resp), "application/json", Vec::new()));
+                        }
+
+                        let format = match find_query_param(&url, "format") {
+                            Ok(format) => Format::from_name(&format)
+                                // unknown format
+                                .ok_or_else(|| StatusCode::BAD_REQUEST)?,
+                            Err(_) => accept.as_ref()
+                                .and_then(|accept| Format::from_accept(accept))
+                                .unwrap_or(Format::Json),
+                        };
+
+                        let filter = Filter::from_query(&url)?;
+                        let page = Page::from_query(&url, RESULTS_LIMIT)?;
+
+                        // the lock isn't held while the body is built
+                        let run = {
+                            let registry = registry.read()
+                                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                            let crawl = registry.get(id)
+                                .ok_or_else(|| StatusCode::NOT_FOUND)?;
+
+                            if !crawl.status.done() {
+                                return Err(StatusCode::ACCEPTED);
+                            }
+
+                            crawl.run
+                        };
+
+                        let (rows, total) = if format == Format::Warc {
+                            // the archive is neither filtered nor paginated
+                            (Vec::new(), 0)
+                        } else {
+                            let rows = RunFiles::new(settings.run_dir(), id, run).rows()
+                                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                            page.select(rows, &filter)
+                                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                        };
+
+                        let body = match format {
+                            Format::Json => {
+                                let urls = rows.iter().map(|row| row.url.as_str()).collect::<Vec<_>>();
+                                let resp = serde_json::to_string(&urls)
+                                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                                Body::from(resp)
+                            }
+                            Format::Text => {
+                                Body::from(rows.iter().map(|row| format!("{}\n", row.url)).collect::<String>())
+                            }
+                            Format::Csv => {
+                                let csv = std::iter::once(csv_header().to_owned())
+                                    .chain(rows.iter().map(|row| row.to_csv()))
+                                    .collect::<String>();
+
+                                Body::from(csv)
+                            }
+                            Format::Ndjson => {
+                                Body::from(rows.iter().map(|row| row.to_ndjson()).collect::<String>())
+                            }
+                            Format::Warc => {
+                                /// size of the chunks the archive is streamed in
+                                const WARC_CHUNK: usize = 64 * 1024;
 
-use std::str::from_utf8;
+                                let path = settings.warc_path(id, run);
 
-use env_logger;
+                                let file = fs::File::open(&path)
+                                    // not archived
+                                    .map_err(|_| StatusCode::NOT_FOUND)?;
 
-mod cli;
-mod error;
-mod util;
+                                // read off the reactor
+                                let mut file = tokio::fs::File::from_std(file);
 
-#[derive(Debug)]
-enum CrawlStatus {
-    Pending,
-    Finished(Urls),
-}
+                                let chunks = stream::poll_fn(move || {
+                                    let mut chunk = vec![0; WARC_CHUNK];
+                                    let len = futures::try_ready!(file.poll_read(&mut chunk));
 
-type Registry = Arc<RwLock<HashMap<String, CrawlStatus>>>;
+                                    if len == 0 {
+                                        return Ok(Async::Ready(None));
+                                    }
 
-fn schedule(url: Url, throttle: usize, registry: Registry) {
-    let origin = url.origin().ascii_serialization();
+                                    chunk.truncate(len);
 
-    tokio::spawn(future::lazy(move || {
-        info!("Scheduling crawl of {}", url);
+                                    Ok::<_, io::Error>(Async::Ready(Some(chunk)))
+                                });
 
-        let crawler = Crawler::new(url, fetch, |base, html| parse(base, html, parse_url)).unwrap();
+                                return Ok((Body::wrap_stream(chunks), format.content_type(), Vec::new()));
+                            }
+                        };
 
-        let (sink, stream) = crawler.split();
+                        let mut headers = vec![("X-Total-Count", total.to_string())];
 
-        stream
-            .buffer_unordered(throttle)
-            .forward(sink)
-            .and_then(|(stream, sink)| {
-                let crawler = stream.into_inner().reunite(sink)?;
+                        if let Some(link) = page.next_link(&url, total) {
+                            headers.push(("Link", link));
+                        }
 
-                Ok(crawler.into_result())
-            })
-            .and_then(move |urls| {
-                let len = urls.len();
-                let mut reg = registry.write().expect("failed to write to registry");
-                info!(
-                    "Finished crawling domain {}, retrieved {} urls",
-                    origin, len
-                );
+                        Ok((body, format.content_type(), headers))
+                    })
+                    .then(|result| Ok(match result {
+                        Ok((body, content_type, headers)) => get_result_with(content_type, &headers, Ok(body)),
+                        Err(status) => get_result(Err(status)),
+                    }));
 
-                reg.insert(origin, CrawlStatus::Finished(urls));
+                    return Box::new(process)
+                }
+                (&Method::GET, "/api/results/seeds") => {
+                    let request = (|| {
+                        let uri = req.uri().to_string();
+                        let url = Url::parse("http://dummy")
+                            .and_then(|url| url.join(&uri))
+                            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-                Ok(())
-            })
-            .map_err(|_| ())
-    }));
-}
+                        let id = find_query_param(&url, "id")?.into_owned();
 
-fn main() -> Result<()> {
-    let env = env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "webcrawl=info");
-    env_logger::Builder::from_env(env).init();
+                        let run = {
+                            let registry = registry.read()
+                                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    // storage for the results of the crawl operation
-    let registry: Arc<RwLock<HashMap<String, CrawlStatus>>> = Arc::new(RwLock::new(HashMap::new()));
+                            let crawl = registry.get(&id)
+                                .ok_or_else(|| StatusCode::NOT_FOUND)?;
 
-    let api = move || {
-        let registry = registry.clone();
+                            if !crawl.status.done() {
+                                return Err(StatusCode::ACCEPTED);
+                            }
 
-        move |req: Request<Body>|
-        -> Box<Future<Item=Response<Body>, Error=hyper::Error> + Send> {
-            let path = req.uri().path();
-            let method = req.method();
+                            crawl.run
+                        };
 
-            let response = match (method, path) {
-                (&Method::GET, "/api/domains") => {
+                        Ok((id, run))
+                    })();
 
-                    let result = (|| {
-                        let registry = registry.read()
-                            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+                    let settings = settings.clone();
 
-                        let names = registry.keys().collect::<Vec<_>>();
-                        let resp = serde_json::to_string(&names)
-                            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+                    // the results are read from disk
+                    let process = future::result(request)
+                        .and_then(move |(id, run)| blocking(move || {
+                            let urls = RunFiles::new(settings.run_dir(), &id, run).urls_by_seed()
+                                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-                        Ok(Body::from(resp))
-                    })();
+                            let resp = serde_json::to_string(&urls)
+                                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-                    get_result(result)
+                            Ok(Body::from(resp))
+                        }))
+                        .then(|result| Ok(get_result(result)));
+
+                    return Box::new(process)
                 }
-                (&Method::GET, "/api/results/count") => {
+                (&Method::GET, "/api/results/stream") => {
                     let result = (|| {
                         let uri = req.uri().to_string();
                         let url = Url::parse("http://dummy")
@@ -171,125 +2508,418 @@ fn main() -> Result<()> {
 
                         let id = find_query_param(&url, "id")?;
 
+                        let since = if let Ok(since) = find_query_param(&url, "since") {
+                            since.parse::<usize>()
+                                .map_err(|_| StatusCode::BAD_REQUEST)?
+                        } else if let Some(last) = req.headers().get("Last-Event-ID") {
+                            // reconnecting event source, continue after the last event
+                            last.to_str().ok()
+                                .and_then(|last| last.parse::<usize>().ok())
+                                .ok_or_else(|| StatusCode::BAD_REQUEST)? + 1
+                        } else {
+                            0
+                        };
+
+                        let sse = match find_query_param(&url, "format") {
+                            Ok(ref format) if format == "sse" => true,
+                            Ok(ref format) if format == "ndjson" => false,
+                            Ok(_) => return Err(StatusCode::BAD_REQUEST),
+                            Err(_) => req.headers().get("Accept")
+                                .and_then(|accept| accept.to_str().ok())
+                                .is_some_and(|accept| accept.contains("text/event-stream")),
+                        };
+
                         let registry = registry.read()
                             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
                         let id: &str = id.borrow();
 
-                        let urls = registry.get(id)
+                        let crawl = registry.get(id)
                             .ok_or_else(|| StatusCode::NOT_FOUND)?;
 
-                        if let CrawlStatus::Finished(urls) = urls {
-                            let count = urls.len();
+                        let pages = crawl.journal.subscribe(since)
+                            // cursor past the end of the results
+                            .ok_or_else(|| StatusCode::BAD_REQUEST)?;
 
-                            let resp = {
-                                let mut h = HashMap::with_capacity(1);
-                                h.insert(id, count);
-                                h
+                        Ok((pages, sse))
+                    })();
+
+                    match result {
+                        Ok((pages, sse)) => {
+                            let lines = pages
+                                .map(move |(position, url)| {
+                                    let record = serde_json::to_string(&PageRecord {
+                                        position,
+                                        url: url.as_str(),
+                                    })
+                                    .expect("failed to serialize page");
+
+                                    if sse {
+                                        format!("id: {}\ndata: {}\n\n", position, record)
+                                    } else {
+                                        format!("{}\n", record)
+                                    }
+                                })
+                                .map_err(|_| io::Error::other("journal failed"));
+
+                            let content_type = if sse {
+                                "text/event-stream"
+                            } else {
+                                "application/x-ndjson"
                             };
 
-                            let resp = serde_json::to_string(&resp)
+                            get_stream(content_type, Body::wrap_stream(lines))
+                        }
+                        Err(status) => get_result(Err(status)),
+                    }
+                }
+                (&Method::GET, "/api/crawls/diff") => {
+                    let request = (|| {
+                        let uri = req.uri().to_string();
+                        let url = Url::parse("http://dummy")
+                            .and_then(|url| url.join(&uri))
+                            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                        let id = find_query_param(&url, "id")?;
+                        let id: &str = id.borrow();
+
+                        let latest = {
+                            let registry = registry.read()
                                 .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-                                Ok(Body::from(resp))
-                        } else {
-                            Err(StatusCode::ACCEPTED)
-                        }
+                            let crawl = registry.get(id)
+                                .ok_or_else(|| StatusCode::NOT_FOUND)?;
+
+                            // the latest finished run, the failed ones aren't stored
+                            match crawl.status {
+                                CrawlStatus::Queued | CrawlStatus::Pending | CrawlStatus::Failed => {
+                                    crawl.run - 1
+                                }
+                                _ => crawl.run,
+                            }
+                        };
+
+                        let run = |name, default| match find_query_param(&url, name) {
+                            Ok(run) => run.parse::<usize>().map_err(|_| StatusCode::BAD_REQUEST),
+                            Err(_) => Ok(default),
+                        };
+
+                        let to = run("to", latest)?;
+                        let from = run("from", to.saturating_sub(1))?;
 
+                        let csv = req.headers().get("Accept")
+                            .and_then(|accept| accept.to_str().ok())
+                            .is_some_and(|accept| accept.contains("text/csv"));
+
+                        Ok((id.to_owned(), from, to, csv))
                     })();
 
-                    get_result(result)
+                    let storage = storage.clone();
+                    let settings = settings.clone();
+
+                    // the runs are read from disk
+                    let process = future::result(request)
+                        .and_then(move |(id, from, to, csv)| blocking(move || {
+                            let files = |run| -> std::result::Result<_, StatusCode> {
+                                storage.load_run(&id, run)
+                                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                                    // unknown or unfinished run
+                                    .ok_or_else(|| StatusCode::NOT_FOUND)?;
+
+                                Ok(RunFiles::new(settings.run_dir(), &id, run))
+                            };
+
+                            let diff = Diff::new(&files(from)?, &files(to)?)
+                                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                            if csv {
+                                return Ok((Body::from(diff.to_csv()), "text/csv"));
+                            }
+
+                            let resp = serde_json::to_string(&DiffResponse {
+                                id: &id,
+                                from,
+                                to,
+                                diff: &diff,
+                            })
+                            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                            Ok((Body::from(resp), "application/json"))
+                        }))
+                        .then(|result| Ok(match result {
+                            Ok((body, content_type)) => get_result_as(content_type, Ok(body)),
+                            Err(status) => get_result(Err(status)),
+                        }));
+
+                    return Box::new(process)
                 }
-                (&Method::GET, "/api/results") => {
-                    let result = (|| {
+                (&Method::POST, "/api/crawl") => {
+                    let process = req.into_body()
+                    .concat2()
+                    .and_then({
+                        let registry = registry.clone();
+                        let storage = storage.clone();
+                        let settings = settings.clone();
+                        let scheduler = scheduler.clone();
+                        let usage = usage.clone();
+
+                        // the crawl is prepared and stored off the reactor
+                        move |chunk| blocking(move || {
+                            let result = (move || {
+                            let body = from_utf8(&chunk)
+                                // invalid utf-8
+                                .map_err(|_| StatusCode::BAD_REQUEST)?;
+                            let apireq = serde_json::from_str(body)
+                                .map(|request| settings.crawl_defaults.apply(request))
+                                .and_then(serde_json::from_value::<CrawlRequest>)
+                                // invalid json
+                                .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+                            let (id, _) = submit(
+                                &apireq,
+                                &client,
+                                &registry,
+                                &storage,
+                                &settings,
+                                &scheduler,
+                                &usage,
+                            )?;
+
+                            let serialized = serde_json::to_string(&CrawlResponse { id: &id })
+                                // response serialization error
+                                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                            // scheduled
+                            Ok(Body::from(serialized))
+                        })();
+
+                        Ok(get_result(result))
+                    })});
+
+                    return Box::new(process)
+                }
+                (&Method::GET, "/api/search") => {
+                    let request = (|| {
                         let uri = req.uri().to_string();
                         let url = Url::parse("http://dummy")
                             .and_then(|url| url.join(&uri))
                             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-                        let id = find_query_param(&url, "id")?;
+                        let id = find_query_param(&url, "id")?.into_owned();
+                        let query = find_query_param(&url, "q")?.into_owned();
 
-                        let registry = registry.read()
+                        /// number of the hits returned by default
+                        const SEARCH_LIMIT: usize = 10;
+
+                        let page = Page::from_query(&url, SEARCH_LIMIT)?;
+
+                        let pending = {
+                            let registry = registry.read()
+                                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                            let crawl = registry.get(&id)
+                                .ok_or_else(|| StatusCode::NOT_FOUND)?;
+
+                            !crawl.status.done()
+                        };
+
+                        Ok((id, query, page, pending))
+                    })();
+
+                    let settings = settings.clone();
+
+                    // the index and the snippets are read from disk
+                    let process = future::result(request)
+                        .and_then(move |(id, query, page, pending)| blocking(move || {
+                            let index = match Index::cached(&settings.index_path(&id)) {
+                                Ok(Some(index)) => index,
+                                // not indexed yet
+                                Ok(None) if pending => return Err(StatusCode::ACCEPTED),
+                                // not indexed
+                                Ok(None) => return Err(StatusCode::NOT_FOUND),
+                                Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+                            };
+
+                            let (hits, total) = index.search(&query, &page)
+                                // nothing to search for
+                                .ok_or_else(|| StatusCode::BAD_REQUEST)?;
+
+                            let resp = SearchResponse {
+                                id: &id,
+                                run: index.run,
+                                total,
+                                hits,
+                            };
+
+                            let resp = serde_json::to_string(&resp)
+                                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                            Ok(Body::from(resp))
+                        }))
+                        .then(|result| Ok(get_result(result)));
+
+                    return Box::new(process)
+                }
+                (&Method::GET, "/api/snapshot") => {
+                    let request = (|| {
+                        let uri = req.uri().to_string();
+                        let url = Url::parse("http://dummy")
+                            .and_then(|url| url.join(&uri))
                             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+                        let id = find_query_param(&url, "id")?;
                         let id: &str = id.borrow();
 
-                        let urls = registry.get(id)
-                            .ok_or_else(|| StatusCode::NOT_FOUND)?;
+                        let target = Url::parse(&find_query_param(&url, "url")?)
+                            // invalid url
+                            .map_err(|_| StatusCode::BAD_REQUEST)?;
 
-                        if let CrawlStatus::Finished(urls) = urls {
-                            let urls = urls
-                                .iter()
-                                .map(|url| url.to_string())
-                                .collect::<Vec<_>>();
-                            let resp = serde_json::to_string(&urls)
+                        let run = match find_query_param(&url, "run") {
+                            Ok(run) => Some(run.parse::<usize>()
+                                .map_err(|_| StatusCode::BAD_REQUEST)?),
+                            Err(_) => None,
+                        };
+
+                        let run = {
+                            let registry = registry.read()
                                 .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-                                Ok(Body::from(resp))
-                        } else {
-                            Err(StatusCode::ACCEPTED)
-                        }
+                            let crawl = registry.get(id)
+                                .ok_or_else(|| StatusCode::NOT_FOUND)?;
+
+                            match run {
+                                Some(run) => run,
+                                None if !crawl.status.done() => {
+                                    return Err(StatusCode::ACCEPTED)
+                                }
+                                None => crawl.run,
+                            }
+                        };
 
+                        Ok((id.to_owned(), target, run))
                     })();
 
-                    get_result(result)
+                    let settings = settings.clone();
+
+                    // the document is read from disk
+                    let process = future::result(request)
+                        .and_then(move |(id, target, run)| blocking(move || {
+                            let blob = RunFiles::new(settings.run_dir(), &id, run)
+                                .blob(target.as_str())
+                                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                                // unknown run, or not stored
+                                .ok_or_else(|| StatusCode::NOT_FOUND)?;
+
+                            let content = BlobStore::new(settings.blob_dir())
+                                .and_then(|store| store.get(&blob.digest))
+                                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                                // removed from the store
+                                .ok_or_else(|| StatusCode::NOT_FOUND)?;
+
+                            Ok((
+                                Body::from(content),
+                                blob.content_type
+                                    .unwrap_or_else(|| "application/octet-stream".to_owned()),
+                                vec![("ETag", format!("\"{}\"", blob.digest))],
+                            ))
+                        }))
+                        .then(|result| Ok(match result {
+                            Ok((body, content_type, headers)) => {
+                                get_result_with(&content_type, &headers, Ok(body))
+                            }
+                            Err(status) => get_result(Err(status)),
+                        }));
+
+                    return Box::new(process)
                 }
-                (&Method::POST, "/api/crawl") => {
-                    #[derive(Debug, Deserialize)]
-                    struct CrawlRequest<'a> {
-                        url: &'a str,
-                        throttle: usize,
-                    }
+                (&Method::GET, "/api/schedules") => {
+                    let result = (|| {
+                        let schedules = schedules.read();
+
+                        let mut resp = schedules
+                            .values()
+                            .map(|schedule| ScheduleResponse {
+                                name: &schedule.record.name,
+                                cron: &schedule.record.cron,
+                                crawl: &schedule.record.crawl,
+                                next: schedule.next,
+                                history: None,
+                            })
+                            .collect::<Vec<_>>();
+
+                        resp.sort_by_key(|schedule| schedule.name);
+
+                        let resp = serde_json::to_string(&resp)
+                            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-                    #[derive(Debug, Serialize)]
-                    struct CrawlResponse<'a> {
-                        id: &'a str,
-                    }
+                        Ok(Body::from(resp))
+                    })();
+
+                    get_result(result)
+                }
+                (&Method::PUT, path) if path.starts_with("/api/schedules/") => {
+                    let name = schedule_name(path);
 
                     let process = req.into_body()
                     .concat2()
                     .map({
-                        let registry = registry.clone();
+                        let storage = storage.clone();
+                        let settings = settings.clone();
+                        let schedules = schedules.clone();
 
                         move |chunk| {
                             let result = (move || {
+                            let name = name
+                                // invalid name
+                                .ok_or_else(|| StatusCode::BAD_REQUEST)?;
+
                             let body = from_utf8(&chunk)
                                 // invalid utf-8
                                 .map_err(|_| StatusCode::BAD_REQUEST)?;
-                            let apireq = serde_json::from_str::<CrawlRequest>(body)
+                            let apireq = serde_json::from_str::<ScheduleRequest>(body)
                                 // invalid json
                                 .map_err(|_| StatusCode::BAD_REQUEST)?;
-                            let url = Url::parse(apireq.url)
-                                // invalid url in the payload
-                                .map_err(|_| StatusCode::BAD_REQUEST)?;
 
-                            let origin = url.origin().ascii_serialization();
+                            let cron = apireq.cron.parse::<Cron>()
+                                // invalid cron expression, or one that never matches
+                                .map_err(|_| StatusCode::BAD_REQUEST)?;
 
-                            let apiresp = CrawlResponse {
-                                id: &origin,
+                            let crawl = serde_json::from_value::<CrawlRequest>(
+                                settings.crawl_defaults.apply(apireq.crawl),
+                            )
+                            // invalid crawl
+                            .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+                            crawl.seeds()?;
+                            crawl.callback_url()?;
+                            crawl.throttle(settings.max_throttle)?;
+
+                            let mut record = ScheduleRecord {
+                                name: name.clone(),
+                                cron: cron.to_string(),
+                                crawl,
+                                owner: client,
+                                history: VecDeque::new(),
                             };
 
-                            let serialized = serde_json::to_string(&apiresp)
-                                // response serialization error
+                            if let Some(previous) = schedules.read().get(&name) {
+                                record.history = previous.record.history.clone();
+                            }
+
+                            storage.save_schedule(&record)
                                 .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-                            {
-                                let mut reg = registry.write()
-                                    // unable to acquire lock
-                                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+                            info!("Saved schedule {} ({})", name, cron);
 
-                                if let Entry::Vacant(e) = reg.entry(origin) {
-                                    e.insert(CrawlStatus::Pending);
+                            let next = schedules.insert(record, cron, timestamp());
 
-                                    schedule(url, apireq.throttle, registry.clone());
+                            let resp = serde_json::to_string(&ScheduledResponse {
+                                name: &name,
+                                next,
+                            })
+                            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-                                    // scheduled
-                                    Ok(Body::from(serialized))
-                                } else {
-                                    // already scheduled/available
-                                    Err(StatusCode::CONFLICT)
-                                }
-                            }
+                            Ok(Body::from(resp))
                         })();
 
                         get_result(result)
@@ -297,6 +2927,179 @@ fn main() -> Result<()> {
 
                     return Box::new(process)
                 }
+                (method, path) if path.starts_with("/api/schedules/") => {
+                    let result = (|| {
+                        let name = schedule_name(path)
+                            // invalid name
+                            .ok_or_else(|| StatusCode::BAD_REQUEST)?;
+                        let name = name.as_str();
+
+                        match *method {
+                            Method::GET => {
+                                let schedules = schedules.read();
+
+                                let schedule = schedules.get(name)
+                                    .ok_or_else(|| StatusCode::NOT_FOUND)?;
+
+                                let resp = ScheduleResponse {
+                                    name,
+                                    cron: &schedule.record.cron,
+                                    crawl: &schedule.record.crawl,
+                                    next: schedule.next,
+                                    history: Some(&schedule.record.history),
+                                };
+
+                                let resp = serde_json::to_string(&resp)
+                                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                                Ok(Body::from(resp))
+                            }
+                            Method::DELETE => {
+                                if !schedules.remove(name) {
+                                    return Err(StatusCode::NOT_FOUND);
+                                }
+
+                                storage.remove_schedule(name)
+                                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                                info!("Removed schedule {}", name);
+
+                                let resp = serde_json::to_string(&ScheduledResponse {
+                                    name,
+                                    next: None,
+                                })
+                                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                                Ok(Body::from(resp))
+                            }
+                            _ => Err(StatusCode::NOT_FOUND),
+                        }
+                    })();
+
+                    get_result(result)
+                }
+                (&Method::GET, path) if path.starts_with("/api/crawl/")
+                    && split_crawl_path(path).is_some_and(|(_, action)| action == Some("changes")) =>
+                {
+                    let request = (|| {
+                        let (id, _) = split_crawl_path(path)
+                            .ok_or_else(|| StatusCode::NOT_FOUND)?;
+
+                        let run = {
+                            let registry = registry.read()
+                                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                            let crawl = registry.get(&id)
+                                .ok_or_else(|| StatusCode::NOT_FOUND)?;
+
+                            if !crawl.status.done() {
+                                // the run is not finished yet
+                                return Err(StatusCode::ACCEPTED);
+                            }
+
+                            crawl.run
+                        };
+
+                        Ok((id, run))
+                    })();
+
+                    let settings = settings.clone();
+
+                    // the changes are read from disk
+                    let process = future::result(request)
+                        .and_then(move |(id, run)| blocking(move || {
+                            let changes = RunFiles::new(settings.run_dir(), &id, run).changes()
+                                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                                // failed, so not compared
+                                .ok_or_else(|| StatusCode::NOT_FOUND)?;
+
+                            let resp = ChangesResponse {
+                                id: &id,
+                                run,
+                                changes: &changes,
+                            };
+
+                            let resp = serde_json::to_string(&resp)
+                                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                            Ok(Body::from(resp))
+                        }))
+                        .then(|result| Ok(get_result(result)));
+
+                    return Box::new(process)
+                }
+                (method, path) if path.starts_with("/api/crawl/") => {
+                    let result = (|| {
+                        let (id, action) = split_crawl_path(path)
+                            .ok_or_else(|| StatusCode::NOT_FOUND)?;
+
+                        if let (&Method::GET, Some("status")) = (method, action) {
+                            let registry = registry.read()
+                                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                            let crawl = registry.get(&id)
+                                .ok_or_else(|| StatusCode::NOT_FOUND)?;
+
+                            let stats = crawl.progress.stats();
+
+                            let resp = StatusResponse {
+                                id: &id,
+                                run: crawl.run,
+                                state: crawl.state(),
+                                queue_position: scheduler.position(&id),
+                                queued: stats.queued,
+                                in_flight: stats.in_flight,
+                                fetched: stats.fetched,
+                                unchanged: stats.unchanged,
+                                effective: stats.effective,
+                                errors: stats.errors,
+                                bytes: stats.bytes,
+                                elapsed: stats.elapsed.as_secs_f64(),
+                                pages_per_sec: stats.pages_per_sec,
+                            };
+
+                            let resp = serde_json::to_string(&resp)
+                                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                            return Ok(Body::from(resp));
+                        }
+
+                        let operation: fn(&Control) -> bool = match (method, action) {
+                            (&Method::DELETE, None) => Control::cancel,
+                            (&Method::POST, Some("pause")) => Control::pause,
+                            (&Method::POST, Some("resume")) => Control::resume,
+                            _ => return Err(StatusCode::NOT_FOUND),
+                        };
+
+                        let registry = registry.read()
+                            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                        let crawl = registry.get(&id)
+                            .ok_or_else(|| StatusCode::NOT_FOUND)?;
+
+                        if let CrawlStatus::Queued | CrawlStatus::Pending = crawl.status {
+                            if operation(&crawl.control) {
+                                if crawl.control.state() == State::Cancelled {
+                                    // a queued crawl is finished right away
+                                    scheduler.expedite(&id);
+                                }
+
+                                let resp = serde_json::to_string(&CrawlResponse { id: &id })
+                                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+                                Ok(Body::from(resp))
+                            } else {
+                                // already in the requested state
+                                Err(StatusCode::CONFLICT)
+                            }
+                        } else {
+                            // the crawl is not running anymore
+                            Err(StatusCode::CONFLICT)
+                        }
+                    })();
+
+                    get_result(result)
+                }
                 _ => {
                     get_result(Err(StatusCode::NOT_FOUND))
                 }
@@ -306,22 +3109,57 @@ fn main() -> Result<()> {
         }
     };
 
-    let args = setup_cli().get_matches();
+    let server: Box<dyn Future<Item = (), Error = ()> + Send> = match tls {
+        Some(acceptor) => {
+            info!("Starting HTTPS server on {}", addr);
 
-    let addr = args
-        .value_of("address")
-        .unwrap()
-        .to_socket_addrs()?
-        .next()
-        .unwrap();
+            let acceptor = tokio_tls::TlsAcceptor::from(acceptor);
 
-    info!("Starting server on {}", addr);
+            let listener = std::net::TcpListener::bind(addr)?;
+
+            let incoming = tokio::net::TcpListener::from_std(listener, &Default::default())?
+                .incoming()
+                .then(|tcp| {
+                    if let Err(e) = &tcp {
+                        warn!("Unable to accept a connection: {}", e);
+                    }
 
-    let server = Server::bind(&addr)
-        .serve(move || service_fn(api()))
-        .map_err(|e| error!("server error: {}", e));
+                    Ok::<_, io::Error>(tcp.ok())
+                })
+                .filter_map(|tcp| tcp)
+                .map(move |tcp| {
+                    acceptor.accept(tcp).then(|tls| match tls {
+                        Ok(tls) => Ok(Some(tls)),
+                        Err(e) => {
+                            debug!("TLS handshake failed: {}", e);
+                            Ok(None)
+                        }
+                    })
+                })
+                // the handshakes of the slow clients don't hold up the other ones
+                .buffer_unordered(TLS_HANDSHAKES)
+                .filter_map(|tls| tls);
+
+            Box::new(
+                Server::builder(incoming)
+                    .serve(move || service_fn(api()))
+                    .with_graceful_shutdown(stopped)
+                    .map_err(|e| error!("server error: {}", e)),
+            )
+        }
+        None => {
+            info!("Starting server on {}", addr);
+
+            Box::new(
+                Server::bind(&addr)
+                    .serve(move || service_fn(api()))
+                    .with_graceful_shutdown(stopped)
+                    .map_err(|e| error!("server error: {}", e)),
+            )
+        }
+    };
 
-    tokio::run(server);
+    tokio::run(resume.and_then(|_| server));
 
     Ok(())
 }
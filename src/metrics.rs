@@ -0,0 +1,302 @@
+//! Metrics of the server in the Prometheus text format
+
+use crate::error::*;
+use crate::{CrawlStatus, Registry};
+use crawler::ty::Fetched;
+use crawler::FetchError;
+use hyper::StatusCode;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds of the fetch latency buckets, in seconds
+const LATENCY_BUCKETS: [f64; 9] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// States of the crawls, see `Crawl::state`
+const STATES: [&str; 7] = [
+    "queued",
+    "running",
+    "paused",
+    "cancelling",
+    "finished",
+    "cancelled",
+    "failed",
+];
+
+/// Maximum number of the running crawls with their own series, the others are summed up
+const MAX_CRAWL_SERIES: usize = 50;
+
+/// Kind of the errors of the pages that couldn't be parsed
+const PARSE_ERROR: &str = "parse";
+
+static FETCHES: Mutex<Fetches> = Mutex::new(Fetches::new());
+
+/// Counters of the requests of all crawls since the start
+#[derive(Debug)]
+struct Fetches {
+    /// number of the retrieved documents
+    pages: u64,
+    /// number of the documents that weren't modified since the previous run
+    unchanged: u64,
+    bytes: u64,
+    /// number of the requests by latency bucket, the last one above all bounds
+    latency: [u64; LATENCY_BUCKETS.len() + 1],
+    /// total latency, in seconds
+    latency_sum: f64,
+    /// number of the responses by status code, 0 for the non-standard ones
+    statuses: BTreeMap<u16, u64>,
+    /// number of the errors by kind
+    errors: BTreeMap<&'static str, u64>,
+}
+
+impl Fetches {
+    const fn new() -> Self {
+        Self {
+            pages: 0,
+            unchanged: 0,
+            bytes: 0,
+            latency: [0; LATENCY_BUCKETS.len() + 1],
+            latency_sum: 0.0,
+            statuses: BTreeMap::new(),
+            errors: BTreeMap::new(),
+        }
+    }
+}
+
+fn fetches() -> std::sync::MutexGuard<'static, Fetches> {
+    // the counters are valid even if an update panicked
+    FETCHES.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Count a finished request of given latency
+pub(super) fn observe_fetch(elapsed: Duration, result: &Result<Fetched>) {
+    let seconds = elapsed.as_secs_f64();
+    let bucket = LATENCY_BUCKETS
+        .iter()
+        .position(|bound| seconds <= *bound)
+        .unwrap_or(LATENCY_BUCKETS.len());
+
+    let mut fetches = fetches();
+
+    fetches.latency[bucket] += 1;
+    fetches.latency_sum += seconds;
+
+    let status = match result {
+        Ok(Fetched::Document { buffer, status, .. }) => {
+            fetches.pages += 1;
+            fetches.bytes += buffer.as_str().len() as u64;
+
+            *status
+        }
+        Ok(Fetched::NotModified) => {
+            fetches.unchanged += 1;
+
+            StatusCode::NOT_MODIFIED.as_u16()
+        }
+        Err(e) => {
+            *fetches.errors.entry(FetchError::of(e).name()).or_default() += 1;
+
+            return;
+        }
+    };
+
+    // the codes are kept to the known ones, so that the series are bounded
+    let status = match StatusCode::from_u16(status) {
+        Ok(code) if code.canonical_reason().is_some() => status,
+        _ => 0,
+    };
+
+    *fetches.statuses.entry(status).or_default() += 1;
+}
+
+/// Count a page that couldn't be parsed
+pub(super) fn parse_error() {
+    *fetches().errors.entry(PARSE_ERROR).or_default() += 1;
+}
+
+/// Return the metrics in the Prometheus text exposition format
+pub(super) fn render(registry: &Registry) -> Result<String> {
+    let mut states = BTreeMap::new();
+    let mut queued = 0;
+    let mut in_flight = Vec::new();
+
+    {
+        let registry = registry
+            .read()
+            .map_err(|_| err_msg("failed to read registry"))?;
+
+        for (id, crawl) in registry.iter() {
+            *states.entry(crawl.state()).or_insert(0) += 1;
+
+            if let CrawlStatus::Pending = crawl.status {
+                let stats = crawl.progress.stats();
+
+                queued += stats.queued;
+                in_flight.push((id.clone(), stats.in_flight));
+            }
+        }
+    }
+
+    let mut out = String::new();
+
+    header(
+        &mut out,
+        "webcrawl_crawls",
+        "gauge",
+        "Number of the crawls by state.",
+    );
+
+    for state in STATES.iter() {
+        let count = states.get(state).copied().unwrap_or(0);
+        writeln!(out, "webcrawl_crawls{{state=\"{}\"}} {}", state, count)?;
+    }
+
+    header(
+        &mut out,
+        "webcrawl_queued_urls",
+        "gauge",
+        "Number of the urls waiting to be fetched by the running crawls.",
+    );
+    writeln!(out, "webcrawl_queued_urls {}", queued)?;
+
+    header(
+        &mut out,
+        "webcrawl_in_flight_requests",
+        "gauge",
+        "Number of the requests in flight by running crawl.",
+    );
+
+    // the busiest crawls get their own series
+    in_flight.sort_by(|(a_id, a), (b_id, b)| b.cmp(a).then_with(|| a_id.cmp(b_id)));
+
+    let others = in_flight
+        .split_off(MAX_CRAWL_SERIES.min(in_flight.len()))
+        .iter()
+        .map(|(_, count)| count)
+        .sum::<usize>();
+
+    for (id, count) in &in_flight {
+        writeln!(
+            out,
+            "webcrawl_in_flight_requests{{crawl=\"{}\"}} {}",
+            escape(id),
+            count
+        )?;
+    }
+
+    if others > 0 {
+        writeln!(
+            out,
+            "webcrawl_in_flight_requests{{crawl=\"other\"}} {}",
+            others
+        )?;
+    }
+
+    let fetches = fetches();
+
+    header(
+        &mut out,
+        "webcrawl_pages_fetched_total",
+        "counter",
+        "Number of the retrieved documents.",
+    );
+    writeln!(out, "webcrawl_pages_fetched_total {}", fetches.pages)?;
+
+    header(
+        &mut out,
+        "webcrawl_pages_unchanged_total",
+        "counter",
+        "Number of the documents not modified since the previous run.",
+    );
+    writeln!(out, "webcrawl_pages_unchanged_total {}", fetches.unchanged)?;
+
+    header(
+        &mut out,
+        "webcrawl_downloaded_bytes_total",
+        "counter",
+        "Number of the downloaded bytes of the documents.",
+    );
+    writeln!(out, "webcrawl_downloaded_bytes_total {}", fetches.bytes)?;
+
+    header(
+        &mut out,
+        "webcrawl_fetch_duration_seconds",
+        "histogram",
+        "Latency of the requests, including the failed ones.",
+    );
+
+    let mut count = 0;
+
+    for (bound, bucket) in LATENCY_BUCKETS.iter().zip(fetches.latency.iter()) {
+        count += bucket;
+        writeln!(
+            out,
+            "webcrawl_fetch_duration_seconds_bucket{{le=\"{}\"}} {}",
+            bound, count
+        )?;
+    }
+
+    count += fetches.latency[LATENCY_BUCKETS.len()];
+
+    writeln!(
+        out,
+        "webcrawl_fetch_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+        count
+    )?;
+    writeln!(
+        out,
+        "webcrawl_fetch_duration_seconds_sum {}",
+        fetches.latency_sum
+    )?;
+    writeln!(out, "webcrawl_fetch_duration_seconds_count {}", count)?;
+
+    header(
+        &mut out,
+        "webcrawl_responses_total",
+        "counter",
+        "Number of the responses by status code, other for the non-standard ones.",
+    );
+
+    for (status, count) in &fetches.statuses {
+        let status = match status {
+            0 => "other".to_owned(),
+            status => status.to_string(),
+        };
+
+        writeln!(
+            out,
+            "webcrawl_responses_total{{status=\"{}\"}} {}",
+            status, count
+        )?;
+    }
+
+    header(
+        &mut out,
+        "webcrawl_errors_total",
+        "counter",
+        "Number of the failed requests and unparsable pages by kind.",
+    );
+
+    for (kind, count) in &fetches.errors {
+        writeln!(out, "webcrawl_errors_total{{kind=\"{}\"}} {}", kind, count)?;
+    }
+
+    Ok(out)
+}
+
+/// Write the help and type lines of a metric
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    out.push_str(&format!(
+        "# HELP {} {}\n# TYPE {} {}\n",
+        name, help, name, kind
+    ));
+}
+
+/// Escape given label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,707 @@
+//! Files of the crawl runs
+//!
+//! A running crawl appends the crawled pages and the stored documents to its logs as they come.
+//! Once it's done, the logs are sorted by url into the results, the pages and the documents of
+//! the run, so neither the running nor the finished crawls keep them in memory.
+
+use crate::error::*;
+use crate::export::Row;
+use crate::util::file_name;
+use crawler::{Page, Response, Urls, Validators};
+use hashbrown::HashSet;
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BinaryHeap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// Number of the records sorted in memory at once, the longer files are merged from sorted chunks
+const SORT_CHUNK: usize = 50_000;
+
+/// The files of a crawl run, named after the crawl id and the run number
+#[derive(Debug, Clone)]
+pub(super) struct RunFiles {
+    base: PathBuf,
+}
+
+/// A persistent representation of a crawled document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct PageRecord {
+    pub(super) url: String,
+    pub(super) effective: String,
+    /// the starting url that led to the document
+    seed: Option<String>,
+    depth: usize,
+    /// 0 if unknown
+    pub(super) status: u16,
+    content_type: Option<String>,
+    pub(super) title: Option<String>,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    digest: u64,
+    links: Vec<String>,
+    /// true if the document wasn't modified since the previous run
+    unchanged: bool,
+}
+
+/// A persistent representation of a stored document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct BlobRecord {
+    /// the url the document was retrieved from
+    url: String,
+    pub(super) digest: String,
+    pub(super) content_type: Option<String>,
+}
+
+/// A resulting url, with the metadata of its page and the hash of its content
+#[derive(Debug, Serialize, Deserialize)]
+struct ResultRecord {
+    #[serde(flatten)]
+    row: Row,
+    /// None if the page wasn't crawled
+    digest: Option<u64>,
+}
+
+/// Differences between two runs of a crawl
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(super) struct ChangesRecord {
+    /// the compared run, if any
+    pub(super) previous: Option<usize>,
+    /// urls found only in this run
+    pub(super) added: Vec<String>,
+    /// urls found only in the compared run
+    pub(super) removed: Vec<String>,
+    /// urls of the documents with modified content
+    pub(super) changed: Vec<String>,
+}
+
+/// An append-only file of json lines
+#[derive(Debug)]
+pub(super) struct Log {
+    file: BufWriter<File>,
+}
+
+impl Log {
+    /// Open given file for appending, creating it if needed
+    ///
+    /// The log of a resumed run may end with a partially written line, which is dropped.
+
+    fn open(path: &Path) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+
+        let mut end = file.seek(SeekFrom::End(0))?;
+        let mut chunk = [0; 4096];
+
+        // the length up to the last line break
+        while end > 0 {
+            let start = end.saturating_sub(chunk.len() as u64);
+            let chunk = &mut chunk[..(end - start) as usize];
+
+            file.seek(SeekFrom::Start(start))?;
+            file.read_exact(chunk)?;
+
+            match chunk.iter().rposition(|byte| *byte == b'\n') {
+                Some(position) => {
+                    end = start + position as u64 + 1;
+                    break;
+                }
+                None => end = start,
+            }
+        }
+
+        file.set_len(end)?;
+
+        Ok(Self {
+            file: BufWriter::new(file),
+        })
+    }
+
+    /// Append given records, writing them through to the file
+    fn append<T: serde::Serialize>(&mut self, records: impl IntoIterator<Item = T>) -> Result<()> {
+        for record in records {
+            write_line(&mut self.file, &record)?;
+        }
+
+        self.file.flush()?;
+
+        Ok(())
+    }
+}
+
+impl RunFiles {
+    pub(super) fn new(dir: impl AsRef<Path>, id: &str, run: usize) -> Self {
+        Self {
+            base: dir.as_ref().join(format!("{}.{}", file_name(id), run)),
+        }
+    }
+
+    fn path(&self, extension: &str) -> PathBuf {
+        let mut path = self.base.clone().into_os_string();
+        path.push(".");
+        path.push(extension);
+        path.into()
+    }
+
+    /// Return the journal of the urls found by the run, see `Journal`
+    pub(super) fn journal(&self) -> PathBuf {
+        self.path("urls")
+    }
+
+    /// Remove the files left by an earlier run of the same number, e.g. without a data directory
+    pub(super) fn clear(&self) -> Result<()> {
+        if let Some(dir) = self.base.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        for extension in &[
+            "urls", "fetched", "captured", "pages", "results", "blobs", "changes",
+        ] {
+            remove(&self.path(extension))?;
+        }
+
+        Ok(())
+    }
+
+    /// Append given crawled pages to the log of the run, opening it first if needed
+    pub(super) fn log_pages(&self, log: &mut Option<Log>, pages: &[Page]) -> Result<()> {
+        if log.is_none() {
+            *log = Some(Log::open(&self.path("fetched"))?);
+        }
+
+        log.as_mut()
+            .expect("log was just opened")
+            .append(pages.iter().map(PageRecord::from))
+    }
+
+    /// Append the document stored from given response to the log of the run, opening it first
+    /// if needed
+    pub(super) fn log_blob(
+        &self,
+        log: &mut Option<Log>,
+        response: &Response,
+        digest: String,
+    ) -> Result<()> {
+        if log.is_none() {
+            *log = Some(Log::open(&self.path("captured"))?);
+        }
+
+        let content_type = response
+            .headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+            .map(|(_, value)| value.clone());
+
+        let blob = BlobRecord {
+            url: response.effective.to_string(),
+            digest,
+            content_type,
+        };
+
+        log.as_mut()
+            .expect("log was just opened")
+            .append(Some(blob))
+    }
+
+    /// Sort the logs of the done run by url, returning the urls of its unmodified documents
+    ///
+    /// The unmodified documents keep their stored content from given previous run.
+
+    pub(super) fn finish(&self, previous: Option<&RunFiles>) -> Result<HashSet<String>> {
+        let fetched = self.path("fetched");
+
+        let mut unchanged = HashSet::new();
+
+        for page in read::<PageRecord>(&fetched)? {
+            let page = page?;
+
+            if page.unchanged {
+                unchanged.insert(page.effective);
+            }
+        }
+
+        // the pages of a resumed run may be logged again, the first one is kept
+        sort(
+            read::<PageRecord>(&fetched)?,
+            |page| page.url.clone(),
+            &self.path("pages"),
+        )?;
+
+        let found = self.path("found");
+        sort(read::<String>(&self.journal())?, String::clone, &found)?;
+
+        // the pages that weren't redirected come first
+        let effective = self.path("effective");
+        sort(
+            read::<PageRecord>(&fetched)?,
+            |page| {
+                let redirected = page.url != page.effective;
+                (page.effective.clone(), redirected, page.url.clone())
+            },
+            &effective,
+        )?;
+
+        let mut pages = read::<PageRecord>(&effective)?.peekable();
+        let mut results = BufWriter::new(File::create(self.path("results"))?);
+
+        for url in read::<String>(&found)? {
+            let url = url?;
+
+            let result = match seek(&mut pages, &url, |page| &page.effective)? {
+                Some(page) => ResultRecord {
+                    row: Row {
+                        url,
+                        // 0 if unknown
+                        status: Some(page.status).filter(|status| *status != 0),
+                        content_type: page.content_type,
+                        depth: Some(page.depth),
+                        title: page.title,
+                        seed: page.seed,
+                        links: Some(page.links.len()),
+                    },
+                    digest: Some(page.digest),
+                },
+                None => ResultRecord {
+                    row: Row::bare(url),
+                    digest: None,
+                },
+            };
+
+            write_line(&mut results, &result)?;
+        }
+
+        results.flush()?;
+
+        let mut kept = Vec::new();
+
+        if let Some(previous) = previous {
+            for url in &unchanged {
+                kept.extend(find(&previous.path("blobs"), url, |blob: &BlobRecord| {
+                    &blob.url
+                })?);
+            }
+        }
+
+        // the documents stored during the run come first
+        sort(
+            read::<BlobRecord>(&self.path("captured"))?.chain(kept.into_iter().map(Ok)),
+            |blob| blob.url.clone(),
+            &self.path("blobs"),
+        )?;
+
+        remove(&found)?;
+        remove(&effective)?;
+
+        Ok(unchanged)
+    }
+
+    /// Remove the logs of the run, once it's finished and stored
+    pub(super) fn remove_logs(&self) -> Result<()> {
+        remove(&self.path("fetched"))?;
+        remove(&self.path("captured"))
+    }
+
+    /// Record the differences of the finished run from given previous one
+    pub(super) fn compare(&self, previous: Option<(usize, &RunFiles)>) -> Result<()> {
+        let mut changes = ChangesRecord {
+            previous: previous.map(|(run, _)| run),
+            ..ChangesRecord::default()
+        };
+
+        let before = match previous {
+            Some((_, files)) => Some(read::<ResultRecord>(&files.path("results"))?),
+            None => None,
+        };
+
+        join(
+            before.into_iter().flatten(),
+            read::<ResultRecord>(&self.path("results"))?,
+            |result| &result.row.url,
+            |result| &result.row.url,
+            |before, after| match (before, after) {
+                (Some(before), Some(after)) => {
+                    if let (Some(from), Some(to)) = (before.digest, after.digest) {
+                        if from != to {
+                            changes.changed.push(after.row.url);
+                        }
+                    }
+                }
+                (None, Some(after)) => changes.added.push(after.row.url),
+                (Some(before), None) => changes.removed.push(before.row.url),
+                (None, None) => {}
+            },
+        )?;
+
+        let mut file = BufWriter::new(File::create(self.path("changes"))?);
+        write_line(&mut file, &changes)?;
+        file.flush()?;
+
+        Ok(())
+    }
+
+    /// Return the differences of the finished run from the previous one, if they were recorded
+    pub(super) fn changes(&self) -> Result<Option<ChangesRecord>> {
+        match File::open(self.path("changes")) {
+            Ok(file) => Ok(Some(serde_json::from_reader(BufReader::new(file))?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Return the crawled documents of the finished run, skipping the invalid ones
+    pub(super) fn history(&self) -> Result<Vec<Page>> {
+        let mut pages = Vec::new();
+
+        for page in self.pages()? {
+            pages.extend(page?.to_page());
+        }
+
+        Ok(pages)
+    }
+
+    /// Return the crawled documents of the finished run, sorted by the requested url
+    pub(super) fn pages(&self) -> Result<impl Iterator<Item = Result<PageRecord>>> {
+        read(&self.path("pages"))
+    }
+
+    /// Return the resulting urls of the done run sorted by url, with the metadata of their pages
+    pub(super) fn rows(&self) -> Result<impl Iterator<Item = Result<Row>>> {
+        Ok(read::<ResultRecord>(&self.path("results"))?.map(|result| Ok(result?.row)))
+    }
+
+    /// Return the resulting urls of the done run grouped by the starting urls that led to them
+    pub(super) fn urls_by_seed(&self) -> Result<BTreeMap<String, Vec<String>>> {
+        let mut urls = BTreeMap::new();
+
+        // sorted already, and the redirects lead to the same row
+        for row in self.rows()? {
+            let row = row?;
+
+            if let Some(seed) = row.seed {
+                urls.entry(seed).or_insert_with(Vec::new).push(row.url);
+            }
+        }
+
+        Ok(urls)
+    }
+
+    /// Return the document stored for given url by the done run, if any
+    ///
+    /// The url is either the one the document was retrieved from, or the requested one.
+
+    pub(super) fn blob(&self, url: &str) -> Result<Option<BlobRecord>> {
+        let blobs = self.path("blobs");
+
+        if let Some(blob) = find(&blobs, url, |blob: &BlobRecord| &blob.url)? {
+            return Ok(Some(blob));
+        }
+
+        // the requested urls lead to the documents they were redirected to
+        match find(&self.path("pages"), url, |page: &PageRecord| &page.url)? {
+            Some(page) if page.effective != url => {
+                find(&blobs, &page.effective, |blob: &BlobRecord| &blob.url)
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+impl From<&Page> for PageRecord {
+    fn from(page: &Page) -> Self {
+        Self {
+            url: page.url.to_string(),
+            effective: page.effective.to_string(),
+            seed: page.seed.as_ref().map(Url::to_string),
+            depth: page.depth,
+            status: page.status,
+            content_type: page.content_type.clone(),
+            title: page.title.clone(),
+            etag: page.validators.etag.clone(),
+            last_modified: page.validators.last_modified.clone(),
+            digest: page.digest,
+            links: page.links.iter().map(Url::to_string).collect(),
+            unchanged: page.unchanged,
+        }
+    }
+}
+
+impl PageRecord {
+    fn to_page(&self) -> Option<Page> {
+        Some(Page {
+            url: Url::parse(&self.url).ok()?,
+            effective: Url::parse(&self.effective).ok()?,
+            seed: self.seed.as_ref().and_then(|seed| Url::parse(seed).ok()),
+            depth: self.depth,
+            status: self.status,
+            content_type: self.content_type.clone(),
+            title: self.title.clone(),
+            validators: Validators {
+                etag: self.etag.clone(),
+                last_modified: self.last_modified.clone(),
+            },
+            digest: self.digest,
+            links: self
+                .links
+                .iter()
+                .filter_map(|url| Url::parse(url).ok())
+                .collect::<Urls>(),
+            unchanged: false,
+        })
+    }
+}
+
+/// Match the records of two sequences sorted by unique keys, passing every pair to given function
+pub(super) fn join<A, B>(
+    left: impl Iterator<Item = Result<A>>,
+    right: impl Iterator<Item = Result<B>>,
+    left_key: impl Fn(&A) -> &str,
+    right_key: impl Fn(&B) -> &str,
+    mut f: impl FnMut(Option<A>, Option<B>),
+) -> Result<()> {
+    let (mut left, mut right) = (left, right);
+    let mut a = left.next().transpose()?;
+    let mut b = right.next().transpose()?;
+
+    loop {
+        let order = match (&a, &b) {
+            (None, None) => return Ok(()),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(a), Some(b)) => left_key(a).cmp(right_key(b)),
+        };
+
+        match order {
+            Ordering::Less => {
+                f(a.take(), None);
+                a = left.next().transpose()?;
+            }
+            Ordering::Greater => {
+                f(None, b.take());
+                b = right.next().transpose()?;
+            }
+            Ordering::Equal => {
+                f(a.take(), b.take());
+                a = left.next().transpose()?;
+                b = right.next().transpose()?;
+            }
+        }
+    }
+}
+
+/// Advance given records sorted by given key to the first one of given key, returning it if found
+fn seek<T, I>(
+    records: &mut Peekable<I>,
+    wanted: &str,
+    key: impl Fn(&T) -> &str,
+) -> Result<Option<T>>
+where
+    I: Iterator<Item = Result<T>>,
+{
+    loop {
+        let order = match records.peek() {
+            Some(Ok(record)) => key(record).cmp(wanted),
+            // returned right away
+            Some(Err(_)) => Ordering::Equal,
+            None => return Ok(None),
+        };
+
+        match order {
+            Ordering::Less => {
+                records.next();
+            }
+            Ordering::Equal => return records.next().transpose(),
+            Ordering::Greater => return Ok(None),
+        }
+    }
+}
+
+/// Read the records of given file of json lines, a missing file has none
+fn read<T: DeserializeOwned>(path: &Path) -> Result<impl Iterator<Item = Result<T>>> {
+    let file = match File::open(path) {
+        Ok(file) => Some(BufReader::new(file)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(file
+        .into_iter()
+        .flat_map(BufRead::lines)
+        .map(|line| Ok(serde_json::from_str(&line?)?)))
+}
+
+/// Find the record of given key in given file of json lines sorted by the key, if any
+///
+/// The file is searched by bisecting its bytes, so only a few lines are read.
+
+fn find<T: DeserializeOwned>(
+    path: &Path,
+    wanted: &str,
+    key: impl Fn(&T) -> &str,
+) -> Result<Option<T>> {
+    let mut file = match File::open(path) {
+        Ok(file) => BufReader::new(file),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    // the wanted line starts between these offsets, the low one being a line start
+    let (mut low, mut high) = (0, file.get_ref().metadata()?.len());
+    let mut line = Vec::new();
+
+    while low < high {
+        let middle = low + (high - low) / 2;
+
+        // the first line starting at the middle or after it
+        let start = if middle == low {
+            file.seek(SeekFrom::Start(low))?;
+            low
+        } else {
+            file.seek(SeekFrom::Start(middle - 1))?;
+            line.clear();
+            middle - 1 + file.read_until(b'\n', &mut line)? as u64
+        };
+
+        if start >= high {
+            high = middle;
+            continue;
+        }
+
+        line.clear();
+        file.read_until(b'\n', &mut line)?;
+
+        let record: T = serde_json::from_slice(&line)?;
+
+        match key(&record).cmp(wanted) {
+            Ordering::Less => low = start + line.len() as u64,
+            Ordering::Equal => return Ok(Some(record)),
+            Ordering::Greater => high = middle,
+        }
+    }
+
+    Ok(None)
+}
+
+/// Sort given records by given key into a file of json lines, keeping the first one of every key
+///
+/// Only a chunk of the records is kept in memory at once, the sorted chunks are merged.
+
+fn sort<T, K>(
+    records: impl Iterator<Item = Result<T>>,
+    key: impl Fn(&T) -> K,
+    path: &Path,
+) -> Result<()>
+where
+    T: serde::Serialize + DeserializeOwned,
+    K: Ord,
+{
+    let sorted = |mut chunk: Vec<T>| {
+        // stable, so the records of the same key stay in their order
+        chunk.sort_by_key(|record| key(record));
+        chunk.dedup_by(|a, b| key(a) == key(b));
+        chunk
+    };
+
+    let mut chunks = Vec::new();
+    let mut chunk = Vec::new();
+
+    let spill = |chunk, chunks: &mut Vec<PathBuf>| -> Result<()> {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{}", chunks.len()));
+
+        let name = PathBuf::from(name);
+        let mut file = BufWriter::new(File::create(&name)?);
+
+        for record in sorted(chunk) {
+            write_line(&mut file, &record)?;
+        }
+
+        file.flush()?;
+        chunks.push(name);
+
+        Ok(())
+    };
+
+    for record in records {
+        chunk.push(record?);
+
+        if chunk.len() == SORT_CHUNK {
+            spill(chunk, &mut chunks)?;
+            chunk = Vec::new();
+        }
+    }
+
+    let mut output = BufWriter::new(File::create(path)?);
+
+    if chunks.is_empty() {
+        for record in sorted(chunk) {
+            write_line(&mut output, &record)?;
+        }
+    } else {
+        spill(chunk, &mut chunks)?;
+
+        let mut inputs = chunks
+            .iter()
+            .map(|chunk| read::<T>(chunk))
+            .collect::<Result<Vec<_>>>()?;
+        let mut heads = Vec::with_capacity(inputs.len());
+        let mut heap = BinaryHeap::new();
+
+        for (index, input) in inputs.iter_mut().enumerate() {
+            let head = input.next().transpose()?;
+
+            if let Some(record) = &head {
+                heap.push(Reverse((key(record), index)));
+            }
+
+            heads.push(head);
+        }
+
+        let mut last = None;
+
+        // the earlier chunks come first among the records of the same key
+        while let Some(Reverse((record_key, index))) = heap.pop() {
+            let record = heads[index].take().expect("merged chunk without a head");
+
+            if let Some(next) = inputs[index].next().transpose()? {
+                heap.push(Reverse((key(&next), index)));
+                heads[index] = Some(next);
+            }
+
+            if last.as_ref() != Some(&record_key) {
+                write_line(&mut output, &record)?;
+                last = Some(record_key);
+            }
+        }
+
+        for chunk in &chunks {
+            remove(chunk)?;
+        }
+    }
+
+    output.flush()?;
+
+    Ok(())
+}
+
+/// Write given record as a json line
+fn write_line<T: serde::Serialize>(file: &mut impl Write, record: &T) -> Result<()> {
+    serde_json::to_writer(&mut *file, record)?;
+    file.write_all(b"\n")?;
+
+    Ok(())
+}
+
+/// Remove given file, unless it's gone already
+fn remove(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,186 @@
+//! Queue of the crawls waiting for a free worker
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// A crawl waiting to be started, holding the worker until it's done
+type Job = Box<dyn FnOnce(Worker) + Send>;
+
+/// Runs the submitted crawls on a limited number of workers
+///
+/// The queued crawls are started by their priority, higher first,
+/// and in the order they were submitted within the same priority.
+
+#[derive(Debug, Clone)]
+pub(super) struct Scheduler {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    /// number of the workers, unlimited if None
+    workers: Option<usize>,
+    /// number of the busy workers
+    running: usize,
+    /// crawls waiting for a worker, in the order they will be started
+    queue: VecDeque<Queued>,
+    /// true if no more crawls are started
+    closed: bool,
+}
+
+struct Queued {
+    id: String,
+    priority: i32,
+    job: Job,
+}
+
+impl fmt::Debug for Queued {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Queued")
+            .field("id", &self.id)
+            .field("priority", &self.priority)
+            .finish()
+    }
+}
+
+impl Scheduler {
+    pub(super) fn new(workers: Option<usize>) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                workers,
+                running: 0,
+                queue: VecDeque::new(),
+                closed: false,
+            })),
+        }
+    }
+
+    /// Queue a crawl, started with a worker as soon as one is free
+    pub(super) fn submit(
+        &self,
+        id: String,
+        priority: i32,
+        job: impl FnOnce(Worker) + Send + 'static,
+    ) {
+        {
+            let mut inner = self.lock();
+
+            // behind all the crawls of the same or higher priority
+            let position = inner
+                .queue
+                .iter()
+                .position(|queued| queued.priority < priority)
+                .unwrap_or(inner.queue.len());
+
+            inner.queue.insert(
+                position,
+                Queued {
+                    id,
+                    priority,
+                    job: Box::new(job),
+                },
+            );
+        }
+
+        self.dispatch();
+    }
+
+    /// Return the position of a queued crawl, starting at 1
+    pub(super) fn position(&self, id: &str) -> Option<usize> {
+        self.lock()
+            .queue
+            .iter()
+            .position(|queued| queued.id == id)
+            .map(|position| position + 1)
+    }
+
+    /// Start a queued crawl right away, without waiting for a worker
+    ///
+    /// Allows the cancelled crawls to finish without holding up the queue.
+    /// Returns false if the crawl isn't queued.
+
+    pub(super) fn expedite(&self, id: &str) -> bool {
+        let queued = {
+            let mut inner = self.lock();
+
+            match inner.queue.iter().position(|queued| queued.id == id) {
+                Some(position) => inner.queue.remove(position),
+                None => None,
+            }
+        };
+
+        match queued {
+            Some(queued) => {
+                (queued.job)(Worker { scheduler: None });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Return true if all workers are busy and crawls are waiting for them
+    pub(super) fn saturated(&self) -> bool {
+        let inner = self.lock();
+
+        !inner.queue.is_empty()
+            && inner
+                .workers
+                .is_some_and(|workers| inner.running >= workers)
+    }
+
+    /// Stop starting the queued crawls, leaving them in the queue
+    pub(super) fn close(&self) {
+        self.lock().closed = true;
+    }
+
+    /// Start the queued crawls while there are free workers
+    fn dispatch(&self) {
+        loop {
+            let queued = {
+                let mut inner = self.lock();
+
+                if inner.closed
+                    || inner
+                        .workers
+                        .is_some_and(|workers| inner.running >= workers)
+                {
+                    return;
+                }
+
+                match inner.queue.pop_front() {
+                    Some(queued) => {
+                        inner.running += 1;
+                        queued
+                    }
+                    None => return,
+                }
+            };
+
+            // outside of the lock, as the job may finish right away
+            (queued.job)(Worker {
+                scheduler: Some(self.clone()),
+            });
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().expect("poisoned scheduler lock")
+    }
+}
+
+/// A busy worker of a [Scheduler](struct.Scheduler.html), freed when dropped
+#[derive(Debug)]
+pub(super) struct Worker {
+    /// the scheduler of the worker, None if it's not counted
+    scheduler: Option<Scheduler>,
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        if let Some(scheduler) = self.scheduler.take() {
+            scheduler.lock().running -= 1;
+            scheduler.dispatch();
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! Crawls started on a cron schedule
+
+use crate::auth::Client;
+use crate::cron::Cron;
+use crate::storage::RecordState;
+use crate::CrawlRequest;
+use hashbrown::HashMap;
+use log::*;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Maximum number of the runs kept in the history of a schedule
+const HISTORY_LENGTH: usize = 100;
+
+/// A persistent representation of a scheduled crawl
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct ScheduleRecord {
+    pub(super) name: String,
+    /// cron expression of the start times
+    pub(super) cron: String,
+    /// the crawl started every time
+    pub(super) crawl: CrawlRequest,
+    /// the client the crawls are started for
+    pub(super) owner: Client,
+    /// the latest runs, the oldest first
+    pub(super) history: VecDeque<RunRecord>,
+}
+
+/// An attempt to start a scheduled crawl
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct RunRecord {
+    /// unix timestamp
+    pub(super) time: u64,
+    pub(super) outcome: Outcome,
+    /// id of the started crawl
+    pub(super) id: Option<String>,
+    /// number of the started run of the crawl
+    pub(super) run: Option<usize>,
+    /// HTTP status code the crawl was rejected with
+    pub(super) status: Option<u16>,
+    /// state the started run ended in, None while it's going
+    pub(super) state: Option<RecordState>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum Outcome {
+    /// the crawl was scheduled
+    Started,
+    /// the previous run of the crawl was still going
+    Skipped,
+    /// the crawl couldn't be scheduled
+    Rejected,
+}
+
+/// A scheduled crawl with its next start time
+#[derive(Debug)]
+pub(super) struct Schedule {
+    pub(super) record: ScheduleRecord,
+    cron: Cron,
+    /// unix timestamp of the next start, None if it never comes
+    pub(super) next: Option<u64>,
+}
+
+/// The scheduled crawls, by their names
+#[derive(Debug, Clone, Default)]
+pub(super) struct Schedules {
+    schedules: Arc<RwLock<HashMap<String, Schedule>>>,
+}
+
+impl Schedules {
+    /// Create the schedules of given records, starting after given time
+    pub(super) fn new(records: Vec<ScheduleRecord>, now: u64) -> Self {
+        let schedules = Self::default();
+
+        for record in records {
+            match record.cron.parse::<Cron>() {
+                Ok(cron) => {
+                    schedules.insert(record, cron, now);
+                }
+                Err(e) => warn!("Skipping schedule {}: {}", record.name, e),
+            }
+        }
+
+        schedules
+    }
+
+    /// Add a schedule, replacing the one of the same name but keeping its history,
+    /// and return its next start time
+    pub(super) fn insert(&self, mut record: ScheduleRecord, cron: Cron, now: u64) -> Option<u64> {
+        let mut schedules = self.write();
+
+        if let Some(previous) = schedules.remove(&record.name) {
+            if record.history.is_empty() {
+                record.history = previous.record.history;
+            }
+        }
+
+        let next = cron.next_after(now);
+
+        schedules.insert(record.name.clone(), Schedule { record, cron, next });
+
+        next
+    }
+
+    /// Remove given schedule, returning false if there's no such one
+    pub(super) fn remove(&self, name: &str) -> bool {
+        self.write().remove(name).is_some()
+    }
+
+    /// Return all schedules
+    pub(super) fn read(&self) -> RwLockReadGuard<'_, HashMap<String, Schedule>> {
+        self.schedules.read().expect("poisoned schedules lock")
+    }
+
+    /// Return the schedules due at given time, moving them to their next start time
+    pub(super) fn due(&self, now: u64) -> Vec<ScheduleRecord> {
+        self.write()
+            .values_mut()
+            .filter(|schedule| schedule.next.is_some_and(|next| next <= now))
+            .map(|schedule| {
+                schedule.next = schedule.cron.next_after(now);
+                schedule.record.clone()
+            })
+            .collect()
+    }
+
+    /// Add a run to the history of given schedule, returning the updated record
+    pub(super) fn record_run(&self, name: &str, run: RunRecord) -> Option<ScheduleRecord> {
+        let mut schedules = self.write();
+        let history = &mut schedules.get_mut(name)?.record.history;
+
+        history.push_back(run);
+
+        while history.len() > HISTORY_LENGTH {
+            history.pop_front();
+        }
+
+        schedules.get(name).map(|schedule| schedule.record.clone())
+    }
+
+    /// Return the started runs which haven't ended yet, as the schedule names
+    /// with the crawl ids and the run numbers
+    pub(super) fn running(&self) -> Vec<(String, String, usize)> {
+        self.read()
+            .values()
+            .flat_map(|schedule| {
+                schedule
+                    .record
+                    .history
+                    .iter()
+                    .filter_map(move |run| match run {
+                        RunRecord {
+                            id: Some(id),
+                            run: Some(number),
+                            state: None,
+                            ..
+                        } => Some((schedule.record.name.clone(), id.clone(), *number)),
+                        _ => None,
+                    })
+            })
+            .collect()
+    }
+
+    /// Record the state given run of given schedule ended in, returning the updated record
+    pub(super) fn finish_run(
+        &self,
+        name: &str,
+        id: &str,
+        number: usize,
+        state: RecordState,
+    ) -> Option<ScheduleRecord> {
+        let mut schedules = self.write();
+        let schedule = schedules.get_mut(name)?;
+
+        let run = schedule
+            .record
+            .history
+            .iter_mut()
+            .find(|run| run.id.as_deref() == Some(id) && run.run == Some(number))?;
+
+        run.state = Some(state);
+
+        Some(schedule.record.clone())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<String, Schedule>> {
+        self.schedules.write().expect("poisoned schedules lock")
+    }
+}
@@ -0,0 +1,507 @@
+//! Full-text search over the crawled documents
+
+use crate::error::*;
+use crate::export::Page;
+use crawler::prelude::parse_text;
+use crawler::Response;
+use hashbrown::{HashMap, HashSet};
+use log::*;
+use serde_derive::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Weight of the terms found in the title
+const TITLE_WEIGHT: usize = 3;
+/// Weight of the terms found in the headings
+const HEADING_WEIGHT: usize = 2;
+/// BM25 term frequency saturation
+const K1: f64 = 1.2;
+/// BM25 document length normalization
+const B: f64 = 0.75;
+/// Maximum length of a snippet, in bytes
+const SNIPPET_LENGTH: usize = 160;
+/// Number of the words preceding the first match in a snippet
+const SNIPPET_CONTEXT: usize = 8;
+/// Maximum number of the indices kept in memory
+const CACHE_SIZE: usize = 8;
+
+/// The text of a crawled document
+#[derive(Debug)]
+pub(super) struct Document {
+    /// the url the document was retrieved from
+    url: String,
+    title: Option<String>,
+    headings: Vec<String>,
+    body: String,
+}
+
+impl Document {
+    /// Extract the text of given response, if it's a successfully fetched html document
+    pub(super) fn from_response(response: &Response) -> Option<Self> {
+        let html = response
+            .headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+            .is_none_or(|(_, value)| value.contains("html"));
+
+        if !html || !(200..300).contains(&response.status) {
+            return None;
+        }
+
+        let text = parse_text(&String::from_utf8_lossy(&response.body)).ok()?;
+
+        Some(Self {
+            url: response.effective.to_string(),
+            title: text.title,
+            headings: text.headings,
+            body: text.body,
+        })
+    }
+
+    /// Return the terms of the document, with their weights
+    fn terms(&self) -> impl Iterator<Item = (String, usize)> + '_ {
+        let title = self
+            .title
+            .iter()
+            .flat_map(|title| terms(title))
+            .map(|term| (term, TITLE_WEIGHT));
+        let headings = self
+            .headings
+            .iter()
+            .flat_map(|heading| terms(heading))
+            .map(|term| (term, HEADING_WEIGHT));
+        let body = terms(&self.body).map(|term| (term, 1));
+
+        title.chain(headings).chain(body)
+    }
+}
+
+/// A document matching a search query
+#[derive(Debug, Serialize)]
+pub(super) struct Hit<'a> {
+    url: &'a str,
+    title: Option<&'a str>,
+    /// relevance of the document, higher is better
+    score: f64,
+    /// an excerpt of the body around the first match
+    snippet: String,
+}
+
+/// An indexed document, with its body left in the bodies file of the index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    url: String,
+    title: Option<String>,
+    /// byte offset of the body in the bodies file
+    offset: u64,
+    /// byte length of the body
+    length: usize,
+}
+
+/// An inverted index of the documents of a crawl run
+///
+/// The bodies of the documents are stored in a separate file, and read only for the snippets
+/// of the returned hits.
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct Index {
+    /// number of the indexed run
+    pub(super) run: usize,
+    documents: Vec<Entry>,
+    /// weighted number of the terms of each document
+    lengths: Vec<usize>,
+    /// documents containing each term, with the weighted term frequency
+    terms: HashMap<String, Vec<(usize, usize)>>,
+    /// file with the bodies of the documents
+    #[serde(skip)]
+    bodies: PathBuf,
+}
+
+/// Return the bodies file of given run of the index stored in given file
+///
+/// Every run has its own file, so that the index and the bodies are replaced independently.
+
+fn bodies_path(path: &Path, run: usize) -> PathBuf {
+    let mut name = path.with_extension("").into_os_string();
+    name.push(format!(".{}.bodies", run));
+
+    name.into()
+}
+
+/// The loaded indices, the most recently used first, with the modification times of their files
+static CACHE: Mutex<Vec<(PathBuf, SystemTime, Arc<Index>)>> = Mutex::new(Vec::new());
+
+impl Index {
+    /// Load the index stored in given file, if any
+    pub(super) fn load(path: &Path) -> Result<Option<Self>> {
+        match File::open(path) {
+            Ok(file) => {
+                let mut index: Self = serde_json::from_reader(BufReader::new(file))?;
+                index.bodies = bodies_path(path, index.run);
+
+                Ok(Some(index))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Return the index stored in given file, if any, loading it only if it changed
+    /// since it was last loaded
+    pub(super) fn cached(path: &Path) -> Result<Option<Arc<Self>>> {
+        let modified = match fs::metadata(path) {
+            Ok(metadata) => metadata.modified()?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        // the cache is valid even if an update panicked
+        let lock = || CACHE.lock().unwrap_or_else(|e| e.into_inner());
+
+        {
+            let mut cache = lock();
+
+            if let Some(position) = cache
+                .iter()
+                .position(|(cached, time, _)| cached == path && *time == modified)
+            {
+                let entry = cache.remove(position);
+                let index = entry.2.clone();
+                cache.insert(0, entry);
+
+                return Ok(Some(index));
+            }
+        }
+
+        // loaded without holding the lock, a concurrent load of the same file is harmless
+        let index = match Self::load(path)? {
+            Some(index) => Arc::new(index),
+            None => return Ok(None),
+        };
+
+        let mut cache = lock();
+
+        cache.retain(|(cached, _, _)| cached != path);
+        cache.insert(0, (path.to_owned(), modified, index.clone()));
+        cache.truncate(CACHE_SIZE);
+
+        Ok(Some(index))
+    }
+
+    /// Return given page of the documents containing all terms of given query, the most
+    /// relevant first, with the total number of the matching documents
+    ///
+    /// Returns `None` if there are no terms in the query.
+
+    pub(super) fn search(&self, query: &str, page: &Page) -> Option<(Vec<Hit<'_>>, usize)> {
+        let query = terms(query).collect::<HashSet<_>>();
+
+        if query.is_empty() {
+            return None;
+        }
+
+        let postings = match query
+            .iter()
+            .map(|term| self.terms.get(term))
+            .collect::<Option<Vec<_>>>()
+        {
+            Some(postings) => postings,
+            // some term isn't indexed at all
+            None => return Some((Vec::new(), 0)),
+        };
+
+        let count = self.documents.len() as f64;
+        let average = self.lengths.iter().sum::<usize>() as f64 / count;
+
+        let mut scores = HashMap::<usize, (usize, f64)>::new();
+
+        for documents in &postings {
+            let frequency = documents.len() as f64;
+            let idf = (1.0 + (count - frequency + 0.5) / (frequency + 0.5)).ln();
+
+            for (position, term_frequency) in documents.iter() {
+                let tf = *term_frequency as f64;
+                let length = self.lengths[*position] as f64;
+
+                let score = idf * tf * (K1 + 1.0) / (tf + K1 * (1.0 - B + B * length / average));
+
+                let entry = scores.entry(*position).or_insert((0, 0.0));
+                entry.0 += 1;
+                entry.1 += score;
+            }
+        }
+
+        let mut matching = scores
+            .into_iter()
+            .filter(|(_, (matched, _))| *matched == postings.len())
+            .map(|(position, (_, score))| (&self.documents[position], score))
+            .collect::<Vec<_>>();
+
+        matching.sort_by(|(a, a_score), (b, b_score)| {
+            b_score.total_cmp(a_score).then_with(|| a.url.cmp(&b.url))
+        });
+
+        // the snippets are made of the bodies of the returned hits only
+        let mut bodies = File::open(&self.bodies)
+            .map_err(|e| warn!("Failed to open {}: {}", self.bodies.display(), e))
+            .ok();
+
+        let hits = matching
+            .iter()
+            .skip(page.offset)
+            .take(page.limit)
+            .map(|(document, score)| {
+                let body = bodies
+                    .as_mut()
+                    .and_then(|file| read_body(file, document).ok())
+                    .unwrap_or_default();
+
+                Hit {
+                    url: &document.url,
+                    title: document.title.as_deref(),
+                    score: *score,
+                    snippet: snippet(&body, &query),
+                }
+            })
+            .collect();
+
+        Some((hits, matching.len()))
+    }
+}
+
+/// Read the body of given document from the bodies file
+fn read_body(file: &mut File, document: &Entry) -> Result<String> {
+    let mut body = vec![0; document.length];
+
+    file.seek(SeekFrom::Start(document.offset))?;
+    file.read_exact(&mut body)?;
+
+    Ok(String::from_utf8(body)?)
+}
+
+/// Builds the index of a crawl run as its documents come, writing their bodies to disk
+#[derive(Debug)]
+pub(super) struct IndexBuilder {
+    /// the file of the index
+    path: PathBuf,
+    index: Index,
+    /// the bodies file being written, renamed once the index is stored
+    tmp: PathBuf,
+    writer: BufWriter<File>,
+    offset: u64,
+}
+
+impl IndexBuilder {
+    /// Start building the index of given run, to be stored in given file
+    pub(super) fn new(path: &Path, run: usize) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let bodies = bodies_path(path, run);
+
+        let mut tmp = bodies.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+
+        let writer = BufWriter::new(File::create(&tmp)?);
+
+        Ok(Self {
+            path: path.to_owned(),
+            index: Index {
+                run,
+                documents: Vec::new(),
+                lengths: Vec::new(),
+                terms: HashMap::new(),
+                bodies,
+            },
+            tmp,
+            writer,
+            offset: 0,
+        })
+    }
+
+    /// Add given document to the index
+    pub(super) fn add(&mut self, document: &Document) -> Result<()> {
+        let mut frequencies = HashMap::new();
+
+        for (term, weight) in document.terms() {
+            *frequencies.entry(term).or_insert(0) += weight;
+        }
+
+        self.insert(
+            document.url.clone(),
+            document.title.clone(),
+            document.body.as_bytes(),
+            frequencies,
+        )
+    }
+
+    /// Add the documents of given urls from the index of a previous run, along with their bodies
+    ///
+    /// Allows carrying the documents that weren't modified over to the next run.
+
+    pub(super) fn carry(&mut self, previous: &Index, urls: &HashSet<String>) -> Result<()> {
+        let mut frequencies = vec![HashMap::new(); previous.documents.len()];
+
+        for (term, postings) in &previous.terms {
+            for (position, frequency) in postings {
+                if urls.contains(&previous.documents[*position].url) {
+                    frequencies[*position].insert(term.clone(), *frequency);
+                }
+            }
+        }
+
+        let mut bodies = File::open(&previous.bodies)?;
+
+        for (document, frequencies) in previous.documents.iter().zip(frequencies) {
+            if urls.contains(&document.url) {
+                let body = read_body(&mut bodies, document)?;
+
+                self.insert(
+                    document.url.clone(),
+                    document.title.clone(),
+                    body.as_bytes(),
+                    frequencies,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn insert(
+        &mut self,
+        url: String,
+        title: Option<String>,
+        body: &[u8],
+        frequencies: HashMap<String, usize>,
+    ) -> Result<()> {
+        self.writer.write_all(body)?;
+
+        let position = self.index.documents.len();
+
+        self.index.documents.push(Entry {
+            url,
+            title,
+            offset: self.offset,
+            length: body.len(),
+        });
+        self.offset += body.len() as u64;
+
+        self.index.lengths.push(frequencies.values().sum());
+
+        for (term, frequency) in frequencies {
+            self.index
+                .terms
+                .entry(term)
+                .or_default()
+                .push((position, frequency));
+        }
+
+        Ok(())
+    }
+
+    /// Store the index, replacing the index of given previous run
+    ///
+    /// The bodies of the previous index are kept for the searches in progress, the older ones
+    /// are removed.
+
+    pub(super) fn finish(mut self, previous: Option<usize>) -> Result<()> {
+        self.writer.flush()?;
+        fs::rename(&self.tmp, &self.index.bodies)?;
+
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+
+        // write to a temporary file first, so a crash won't leave a truncated index
+        serde_json::to_writer(BufWriter::new(File::create(&tmp)?), &self.index)?;
+        fs::rename(tmp, &self.path)?;
+
+        // the bodies files of the index are named after their runs
+        let prefix = self.path.with_extension("").to_string_lossy().into_owned() + ".";
+
+        if let Some(dir) = self.path.parent() {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                let run = path
+                    .to_string_lossy()
+                    .strip_prefix(&prefix)
+                    .and_then(|name| name.strip_suffix(".bodies"))
+                    .and_then(|run| run.parse::<usize>().ok());
+
+                match run {
+                    Some(run) if run != self.index.run && Some(run) != previous => {
+                        fs::remove_file(&path)?
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for IndexBuilder {
+    fn drop(&mut self) {
+        // left behind if the index wasn't stored
+        let _ = fs::remove_file(&self.tmp);
+    }
+}
+
+/// Split given text into words, with their byte offsets
+fn words(text: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+
+    for (offset, c) in text.char_indices().chain(Some((text.len(), ' '))) {
+        match (start, c.is_alphanumeric()) {
+            (None, true) => start = Some(offset),
+            (Some(begin), false) => {
+                words.push((begin, &text[begin..offset]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    words
+}
+
+/// Return the normalized search terms of given text
+fn terms(text: &str) -> impl Iterator<Item = String> + '_ {
+    words(text).into_iter().map(|(_, word)| word.to_lowercase())
+}
+
+/// Return an excerpt of given text around the first occurrence of any of given terms
+fn snippet(text: &str, terms: &HashSet<String>) -> String {
+    let words = words(text);
+
+    let first = words
+        .iter()
+        .position(|(_, word)| terms.contains(&word.to_lowercase()))
+        .unwrap_or_default()
+        .saturating_sub(SNIPPET_CONTEXT);
+
+    let start = words
+        .get(first)
+        .map(|(offset, _)| *offset)
+        .unwrap_or_default();
+    let end = words[first.min(words.len())..]
+        .iter()
+        .map(|(offset, word)| offset + word.len())
+        .take_while(|end| end - start <= SNIPPET_LENGTH)
+        .last()
+        .unwrap_or(start);
+
+    format!(
+        "{}{}{}",
+        if start > 0 { "…" } else { "" },
+        &text[start..end],
+        if end < text.len() { "…" } else { "" }
+    )
+}
@@ -0,0 +1,319 @@
+use crate::auth::{ApiKeys, Quota};
+use crate::config::{
+    AuthConfig, Config, CrawlDefaults, FetchConfig, LimitsConfig, NetworkConfig, TlsConfig,
+    REDACTED,
+};
+use crate::error::*;
+use crate::util::file_name;
+use clap::{value_t, ArgMatches};
+use crawler::Cidr;
+use native_tls::{Identity, TlsAcceptor};
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+pub(super) const DEFAULT_ADDRESS: &str = "localhost:8000";
+pub(super) const DEFAULT_CHECKPOINT_EVERY: usize = 100;
+pub(super) const DEFAULT_MAX_THROTTLE: usize = 100;
+pub(super) const DEFAULT_LOG: &str = "webcrawl=info";
+pub(super) const DEFAULT_SHUTDOWN_GRACE: u64 = 30;
+
+/// Server settings
+#[derive(Debug, Clone)]
+pub(super) struct Settings {
+    /// address to bind to
+    pub(super) address: SocketAddr,
+    /// directory for storing crawl results, if any
+    pub(super) data_dir: Option<PathBuf>,
+    /// filter of the logged messages, unless overridden by `RUST_LOG`
+    pub(super) log: String,
+    /// number of documents processed between the checkpoints of a running crawl
+    pub(super) checkpoint_every: usize,
+    /// number of queued urls kept in memory before spilling them to disk, if limited
+    pub(super) frontier_capacity: Option<usize>,
+    /// expected number of urls per crawl, if a Bloom filter should be used for the seen urls
+    pub(super) bloom_filter: Option<usize>,
+    /// secret for signing the crawl callbacks, if any
+    pub(super) webhook_secret: Option<String>,
+    /// time the running crawls get to finish on shutdown, before they're checkpointed
+    pub(super) shutdown_grace: Duration,
+    /// keys accepted by the API, none if it's open
+    pub(super) api_keys: ApiKeys,
+    /// maximum number of the concurrent requests of a crawl
+    pub(super) max_throttle: usize,
+    /// maximum number of the running crawls, unlimited if None
+    pub(super) max_crawls: Option<usize>,
+    /// maximum number of the concurrent requests of all crawls, unlimited if None
+    pub(super) max_connections: Option<usize>,
+    /// limits of the clients whose keys don't set their own
+    pub(super) quota: Quota,
+    /// address blocks the crawls may fetch from, even if they're not public
+    pub(super) allowed_cidrs: Vec<Cidr>,
+    /// address blocks the crawls may never fetch from
+    pub(super) denied_cidrs: Vec<Cidr>,
+    /// `User-Agent` of the crawls, none if None
+    pub(super) user_agent: Option<String>,
+    /// time before giving up on a request, if limited
+    pub(super) timeout: Option<Duration>,
+    /// time before giving up on connecting to a server, if limited
+    pub(super) connect_timeout: Option<Duration>,
+    /// certificate of the server, if it serves HTTPS
+    pub(super) tls: Option<Tls>,
+    /// values of the fields left out of the crawl requests
+    pub(super) crawl_defaults: CrawlDefaults,
+}
+
+/// Certificate of the HTTPS server
+#[derive(Debug, Clone)]
+pub(super) struct Tls {
+    /// PKCS #12 archive with the certificate chain and the private key
+    pub(super) certificate: PathBuf,
+    /// password of the archive
+    pub(super) password: String,
+}
+
+impl Tls {
+    /// Load the certificate, returning an acceptor of the TLS connections
+    pub(super) fn acceptor(&self) -> Result<TlsAcceptor> {
+        let archive = fs::read(&self.certificate).map_err(|e| {
+            err_msg(format!(
+                "failed to read TLS certificate from {}: {}",
+                self.certificate.display(),
+                e
+            ))
+        })?;
+
+        let identity = Identity::from_pkcs12(&archive, &self.password).map_err(|e| {
+            err_msg(format!(
+                "invalid TLS certificate in {}: {}",
+                self.certificate.display(),
+                e
+            ))
+        })?;
+
+        Ok(TlsAcceptor::new(identity)?)
+    }
+}
+
+impl Settings {
+    /// Read the settings from the command line, the environment and the configuration file,
+    /// in this order of precedence
+    pub(super) fn from_args(args: &ArgMatches) -> Result<Self> {
+        let config = match args.value_of("config") {
+            Some(path) => Config::load(Path::new(path))?,
+            None => Config::default(),
+        };
+
+        let string = |name| args.value_of(name).map(str::to_owned);
+        let number = |name| value::<usize>(args, name);
+        let seconds = |name, config: Option<u64>| -> Result<Option<Duration>> {
+            Ok(value::<u64>(args, name)?
+                .or(config)
+                .map(Duration::from_secs))
+        };
+
+        let address = string("address")
+            .or(config.address)
+            .unwrap_or_else(|| DEFAULT_ADDRESS.to_owned());
+        let address = address
+            .to_socket_addrs()
+            .map_err(|e| err_msg(format!("invalid address {:?}: {}", address, e)))?
+            .next()
+            .ok_or_else(|| err_msg("no address to bind to"))?;
+
+        let keys = match args.value_of("api-keys") {
+            Some(keys) => keys.to_owned(),
+            None => config.auth.api_keys.unwrap_or_default().join("\n"),
+        };
+        let mut api_keys = ApiKeys::parse(&keys)?;
+
+        if let Some(path) = string("api-keys-file")
+            .map(PathBuf::from)
+            .or(config.auth.api_keys_file)
+        {
+            let keys = fs::read_to_string(&path).map_err(|e| {
+                err_msg(format!(
+                    "failed to read API keys from {}: {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+
+            api_keys.extend(ApiKeys::parse(&keys)?);
+        }
+
+        let cidrs = |name, config: Option<Vec<String>>| -> Result<Vec<Cidr>> {
+            match args.values_of(name) {
+                Some(values) => values.map(str::parse).collect(),
+                None => config
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|s| s.parse())
+                    .collect(),
+            }
+        };
+
+        let max_crawls = number("max-crawls")?.or(config.limits.max_crawls);
+
+        if max_crawls == Some(0) {
+            return Err(err_msg("invalid number of workers 0"));
+        }
+
+        let max_throttle = number("max-throttle")?
+            .or(config.limits.max_throttle)
+            .unwrap_or(DEFAULT_MAX_THROTTLE);
+
+        if max_throttle == 0 {
+            return Err(err_msg("invalid maximum throttle 0"));
+        }
+
+        let password = string("tls-password")
+            .or(config.tls.password)
+            .unwrap_or_default();
+        let tls = string("tls-certificate")
+            .map(PathBuf::from)
+            .or(config.tls.certificate)
+            .map(|certificate| Tls {
+                certificate,
+                password,
+            });
+
+        if let Some(tls) = &tls {
+            tls.acceptor()?;
+        }
+
+        Ok(Self {
+            address,
+            data_dir: string("data-dir").map(PathBuf::from).or(config.data_dir),
+            log: config.log.unwrap_or_else(|| DEFAULT_LOG.to_owned()),
+            checkpoint_every: number("checkpoint-every")?
+                .or(config.checkpoint_every)
+                .unwrap_or(DEFAULT_CHECKPOINT_EVERY),
+            frontier_capacity: number("frontier-capacity")?.or(config.frontier_capacity),
+            bloom_filter: number("bloom-filter")?.or(config.bloom_filter),
+            webhook_secret: string("webhook-secret").or(config.webhook_secret),
+            shutdown_grace: seconds("shutdown-grace", config.shutdown_grace)?
+                .unwrap_or_else(|| Duration::from_secs(DEFAULT_SHUTDOWN_GRACE)),
+            api_keys,
+            max_throttle,
+            max_crawls,
+            max_connections: number("max-connections")?.or(config.limits.max_connections),
+            quota: Quota {
+                crawls_per_day: number("crawls-per-day")?.or(config.limits.crawls_per_day),
+                pages_per_crawl: number("pages-per-crawl")?.or(config.limits.pages_per_crawl),
+            },
+            allowed_cidrs: cidrs("allow-cidr", config.network.allow_cidr)?,
+            denied_cidrs: cidrs("deny-cidr", config.network.deny_cidr)?,
+            user_agent: string("user-agent").or(config.fetch.user_agent),
+            timeout: seconds("timeout", config.fetch.timeout)?,
+            connect_timeout: seconds("connect-timeout", config.fetch.connect_timeout)?,
+            tls,
+            crawl_defaults: config.crawl,
+        })
+    }
+
+    /// Return the effective configuration, with the secrets redacted
+    pub(super) fn to_config(&self) -> Config {
+        let redacted = |secret: &Option<String>| secret.as_ref().map(|_| REDACTED.to_owned());
+        let strings = |cidrs: &[Cidr]| Some(cidrs.iter().map(Cidr::to_string).collect());
+
+        Config {
+            address: Some(self.address.to_string()),
+            data_dir: self.data_dir.clone(),
+            log: Some(self.log.clone()),
+            checkpoint_every: Some(self.checkpoint_every),
+            frontier_capacity: self.frontier_capacity,
+            bloom_filter: self.bloom_filter,
+            webhook_secret: redacted(&self.webhook_secret),
+            shutdown_grace: Some(self.shutdown_grace.as_secs()),
+            fetch: FetchConfig {
+                user_agent: self.user_agent.clone(),
+                timeout: self.timeout.map(|timeout| timeout.as_secs()),
+                connect_timeout: self.connect_timeout.map(|timeout| timeout.as_secs()),
+            },
+            limits: LimitsConfig {
+                max_throttle: Some(self.max_throttle),
+                max_crawls: self.max_crawls,
+                max_connections: self.max_connections,
+                crawls_per_day: self.quota.crawls_per_day,
+                pages_per_crawl: self.quota.pages_per_crawl,
+            },
+            network: NetworkConfig {
+                allow_cidr: strings(&self.allowed_cidrs),
+                deny_cidr: strings(&self.denied_cidrs),
+            },
+            auth: AuthConfig {
+                api_keys: Some(self.api_keys.redacted()),
+                api_keys_file: None,
+            },
+            tls: TlsConfig {
+                certificate: self.tls.as_ref().map(|tls| tls.certificate.clone()),
+                password: self.tls.as_ref().map(|_| REDACTED.to_owned()),
+            },
+            crawl: self.crawl_defaults.clone(),
+        }
+    }
+
+    /// Return a WARC file for given run of a crawl
+    pub(super) fn warc_path(&self, id: &str, run: usize) -> PathBuf {
+        let base = match &self.data_dir {
+            Some(dir) => dir.join("warc"),
+            None => env::temp_dir().join("webcrawl-warc"),
+        };
+
+        base.join(format!("{}.{}.warc", file_name(id), run))
+    }
+
+    /// Return the directory of the stored documents
+    pub(super) fn blob_dir(&self) -> PathBuf {
+        match &self.data_dir {
+            Some(dir) => dir.join("blobs"),
+            None => env::temp_dir().join("webcrawl-blobs"),
+        }
+    }
+
+    /// Return the directory of the files of the crawl runs, see `RunFiles`
+    pub(super) fn run_dir(&self) -> PathBuf {
+        match &self.data_dir {
+            Some(dir) => dir.join("runs"),
+            None => env::temp_dir().join("webcrawl-runs"),
+        }
+    }
+
+    /// Return the search index file of a crawl
+    pub(super) fn index_path(&self, id: &str) -> PathBuf {
+        let base = match &self.data_dir {
+            Some(dir) => dir.join("index"),
+            None => env::temp_dir().join("webcrawl-index"),
+        };
+
+        base.join(format!("{}.json", file_name(id)))
+    }
+
+    /// Return a directory for the spilled queue of given crawl
+    pub(super) fn frontier_dir(&self, id: &str) -> PathBuf {
+        let base = match &self.data_dir {
+            Some(dir) => dir.join("frontier"),
+            None => env::temp_dir().join("webcrawl-frontier"),
+        };
+
+        base.join(file_name(id))
+    }
+}
+
+/// Return the value of given argument, if it's present
+fn value<T>(args: &ArgMatches, name: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if args.is_present(name) {
+        Ok(Some(value_t!(args, name, T)?))
+    } else {
+        Ok(None)
+    }
+}
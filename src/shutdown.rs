@@ -0,0 +1,145 @@
+//! Graceful shutdown of the server on SIGINT and SIGTERM
+
+use crate::error::*;
+use crate::scheduler::Scheduler;
+use crate::{CrawlStatus, Registry};
+use futures::sync::oneshot;
+use log::*;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::prelude::*;
+use tokio::timer::Interval;
+
+/// the server accepts requests and starts crawls
+const SERVING: usize = 0;
+/// the server waits for the running crawls to finish
+const DRAINING: usize = 1;
+/// the crawls still running after the grace period are cancelled and checkpointed
+const INTERRUPTING: usize = 2;
+
+/// Interval of checking whether the running crawls are done
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+static PHASE: AtomicUsize = AtomicUsize::new(SERVING);
+
+/// number of the started crawls that aren't stored yet
+static RUNNING: AtomicUsize = AtomicUsize::new(0);
+
+/// Return true if the server is shutting down
+pub(super) fn requested() -> bool {
+    PHASE.load(Ordering::SeqCst) != SERVING
+}
+
+/// Return true if the cancelled crawls are to be resumed on the next start
+pub(super) fn interrupting() -> bool {
+    PHASE.load(Ordering::SeqCst) == INTERRUPTING
+}
+
+/// A started crawl, counted as running until dropped
+#[derive(Debug)]
+pub(super) struct Running(());
+
+impl Running {
+    pub(super) fn new() -> Self {
+        RUNNING.fetch_add(1, Ordering::SeqCst);
+
+        Running(())
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        RUNNING.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Shut the server down on the first SIGINT or SIGTERM, and exit right away on the second one
+///
+/// The server stops accepting connections and starting the queued crawls, and the running
+/// crawls get the given grace period to finish. The ones still running afterwards are cancelled
+/// and checkpointed, so they're resumed on the next start along with the queued ones.
+
+pub(super) fn on_signal(
+    stop: oneshot::Sender<()>,
+    registry: Registry,
+    scheduler: Scheduler,
+    grace: Duration,
+) -> impl Future<Item = (), Error = ()> + Send {
+    signals()
+        .into_future()
+        .map_err(|(e, _)| error!("signal handler error: {}", e))
+        .and_then(move |(_, signals)| {
+            PHASE.store(DRAINING, Ordering::SeqCst);
+
+            info!(
+                "Shutting down, waiting up to {}s for {} running crawls",
+                grace.as_secs(),
+                RUNNING.load(Ordering::SeqCst)
+            );
+
+            // the requests in progress are still answered
+            let _ = stop.send(());
+            scheduler.close();
+
+            let drained = idle(Some(Instant::now() + grace))
+                .and_then(move |_| {
+                    if RUNNING.load(Ordering::SeqCst) > 0 {
+                        PHASE.store(INTERRUPTING, Ordering::SeqCst);
+
+                        let registry = registry.read().expect("failed to read registry");
+
+                        let interrupted = registry
+                            .values()
+                            .filter(|crawl| matches!(crawl.status, CrawlStatus::Pending))
+                            .filter(|crawl| crawl.control.cancel())
+                            .count();
+
+                        info!(
+                            "Interrupting {} crawls, to be resumed on the next start",
+                            interrupted
+                        );
+                    }
+
+                    // the in-flight requests of the cancelled crawls are still processed
+                    idle(None)
+                })
+                .map(|_| info!("All crawls stopped"));
+
+            let forced = signals
+                .into_future()
+                .then(|_| -> std::result::Result<(), ()> {
+                    warn!("Exiting without waiting for the crawls");
+                    process::exit(1)
+                });
+
+            drained.select2(forced).then(|_| Ok(()))
+        })
+}
+
+/// Wait until no crawls are running, or given deadline passes
+fn idle(deadline: Option<Instant>) -> impl Future<Item = (), Error = ()> + Send {
+    Interval::new_interval(POLL_INTERVAL)
+        .take_while(move |_| {
+            Ok(RUNNING.load(Ordering::SeqCst) > 0
+                && deadline.is_none_or(|deadline| Instant::now() < deadline))
+        })
+        .for_each(|_| Ok(()))
+        .map_err(|e| error!("shutdown timer error: {}", e))
+}
+
+/// Return a stream of the received termination signals
+#[cfg(unix)]
+fn signals() -> impl Stream<Item = (), Error = Error> + Send {
+    use tokio_signal::unix::{Signal, SIGINT, SIGTERM};
+
+    let signal = |signal| Signal::new(signal).flatten_stream().map(|_| ());
+
+    signal(SIGINT).select(signal(SIGTERM)).map_err(Error::from)
+}
+
+/// Return a stream of the received termination signals
+#[cfg(not(unix))]
+fn signals() -> impl Stream<Item = (), Error = Error> + Send {
+    tokio_signal::ctrl_c().flatten_stream().map_err(Error::from)
+}
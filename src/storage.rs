@@ -0,0 +1,584 @@
+use crate::error::*;
+use crate::schedules::ScheduleRecord;
+use crate::util::{file_name, persist};
+use crawler::{Checkpoint, Priority, Queued, Seed, Stats, Urls};
+use hashbrown::HashMap;
+use log::*;
+use serde_derive::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// Version of the format of the stored files, increased with every incompatible change
+const FORMAT_VERSION: u32 = 4;
+
+/// A stored value, along with the version of its format
+#[derive(Debug, Serialize, Deserialize)]
+struct Versioned<T> {
+    version: u32,
+    #[serde(flatten)]
+    value: T,
+}
+
+/// State of the stored crawl
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum RecordState {
+    Pending,
+    Finished,
+    Cancelled,
+    Failed,
+}
+
+/// Crawling order of the stored crawl
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum CrawlPriority {
+    /// in the order of discovery
+    #[default]
+    Fifo,
+    /// the shallowest pages first
+    Bfs,
+    /// the deepest pages first
+    Dfs,
+    /// the shortest urls first
+    Shortest,
+    /// by the priorities listed in the sitemap
+    Sitemap,
+}
+
+impl CrawlPriority {
+    pub(super) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "fifo" => Some(CrawlPriority::Fifo),
+            "bfs" => Some(CrawlPriority::Bfs),
+            "dfs" => Some(CrawlPriority::Dfs),
+            "shortest" => Some(CrawlPriority::Shortest),
+            "sitemap" => Some(CrawlPriority::Sitemap),
+            _ => None,
+        }
+    }
+
+    /// Return the crawler priority, if it doesn't depend on the crawled site
+    pub(super) fn priority(self) -> Option<Priority> {
+        match self {
+            CrawlPriority::Fifo | CrawlPriority::Sitemap => None,
+            CrawlPriority::Bfs => Some(Priority::BreadthFirst),
+            CrawlPriority::Dfs => Some(Priority::DepthFirst),
+            CrawlPriority::Shortest => Some(Priority::ShortestUrl),
+        }
+    }
+}
+
+/// Final statistics of the stored crawl
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct StatsRecord {
+    fetched: usize,
+    unchanged: usize,
+    effective: usize,
+    errors: usize,
+    bytes: u64,
+    /// crawl duration, in seconds
+    elapsed: f64,
+}
+
+/// A persistent representation of a crawl run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct Record {
+    pub(super) id: String,
+    /// number of the run, starting at 1
+    pub(super) run: usize,
+    /// the first starting url
+    pub(super) url: String,
+    /// all starting urls
+    seeds: Vec<SeedRecord>,
+    pub(super) throttle: usize,
+    pub(super) priority: CrawlPriority,
+    /// url notified when the crawl is done
+    pub(super) callback_url: Option<String>,
+    /// true if the documents are archived in a WARC file
+    pub(super) warc: bool,
+    /// true if the documents are indexed for the full-text search
+    pub(super) index: bool,
+    /// true if the documents are kept in the blob store
+    pub(super) snapshots: bool,
+    /// maximum number of the fetched pages, unlimited if None
+    pub(super) max_pages: Option<usize>,
+    /// maximum depth of the followed links, unlimited if None
+    pub(super) max_depth: Option<usize>,
+    /// true if the urls disallowed by the robots.txt of their sites are skipped
+    pub(super) robots: bool,
+    /// priority in the queue of the crawls waiting for a worker, higher first
+    pub(super) queue_priority: i32,
+    pub(super) state: RecordState,
+    /// unix timestamps
+    pub(super) started: u64,
+    pub(super) finished: Option<u64>,
+    pub(super) stats: Option<StatsRecord>,
+    /// number of the resulting urls, kept with the other files of the run, see `RunFiles`
+    pub(super) found: usize,
+}
+
+/// A persistent representation of a starting url
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SeedRecord {
+    url: String,
+    scope: bool,
+}
+
+/// A persistent representation of the crawler state
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(super) struct CheckpointRecord {
+    seen: Vec<u64>,
+    queue: Vec<String>,
+    /// depths of the queued urls
+    depths: Vec<usize>,
+    /// indices of the seeds that led to the queued urls
+    sources: Vec<usize>,
+    /// segment files of the urls spilled by the queue
+    segments: Vec<PathBuf>,
+    /// number of the found urls, the first ones in the journal of the run
+    pub(super) found: usize,
+}
+
+/// Crawls scheduled by each client in the current quota period
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(super) struct UsageRecord {
+    /// the quota period, in days since the epoch
+    pub(super) day: u64,
+    /// the number of the crawls, by client
+    pub(super) crawls: HashMap<String, usize>,
+}
+
+/// A crawl storage backend
+pub(super) trait Storage: Send + Sync {
+    /// Store given crawl, replacing the previous version
+    fn save(&self, record: &Record) -> Result<()>;
+
+    /// Load all stored crawls
+    fn load(&self) -> Result<Vec<Record>>;
+
+    /// Store the crawler state of a running crawl, replacing the previous one
+    fn save_checkpoint(&self, id: &str, checkpoint: &CheckpointRecord) -> Result<()>;
+
+    /// Load the crawler state of given crawl, if any
+    fn load_checkpoint(&self, id: &str) -> Result<Option<CheckpointRecord>>;
+
+    /// Remove the crawler state of given crawl
+    fn remove_checkpoint(&self, id: &str) -> Result<()>;
+
+    /// Store a finished run of given crawl, to be compared with the following runs
+    fn save_run(&self, record: &Record) -> Result<()>;
+
+    /// Load given finished run of given crawl, if any
+    fn load_run(&self, id: &str, run: usize) -> Result<Option<Record>>;
+
+    /// Store given scheduled crawl, replacing the previous version
+    fn save_schedule(&self, schedule: &ScheduleRecord) -> Result<()>;
+
+    /// Load all scheduled crawls
+    fn load_schedules(&self) -> Result<Vec<ScheduleRecord>>;
+
+    /// Remove given scheduled crawl
+    fn remove_schedule(&self, name: &str) -> Result<()>;
+
+    /// Store the crawls scheduled by the clients, replacing the previous version
+    fn save_usage(&self, usage: &UsageRecord) -> Result<()>;
+
+    /// Load the crawls scheduled by the clients, if any
+    fn load_usage(&self) -> Result<Option<UsageRecord>>;
+
+    /// Check that the crawls can be stored
+    fn check(&self) -> Result<()>;
+}
+
+/// A storage that doesn't persist anything
+///
+/// Only the records of the finished runs are kept, in memory, to allow for the incremental
+/// re-crawls. Their files are kept in the system temporary directory, see `RunFiles`.
+
+#[derive(Debug, Default)]
+pub(super) struct MemoryStorage {
+    runs: RwLock<HashMap<(String, usize), Record>>,
+}
+
+impl Storage for MemoryStorage {
+    fn save(&self, _record: &Record) -> Result<()> {
+        Ok(())
+    }
+
+    fn load(&self) -> Result<Vec<Record>> {
+        Ok(Vec::new())
+    }
+
+    fn save_checkpoint(&self, _id: &str, _checkpoint: &CheckpointRecord) -> Result<()> {
+        Ok(())
+    }
+
+    fn load_checkpoint(&self, _id: &str) -> Result<Option<CheckpointRecord>> {
+        Ok(None)
+    }
+
+    fn remove_checkpoint(&self, _id: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn save_run(&self, record: &Record) -> Result<()> {
+        self.runs
+            .write()
+            .map_err(|_| err_msg("poisoned storage lock"))?
+            .insert((record.id.clone(), record.run), record.clone());
+
+        Ok(())
+    }
+
+    fn load_run(&self, id: &str, run: usize) -> Result<Option<Record>> {
+        Ok(self
+            .runs
+            .read()
+            .map_err(|_| err_msg("poisoned storage lock"))?
+            .get(&(id.to_owned(), run))
+            .cloned())
+    }
+
+    fn save_schedule(&self, _schedule: &ScheduleRecord) -> Result<()> {
+        Ok(())
+    }
+
+    fn load_schedules(&self) -> Result<Vec<ScheduleRecord>> {
+        Ok(Vec::new())
+    }
+
+    fn remove_schedule(&self, _name: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn save_usage(&self, _usage: &UsageRecord) -> Result<()> {
+        Ok(())
+    }
+
+    fn load_usage(&self) -> Result<Option<UsageRecord>> {
+        Ok(None)
+    }
+
+    fn check(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A storage keeping every crawl in a separate json file under given directory
+#[derive(Debug)]
+pub(super) struct FileStorage {
+    dir: PathBuf,
+}
+
+impl FileStorage {
+    pub(super) fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self { dir })
+    }
+
+    fn path(&self, id: &str, extension: &str) -> PathBuf {
+        self.dir.join(file_name(id) + "." + extension)
+    }
+
+    /// Return the directory of the scheduled crawls, kept apart from the crawl records
+    fn schedule_dir(&self) -> PathBuf {
+        self.dir.join("schedules")
+    }
+
+    fn schedule_path(&self, name: &str) -> PathBuf {
+        self.schedule_dir().join(file_name(name) + ".json")
+    }
+
+    /// Return the file of the quota usage, not taken for a crawl record
+    fn usage_path(&self) -> PathBuf {
+        self.dir.join("usage")
+    }
+
+    fn write<T: serde::Serialize>(&self, path: PathBuf, value: &T) -> Result<()> {
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+
+        let versioned = Versioned {
+            version: FORMAT_VERSION,
+            value,
+        };
+
+        // write to a temporary file first, so a crash won't leave a truncated file
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        serde_json::to_writer(&mut writer, &versioned)?;
+        persist(writer.into_inner()?, tmp.as_ref(), &path)?;
+
+        Ok(())
+    }
+
+    fn read<T: serde::de::DeserializeOwned>(&self, path: PathBuf) -> Result<Option<T>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let versioned: Versioned<T> = serde_json::from_reader(BufReader::new(file))?;
+
+        if versioned.version != FORMAT_VERSION {
+            return Err(err_msg(format!(
+                "unsupported format version {}",
+                versioned.version
+            )));
+        }
+
+        Ok(Some(versioned.value))
+    }
+}
+
+impl Storage for FileStorage {
+    fn save(&self, record: &Record) -> Result<()> {
+        self.write(self.path(&record.id, "json"), record)
+    }
+
+    fn load(&self) -> Result<Vec<Record>> {
+        let mut records = Vec::new();
+
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+
+            match self.read(path.clone()) {
+                Ok(Some(record)) => records.push(record),
+                Ok(None) => {}
+                Err(e) => warn!("Skipping unreadable record {}: {}", path.display(), e),
+            }
+        }
+
+        Ok(records)
+    }
+
+    fn save_checkpoint(&self, id: &str, checkpoint: &CheckpointRecord) -> Result<()> {
+        self.write(self.path(id, "checkpoint"), checkpoint)
+    }
+
+    fn load_checkpoint(&self, id: &str) -> Result<Option<CheckpointRecord>> {
+        self.read(self.path(id, "checkpoint"))
+    }
+
+    fn remove_checkpoint(&self, id: &str) -> Result<()> {
+        match fs::remove_file(self.path(id, "checkpoint")) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn save_run(&self, record: &Record) -> Result<()> {
+        self.write(
+            self.path(&record.id, &format!("{}.run", record.run)),
+            record,
+        )
+    }
+
+    fn load_run(&self, id: &str, run: usize) -> Result<Option<Record>> {
+        self.read(self.path(id, &format!("{}.run", run)))
+    }
+
+    fn save_schedule(&self, schedule: &ScheduleRecord) -> Result<()> {
+        fs::create_dir_all(self.schedule_dir())?;
+
+        self.write(self.schedule_path(&schedule.name), schedule)
+    }
+
+    fn load_schedules(&self) -> Result<Vec<ScheduleRecord>> {
+        let entries = match fs::read_dir(self.schedule_dir()) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut schedules = Vec::new();
+
+        for entry in entries {
+            let path = entry?.path();
+
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+
+            match self.read(path.clone()) {
+                Ok(Some(schedule)) => schedules.push(schedule),
+                Ok(None) => {}
+                Err(e) => warn!("Skipping unreadable schedule {}: {}", path.display(), e),
+            }
+        }
+
+        Ok(schedules)
+    }
+
+    fn remove_schedule(&self, name: &str) -> Result<()> {
+        match fs::remove_file(self.schedule_path(name)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn save_usage(&self, usage: &UsageRecord) -> Result<()> {
+        self.write(self.usage_path(), usage)
+    }
+
+    fn load_usage(&self) -> Result<Option<UsageRecord>> {
+        self.read(self.usage_path())
+    }
+
+    fn check(&self) -> Result<()> {
+        // the directory may be gone, or mounted read-only
+        let path = self.dir.join(".check");
+
+        fs::write(&path, b"")?;
+
+        match fs::remove_file(path) {
+            // a concurrent check removed it already
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Record {
+    pub(super) fn new(
+        id: String,
+        seeds: &[Seed],
+        throttle: usize,
+        priority: CrawlPriority,
+    ) -> Self {
+        Self {
+            id,
+            run: 1,
+            url: seeds
+                .first()
+                .map(|seed| seed.url.to_string())
+                .unwrap_or_default(),
+            seeds: seeds
+                .iter()
+                .map(|seed| SeedRecord {
+                    url: seed.url.to_string(),
+                    scope: seed.scope,
+                })
+                .collect(),
+            throttle,
+            priority,
+            callback_url: None,
+            warc: false,
+            index: false,
+            snapshots: false,
+            max_pages: None,
+            max_depth: None,
+            robots: false,
+            queue_priority: 0,
+            state: RecordState::Pending,
+            started: timestamp(),
+            finished: None,
+            stats: None,
+            found: 0,
+        }
+    }
+
+    /// Mark the record as done
+    pub(super) fn finish(&mut self, state: RecordState, stats: &Stats, found: usize) {
+        self.state = state;
+        self.finished = Some(timestamp());
+        self.stats = Some(StatsRecord {
+            fetched: stats.fetched,
+            unchanged: stats.unchanged,
+            effective: stats.effective,
+            errors: stats.errors,
+            bytes: stats.bytes,
+            elapsed: stats.elapsed.as_secs_f64(),
+        });
+        self.found = found;
+    }
+
+    /// Return the starting urls, skipping the invalid ones
+    pub(super) fn seeds(&self) -> Vec<Seed> {
+        self.seeds
+            .iter()
+            .filter_map(|seed| Seed::new(seed.url.as_str(), seed.scope).ok())
+            .collect()
+    }
+}
+
+impl From<&StatsRecord> for Stats {
+    fn from(record: &StatsRecord) -> Self {
+        let elapsed = Duration::from_secs_f64(record.elapsed);
+
+        Stats {
+            queued: 0,
+            in_flight: 0,
+            fetched: record.fetched,
+            unchanged: record.unchanged,
+            effective: record.effective,
+            errors: record.errors,
+            bytes: record.bytes,
+            elapsed,
+            pages_per_sec: if record.elapsed > 0.0 {
+                record.fetched as f64 / record.elapsed
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+/// Return current unix timestamp
+pub(super) fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl From<&Checkpoint> for CheckpointRecord {
+    fn from(checkpoint: &Checkpoint) -> Self {
+        let queue = &checkpoint.queue;
+
+        Self {
+            seen: checkpoint.seen.clone(),
+            queue: queue.iter().map(|queued| queued.url.to_string()).collect(),
+            depths: queue.iter().map(|queued| queued.depth).collect(),
+            sources: queue.iter().map(|queued| queued.seed).collect(),
+            segments: checkpoint.segments.clone(),
+            found: checkpoint.found,
+        }
+    }
+}
+
+impl From<&CheckpointRecord> for Checkpoint {
+    fn from(record: &CheckpointRecord) -> Self {
+        let queue = record
+            .queue
+            .iter()
+            .zip(&record.depths)
+            .zip(&record.sources)
+            .filter_map(|((url, depth), seed)| {
+                Some(Queued::new(Url::parse(url).ok()?, *depth, *seed))
+            })
+            .collect();
+
+        // the seeds are stored with the crawl, and the found urls with the run
+        Checkpoint {
+            seen: record.seen.clone(),
+            queue,
+            segments: record.segments.clone(),
+            seeds: Vec::new(),
+            effective: Urls::default(),
+            found: record.found,
+        }
+    }
+}
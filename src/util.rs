@@ -1,5 +1,10 @@
+use futures::{future, stream, Async, Future, Stream};
 use hyper::{Body, Response, StatusCode};
 use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+use url::percent_encoding::{percent_decode, utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
 use url::Url;
 
 pub(super) fn find_query_param<'a, 'b>(
@@ -12,15 +17,216 @@ pub(super) fn find_query_param<'a, 'b>(
         .ok_or_else(|| StatusCode::BAD_REQUEST)?)
 }
 
+/// Split `/api/crawl/{id}[/{action}]` path into the crawl id and an optional action
+///
+/// The id is an origin, so it may be passed either verbatim or percent-encoded.
+
+pub(super) fn split_crawl_path(path: &str) -> Option<(String, Option<&str>)> {
+    const ACTIONS: &[&str] = &["changes", "pause", "resume", "status"];
+
+    let rest = path.trim_start_matches("/api/crawl/");
+
+    let (id, action) = match rest.rfind('/') {
+        Some(pos) if ACTIONS.contains(&&rest[pos + 1..]) => (&rest[..pos], Some(&rest[pos + 1..])),
+        _ => (rest, None),
+    };
+
+    let id = percent_decode(id.as_bytes()).decode_utf8().ok()?;
+
+    if id.is_empty() {
+        None
+    } else {
+        Some((id.into_owned(), action))
+    }
+}
+
+/// Extract the decoded schedule name from given `/api/schedules/{name}` path
+///
+/// Returns `None` unless the name is short and safe to use in paths.
+
+pub(super) fn schedule_name(path: &str) -> Option<String> {
+    let name = path.trim_start_matches("/api/schedules/");
+    let name = percent_decode(name.as_bytes()).decode_utf8().ok()?;
+
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'.' || b == b'-' || b == b'_');
+
+    if valid {
+        Some(name.into_owned())
+    } else {
+        None
+    }
+}
+
+/// Convert crawl id to a string usable as a file name
+///
+/// Ids are origins, so they contain slashes.
+
+pub(super) fn file_name(id: &str) -> String {
+    utf8_percent_encode(id, PATH_SEGMENT_ENCODE_SET).to_string()
+}
+
+/// Return the year, month and day of given number of days since the unix epoch
+pub(super) fn civil_date(days: u64) -> (i64, u32, u32) {
+    // after H. Hinnant's civil_from_days
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month as u32, day as u32)
+}
+
+/// Quote given value for a CSV field, if necessary
+pub(super) fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\r', '\n']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Replace given file with given written temporary one, syncing them to the disk
+///
+/// A crash leaves either the old or the new content of the file, rather than a truncated one.
+
+pub(super) fn persist(file: File, tmp: &Path, path: &Path) -> io::Result<()> {
+    file.sync_all()?;
+    fs::rename(tmp, path)?;
+
+    // the rename is durable once the directory is synced
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => File::open(dir)?.sync_all(),
+        _ => Ok(()),
+    }
+}
+
+/// Run given blocking function, such as a file read, without blocking the reactor
+///
+/// Runs it in place if not running on a thread pool.
+
+pub(super) fn blocking<F, T, E>(f: F) -> impl Future<Item = T, Error = E>
+where
+    F: FnOnce() -> Result<T, E>,
+{
+    let mut f = Some(f);
+
+    future::poll_fn(move || {
+        match tokio_threadpool::blocking(|| f.take().expect("polled after completion")()) {
+            Ok(Async::Ready(result)) => result.map(Async::Ready),
+            // no thread is available for blocking yet
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(_) => f.take().expect("polled after completion")().map(Async::Ready),
+        }
+    })
+}
+
+/// Group the items of given stream which are ready at once, so they can be handled together
+pub(super) fn batches<S: Stream>(stream: S) -> impl Stream<Item = Vec<S::Item>, Error = S::Error> {
+    /// maximum number of the items of a batch
+    const MAX_BATCH: usize = 1024;
+
+    let mut stream = stream.fuse();
+
+    stream::poll_fn(move || {
+        let mut batch = Vec::new();
+
+        while batch.len() < MAX_BATCH {
+            match stream.poll()? {
+                Async::Ready(Some(item)) => batch.push(item),
+                Async::Ready(None) if batch.is_empty() => return Ok(Async::Ready(None)),
+                Async::NotReady if batch.is_empty() => return Ok(Async::NotReady),
+                Async::Ready(None) | Async::NotReady => break,
+            }
+        }
+
+        Ok(Async::Ready(Some(batch)))
+    })
+}
+
 pub(super) fn get_result(result: std::result::Result<Body, StatusCode>) -> Response<Body> {
+    get_result_as("application/json", result)
+}
+
+pub(super) fn get_result_as(
+    content_type: &str,
+    result: std::result::Result<Body, StatusCode>,
+) -> Response<Body> {
+    get_result_with(content_type, &[], result)
+}
+
+pub(super) fn get_result_with(
+    content_type: &str,
+    headers: &[(&str, String)],
+    result: std::result::Result<Body, StatusCode>,
+) -> Response<Body> {
     let mut response = Response::builder();
 
     match result {
-        Ok(body) => response
-            .status(StatusCode::OK)
-            .header("Content-Type", "application/json")
-            .body(body),
+        Ok(body) => {
+            response
+                .status(StatusCode::OK)
+                .header("Content-Type", content_type);
+
+            for (name, value) in headers {
+                response.header(*name, value.as_str());
+            }
+
+            response.body(body)
+        }
         Err(status) => response.status(status).body(Body::empty()),
     }
     .expect("failed to create response")
 }
+
+/// Reject a request with given status, asking for the credentials if they're missing
+pub(super) fn get_denied(status: StatusCode) -> Response<Body> {
+    let mut response = Response::builder();
+    response.status(status);
+
+    if status == StatusCode::UNAUTHORIZED {
+        response.header("WWW-Authenticate", "Bearer");
+    }
+
+    response
+        .body(Body::empty())
+        .expect("failed to create response")
+}
+
+pub(super) fn get_stream(content_type: &str, body: Body) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", content_type)
+        .header("Cache-Control", "no-cache")
+        .body(body)
+        .expect("failed to create response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv() {
+        assert_eq!(csv_field("plain text"), "plain text");
+        assert!(matches!(csv_field("plain"), Cow::Borrowed(_)));
+
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field("\r"), "\"\r\"");
+        assert_eq!(
+            csv_field("a, \"quoted\"\nvalue"),
+            "\"a, \"\"quoted\"\"\nvalue\""
+        );
+        assert_eq!(csv_field(""), "");
+    }
+}
@@ -0,0 +1,372 @@
+//! WARC archives of the crawled documents
+
+use crate::error::*;
+use crate::util::civil_date;
+use crawler::Response;
+use hyper::StatusCode;
+use std::collections::hash_map::RandomState;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// A WARC 1.0 file writer, appending request and response records
+#[derive(Debug)]
+pub(super) struct WarcWriter {
+    file: BufWriter<File>,
+    /// seed of the record ids
+    ids: RandomState,
+    /// number of the written records
+    written: u64,
+}
+
+impl WarcWriter {
+    /// Open given WARC file for appending, starting with a `warcinfo` record if it's new
+    pub(super) fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let new = file.metadata()?.len() == 0;
+
+        let mut writer = Self {
+            file: BufWriter::new(file),
+            ids: RandomState::new(),
+            written: 0,
+        };
+
+        if new {
+            let filename = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+
+            let info = format!(
+                "software: {}/{}\r\nformat: WARC File Format 1.0\r\n",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION")
+            );
+
+            let id = writer.record_id();
+
+            writer.record(
+                "warcinfo",
+                &id,
+                SystemTime::now(),
+                &[("WARC-Filename", &filename)],
+                "application/warc-fields",
+                info.as_bytes(),
+            )?;
+
+            writer.file.flush()?;
+        }
+
+        Ok(writer)
+    }
+
+    /// Append the request and the response records of given exchange
+    ///
+    /// The request is reconstructed from the effective url and the conditional headers.
+    /// The body is stored as received but without the chunked transfer coding, so
+    /// `Transfer-Encoding` is omitted and `Content-Length` is recalculated. Both records are
+    /// dated by the time the response was received.
+
+    pub(super) fn write(&mut self, response: &Response) -> Result<()> {
+        let uri = response.effective.as_str();
+        let (request_id, response_id) = (self.record_id(), self.record_id());
+
+        let mut request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\n",
+            target(&response.effective),
+            host(&response.effective)
+        );
+
+        for (name, value) in &response.request_headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+
+        request.push_str("\r\n");
+
+        let reason = StatusCode::from_u16(response.status)
+            .ok()
+            .and_then(|status| status.canonical_reason())
+            .unwrap_or("");
+
+        let mut head = format!("HTTP/1.1 {} {}\r\n", response.status, reason);
+
+        for (name, value) in &response.headers {
+            // the body is stored complete, with its own length
+            if let "transfer-encoding" | "content-length" = name.to_ascii_lowercase().as_str() {
+                continue;
+            }
+
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+
+        head.push_str(&format!("content-length: {}\r\n\r\n", response.body.len()));
+
+        let mut block = head.into_bytes();
+        block.extend_from_slice(&response.body);
+
+        self.record(
+            "request",
+            &request_id,
+            response.fetched,
+            &[
+                ("WARC-Target-URI", uri),
+                ("WARC-Concurrent-To", &response_id),
+            ],
+            "application/http; msgtype=request",
+            request.as_bytes(),
+        )?;
+
+        self.record(
+            "response",
+            &response_id,
+            response.fetched,
+            &[("WARC-Target-URI", uri)],
+            "application/http; msgtype=response",
+            &block,
+        )?;
+
+        // keep the archive readable while the crawl is running
+        self.file.flush()?;
+
+        Ok(())
+    }
+
+    /// Append a single record
+    fn record(
+        &mut self,
+        kind: &str,
+        id: &str,
+        date: SystemTime,
+        fields: &[(&str, &str)],
+        content_type: &str,
+        block: &[u8],
+    ) -> Result<()> {
+        write!(
+            self.file,
+            "WARC/1.0\r\nWARC-Type: {}\r\nWARC-Record-ID: {}\r\nWARC-Date: {}\r\n",
+            kind,
+            id,
+            warc_date(date)
+        )?;
+
+        for (name, value) in fields {
+            write!(self.file, "{}: {}\r\n", name, value)?;
+        }
+
+        write!(
+            self.file,
+            "Content-Type: {}\r\nContent-Length: {}\r\n\r\n",
+            content_type,
+            block.len()
+        )?;
+
+        self.file.write_all(block)?;
+        self.file.write_all(b"\r\n\r\n")?;
+
+        Ok(())
+    }
+
+    /// Return new unique record id, formatted as a random UUID
+    fn record_id(&mut self) -> String {
+        let mut half = || {
+            let mut hasher = self.ids.build_hasher();
+            hasher.write_u64(self.written);
+            self.written += 1;
+            hasher.finish()
+        };
+
+        let (high, low) = (half(), half());
+
+        format!(
+            "<urn:uuid:{:08x}-{:04x}-4{:03x}-{:04x}-{:012x}>",
+            high >> 32,
+            (high >> 16) & 0xffff,
+            high & 0x0fff,
+            // RFC 4122 variant
+            (low >> 48) & 0x3fff | 0x8000,
+            low & 0xffff_ffff_ffff
+        )
+    }
+}
+
+/// Return the request target of given url, i.e. its path and query
+fn target(url: &Url) -> String {
+    match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_owned(),
+    }
+}
+
+/// Return the value of the `Host` header for given url
+fn host(url: &Url) -> String {
+    let host = url.host_str().unwrap_or_default();
+
+    match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_owned(),
+    }
+}
+
+/// Format given time as a WARC date, i.e. ISO 8601 in UTC with seconds precision
+fn warc_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+    let (year, month, day) = civil_date(days);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3_600,
+        secs % 3_600 / 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    type Record = (Vec<(String, String)>, Vec<u8>);
+
+    /// Split given WARC file into the header fields and the blocks of its records
+    fn records(mut warc: &[u8]) -> Vec<Record> {
+        let mut records = Vec::new();
+
+        while !warc.is_empty() {
+            let end = warc
+                .windows(4)
+                .position(|window| window == b"\r\n\r\n")
+                .unwrap();
+            let head = std::str::from_utf8(&warc[..end]).unwrap();
+
+            let mut lines = head.split("\r\n");
+            assert_eq!(lines.next(), Some("WARC/1.0"));
+
+            let fields = lines
+                .map(|line| {
+                    let (name, value) = line.split_once(": ").unwrap();
+                    (name.to_owned(), value.to_owned())
+                })
+                .collect::<Vec<_>>();
+
+            let length = field(&fields, "Content-Length").parse::<usize>().unwrap();
+            let block = &warc[end + 4..end + 4 + length];
+
+            // every record ends with two line breaks
+            assert_eq!(&warc[end + 4 + length..end + 8 + length], b"\r\n\r\n");
+
+            records.push((fields, block.to_vec()));
+            warc = &warc[end + 8 + length..];
+        }
+
+        records
+    }
+
+    fn field<'a>(fields: &'a [(String, String)], name: &str) -> &'a str {
+        fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value.as_str())
+            .unwrap_or_else(|| panic!("missing {}", name))
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = std::env::temp_dir().join(format!("warc-{}", std::process::id()));
+        let path = dir.join("crawl.warc");
+
+        // the body contains a blank line and invalid UTF-8, so only its length delimits it
+        let body = b"<html>\r\n\r\n\xff</html>".to_vec();
+        let effective = Url::parse("http://example.com:8080/a/b?x=1").unwrap();
+
+        let response = Response {
+            url: Url::parse("http://example.com:8080/").unwrap(),
+            effective: effective.clone(),
+            request_headers: vec![("If-None-Match".to_owned(), "\"v1\"".to_owned())],
+            status: 200,
+            headers: vec![
+                ("Content-Type".to_owned(), "text/html".to_owned()),
+                ("Transfer-Encoding".to_owned(), "chunked".to_owned()),
+                ("Content-Length".to_owned(), "3".to_owned()),
+            ],
+            body: body.clone(),
+            fetched: UNIX_EPOCH + Duration::from_secs(1_709_209_800),
+        };
+
+        WarcWriter::open(&path).unwrap().write(&response).unwrap();
+        // reopened for appending, without another warcinfo record
+        WarcWriter::open(&path).unwrap().write(&response).unwrap();
+
+        let records = records(&fs::read(&path).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+
+        let kinds = records
+            .iter()
+            .map(|(fields, _)| field(fields, "WARC-Type"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec!["warcinfo", "request", "response", "request", "response"]
+        );
+
+        let (info, block) = &records[0];
+        assert_eq!(field(info, "WARC-Filename"), "crawl.warc");
+        assert_eq!(field(info, "Content-Type"), "application/warc-fields");
+        assert!(std::str::from_utf8(block)
+            .unwrap()
+            .contains("format: WARC File Format 1.0"));
+
+        let (request, block) = &records[1];
+        assert_eq!(field(request, "WARC-Target-URI"), effective.as_str());
+        assert_eq!(field(request, "WARC-Date"), "2024-02-29T12:30:00Z");
+        assert_eq!(
+            field(request, "Content-Type"),
+            "application/http; msgtype=request"
+        );
+        assert_eq!(
+            std::str::from_utf8(block).unwrap(),
+            "GET /a/b?x=1 HTTP/1.1\r\nHost: example.com:8080\r\nIf-None-Match: \"v1\"\r\n\r\n"
+        );
+
+        let (fields, block) = &records[2];
+        assert_eq!(
+            field(request, "WARC-Concurrent-To"),
+            field(fields, "WARC-Record-ID")
+        );
+        assert_eq!(field(fields, "WARC-Target-URI"), effective.as_str());
+        assert_eq!(field(fields, "WARC-Date"), "2024-02-29T12:30:00Z");
+
+        let head = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\ncontent-length: {}\r\n\r\n",
+            body.len()
+        );
+        assert_eq!(&block[..head.len()], head.as_bytes());
+        assert_eq!(&block[head.len()..], body.as_slice());
+
+        // the record ids are unique
+        let mut ids = records
+            .iter()
+            .map(|(fields, _)| field(fields, "WARC-Record-ID"))
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), records.len());
+        assert!(ids
+            .iter()
+            .all(|id| id.starts_with("<urn:uuid:") && id.len() == 47));
+    }
+}
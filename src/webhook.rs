@@ -0,0 +1,145 @@
+//! Notifications about the crawls that are done
+
+use crawler::prelude::send;
+use crawler::{FetchError, Stats};
+use futures::future::{self, Either, Loop};
+use hmac::{Hmac, Mac};
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Request, StatusCode};
+use log::*;
+use serde_derive::Serialize;
+use sha2::Sha256;
+use std::time::{Duration, Instant};
+use tokio::prelude::*;
+use tokio::timer::Delay;
+use url::Url;
+
+/// Header carrying the signature of the payload
+const SIGNATURE_HEADER: &str = "X-Webcrawl-Signature";
+/// Number of delivery attempts
+const ATTEMPTS: usize = 5;
+/// Delay before the first retry, doubled with every following one
+const BACKOFF: Duration = Duration::from_secs(1);
+
+/// A summary of a crawl run, sent to its callback url
+#[derive(Debug, Serialize)]
+pub(super) struct Summary<'a> {
+    id: &'a str,
+    run: usize,
+    /// termination reason, one of `finished`, `cancelled`, `failed`
+    state: &'a str,
+    /// cause of the failure
+    error: Option<String>,
+    fetched: usize,
+    unchanged: usize,
+    effective: usize,
+    errors: usize,
+    bytes: u64,
+    /// crawl duration, in seconds
+    elapsed: f64,
+}
+
+impl<'a> Summary<'a> {
+    pub(super) fn new(
+        id: &'a str,
+        run: usize,
+        state: &'a str,
+        error: Option<String>,
+        stats: &Stats,
+    ) -> Self {
+        Self {
+            id,
+            run,
+            state,
+            error,
+            fetched: stats.fetched,
+            unchanged: stats.unchanged,
+            effective: stats.effective,
+            errors: stats.errors,
+            bytes: stats.bytes,
+            elapsed: stats.elapsed.as_secs_f64(),
+        }
+    }
+}
+
+/// POST given summary to the callback url
+///
+/// The failed deliveries are retried with exponential backoff, unless the callback
+/// rejects the payload. The payload is signed with given secret, if any. The callback
+/// is reached only at the addresses permitted by the installed network policy.
+
+pub(super) fn notify(
+    callback: Url,
+    summary: &Summary,
+    secret: Option<&str>,
+) -> impl Future<Item = (), Error = ()> + Send {
+    let body = serde_json::to_string(summary).expect("failed to serialize summary");
+    let signature = secret.map(|secret| format!("sha256={}", sign(secret, &body)));
+
+    future::loop_fn((1, BACKOFF), move |(attempt, backoff)| {
+        let mut request = Request::post(callback.as_str());
+        request.header(CONTENT_TYPE, "application/json");
+
+        if let Some(signature) = &signature {
+            request.header(SIGNATURE_HEADER, signature.as_str());
+        }
+
+        let sent = match request.body(Body::from(body.clone())) {
+            Ok(request) => Either::A(send(request)),
+            Err(e) => Either::B(future::err(e.into())),
+        };
+
+        let callback = callback.clone();
+
+        sent.then(move |response| {
+            let retry = match response {
+                Ok(ref response) if response.status().is_success() => {
+                    debug!("Notified {}", callback);
+
+                    return Either::A(future::ok(Loop::Break(())));
+                }
+                Ok(response) => {
+                    let status = response.status();
+
+                    warn!("Callback {} responded with {}", callback, status);
+
+                    // the other client errors won't go away
+                    status.is_server_error()
+                        || status == StatusCode::REQUEST_TIMEOUT
+                        || status == StatusCode::TOO_MANY_REQUESTS
+                }
+                Err(e) => {
+                    warn!("Failed to notify {}: {}", callback, e);
+
+                    // neither will a refused address
+                    FetchError::of(&e) != FetchError::Refused
+                }
+            };
+
+            if !retry || attempt >= ATTEMPTS {
+                error!(
+                    "Giving up notifying {} after {} attempts",
+                    callback, attempt
+                );
+
+                return Either::A(future::ok(Loop::Break(())));
+            }
+
+            Either::B(
+                Delay::new(Instant::now() + backoff)
+                    .map(move |_| Loop::Continue((attempt + 1, backoff * 2)))
+                    .map_err(|e| error!("Webhook timer failed: {}", e)),
+            )
+        })
+    })
+}
+
+/// Return hex-encoded HMAC-SHA256 of given payload
+fn sign(secret: &str, payload: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+
+    mac.update(payload.as_bytes());
+
+    hex::encode(mac.finalize().into_bytes())
+}